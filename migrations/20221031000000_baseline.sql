@@ -0,0 +1,33 @@
+-- tables the service was deployed with before the migrations were tracked, a no-op on the
+-- existing databases
+CREATE TABLE IF NOT EXISTS user (
+    id VARCHAR(64) NOT NULL PRIMARY KEY,
+    -- unix seconds of the first transaction
+    activation_time BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS chain (
+    id VARCHAR(32) NOT NULL PRIMARY KEY,
+    community_id INT NOT NULL,
+    name VARCHAR(64) NOT NULL,
+    native_token_id VARCHAR(128) NOT NULL,
+    logo_url VARCHAR(255) NOT NULL DEFAULT ''
+);
+
+CREATE TABLE IF NOT EXISTS token (
+    id VARCHAR(128) NOT NULL,
+    chain VARCHAR(32) NOT NULL,
+    name VARCHAR(128) NOT NULL,
+    symbol VARCHAR(64) NOT NULL,
+    decimals INT NOT NULL,
+    logo_url VARCHAR(255) NOT NULL DEFAULT '',
+    protocol_id VARCHAR(64) NOT NULL DEFAULT '',
+    is_core BOOLEAN NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (id, chain)
+);
+
+-- the token id of a vote token on each chain is kept in a column named after the chain, the
+-- columns are added by the admin api
+CREATE TABLE IF NOT EXISTS vote_token (
+    id VARCHAR(64) NOT NULL PRIMARY KEY
+);
@@ -0,0 +1,13 @@
+-- every token movement of a user, amount is positive for incoming and negative for outgoing
+CREATE TABLE IF NOT EXISTS token_transfer (
+    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    user_id VARCHAR(64) NOT NULL,
+    chain VARCHAR(32) NOT NULL,
+    token_id VARCHAR(128) NOT NULL,
+    tx_hash VARCHAR(128) NOT NULL,
+    timestamp BIGINT NOT NULL,
+    amount DOUBLE NOT NULL,
+    -- usd price of the token at `timestamp`
+    price DOUBLE NOT NULL,
+    INDEX idx_token_transfer_user (user_id, timestamp)
+);
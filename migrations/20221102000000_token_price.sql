@@ -0,0 +1,8 @@
+-- historical usd price of the tokens
+CREATE TABLE IF NOT EXISTS token_price (
+    chain VARCHAR(32) NOT NULL,
+    token_id VARCHAR(128) NOT NULL,
+    timestamp BIGINT NOT NULL,
+    price DOUBLE NOT NULL,
+    PRIMARY KEY (chain, token_id, timestamp)
+);
@@ -0,0 +1,17 @@
+-- extra tokens counted for a vote token, e.g. lp tokens counted as their underlying
+CREATE TABLE IF NOT EXISTS vote_token_weight (
+    vote_token VARCHAR(64) NOT NULL,
+    chain VARCHAR(32) NOT NULL,
+    token_id VARCHAR(128) NOT NULL,
+    -- `token` or `uniswap_v2_lp`
+    kind VARCHAR(32) NOT NULL DEFAULT 'token',
+    weight DOUBLE NOT NULL DEFAULT 1,
+    PRIMARY KEY (vote_token, chain, token_id)
+);
+
+-- addresses whose voting power is not counted, e.g. the treasury
+CREATE TABLE IF NOT EXISTS vote_exclusion (
+    vote_token VARCHAR(64) NOT NULL,
+    address VARCHAR(64) NOT NULL,
+    PRIMARY KEY (vote_token, address)
+);
@@ -0,0 +1,23 @@
+-- addresses tracked for the leaderboard of a vote token
+CREATE TABLE IF NOT EXISTS vote_address (
+    vote_token VARCHAR(64) NOT NULL,
+    address VARCHAR(64) NOT NULL,
+    PRIMARY KEY (vote_token, address)
+);
+
+-- a computed leaderboard of a vote token
+CREATE TABLE IF NOT EXISTS vote_snapshot (
+    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    vote_token VARCHAR(64) NOT NULL,
+    computed_at BIGINT NOT NULL,
+    INDEX idx_vote_snapshot_token (vote_token, computed_at)
+);
+
+CREATE TABLE IF NOT EXISTS vote_snapshot_entry (
+    snapshot_id BIGINT NOT NULL,
+    address VARCHAR(64) NOT NULL,
+    amount DOUBLE NOT NULL,
+    `rank` BIGINT NOT NULL,
+    PRIMARY KEY (snapshot_id, address),
+    INDEX idx_vote_snapshot_entry_rank (snapshot_id, `rank`)
+);
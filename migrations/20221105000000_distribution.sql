@@ -0,0 +1,20 @@
+-- merkle distributions built from a vote snapshot
+CREATE TABLE IF NOT EXISTS distribution (
+    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    snapshot_id BIGINT NOT NULL,
+    decimals INT NOT NULL,
+    merkle_root VARCHAR(66) NOT NULL,
+    -- hex encoded raw amount
+    token_total VARCHAR(80) NOT NULL,
+    created_at BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS distribution_claim (
+    distribution_id BIGINT NOT NULL,
+    address VARCHAR(64) NOT NULL,
+    idx BIGINT NOT NULL,
+    amount VARCHAR(80) NOT NULL,
+    -- json array of the hex encoded proof hashes
+    proof TEXT NOT NULL,
+    PRIMARY KEY (distribution_id, address)
+);
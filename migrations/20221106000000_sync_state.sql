@@ -0,0 +1,5 @@
+-- last successful run of the sync jobs
+CREATE TABLE IF NOT EXISTS sync_state (
+    name VARCHAR(64) NOT NULL PRIMARY KEY,
+    last_synced_at BIGINT NOT NULL
+);
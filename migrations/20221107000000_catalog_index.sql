@@ -0,0 +1,2 @@
+-- single token lookups and the chain filter of the token list
+CREATE INDEX idx_token_chain_id ON token (chain, id);
@@ -0,0 +1,16 @@
+-- disabled rows are kept for the history but hidden from the public api
+ALTER TABLE chain ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE token ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE vote_token ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- changes made through the admin api
+CREATE TABLE IF NOT EXISTS admin_audit (
+    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    actor VARCHAR(64) NOT NULL,
+    action VARCHAR(64) NOT NULL,
+    target VARCHAR(255) NOT NULL,
+    -- json body of the request
+    payload TEXT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+CREATE INDEX idx_admin_audit_created_at ON admin_audit (created_at);
@@ -0,0 +1,24 @@
+-- clients of the public api, only the keccak256 of their key is stored
+CREATE TABLE IF NOT EXISTS api_client (
+    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    name VARCHAR(64) NOT NULL,
+    key_hash CHAR(66) NOT NULL UNIQUE,
+    -- first characters of the key to recognize it
+    key_prefix VARCHAR(16) NOT NULL,
+    -- requests per utc day, unlimited when null
+    daily_quota BIGINT NULL,
+    -- requests per minute, unlimited when null
+    rate_limit BIGINT NULL,
+    created_at BIGINT NOT NULL,
+    revoked_at BIGINT NULL
+);
+
+-- requests per client, utc day and endpoint
+CREATE TABLE IF NOT EXISTS api_usage (
+    client_id BIGINT NOT NULL,
+    -- unix time of the start of the day
+    day BIGINT NOT NULL,
+    endpoint VARCHAR(255) NOT NULL,
+    requests BIGINT NOT NULL,
+    PRIMARY KEY (client_id, day, endpoint)
+);
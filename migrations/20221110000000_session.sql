@@ -0,0 +1,14 @@
+-- nonces issued for the sign-in with ethereum messages, deleted once used
+CREATE TABLE IF NOT EXISTS siwe_nonce (
+    nonce VARCHAR(64) NOT NULL PRIMARY KEY,
+    expires_at BIGINT NOT NULL
+);
+
+-- sessions of the signed in addresses, only the keccak256 of the token is stored
+CREATE TABLE IF NOT EXISTS session (
+    token_hash CHAR(66) NOT NULL PRIMARY KEY,
+    address VARCHAR(64) NOT NULL,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL
+);
+CREATE INDEX idx_session_address ON session (address);
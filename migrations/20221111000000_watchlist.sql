@@ -0,0 +1,19 @@
+-- addresses followed by the signed in users
+CREATE TABLE IF NOT EXISTS watchlist (
+    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    -- lowercase address of the owner
+    owner VARCHAR(64) NOT NULL,
+    name VARCHAR(128) NOT NULL,
+    created_at BIGINT NOT NULL
+);
+CREATE INDEX idx_watchlist_owner ON watchlist (owner);
+
+CREATE TABLE IF NOT EXISTS watchlist_entry (
+    watchlist_id BIGINT NOT NULL,
+    -- lowercase address
+    address VARCHAR(64) NOT NULL,
+    label VARCHAR(128) NULL,
+    note TEXT NULL,
+    created_at BIGINT NOT NULL,
+    PRIMARY KEY (watchlist_id, address)
+);
@@ -0,0 +1,38 @@
+-- balance alerts of the signed in users delivered to their webhook
+CREATE TABLE IF NOT EXISTS alert_rule (
+    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    -- lowercase address of the owner
+    owner VARCHAR(64) NOT NULL,
+    -- lowercase watched address
+    address VARCHAR(64) NOT NULL,
+    -- `total_usd` or `token_amount`
+    kind VARCHAR(32) NOT NULL,
+    -- the token of the `token_amount` alerts
+    chain VARCHAR(32) NULL,
+    token_id VARCHAR(128) NULL,
+    threshold DOUBLE NOT NULL,
+    -- `absolute` or `percent`
+    threshold_mode VARCHAR(16) NOT NULL,
+    webhook_url VARCHAR(512) NOT NULL,
+    -- key of the hmac signature of the webhooks
+    secret VARCHAR(128) NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at BIGINT NOT NULL,
+    -- last observed value, compared with the next observation
+    last_value DOUBLE NULL,
+    last_observed_at BIGINT NULL
+);
+CREATE INDEX idx_alert_rule_owner ON alert_rule (owner);
+
+-- every attempt to deliver a webhook
+CREATE TABLE IF NOT EXISTS alert_delivery (
+    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    rule_id BIGINT NOT NULL,
+    attempt BIGINT NOT NULL,
+    status_code BIGINT NULL,
+    error TEXT NULL,
+    success BOOLEAN NOT NULL,
+    payload TEXT NOT NULL,
+    attempted_at BIGINT NOT NULL
+);
+CREATE INDEX idx_alert_delivery_rule ON alert_delivery (rule_id, id);
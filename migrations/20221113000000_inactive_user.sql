@@ -0,0 +1,12 @@
+-- addresses etherscan found without transaction, checked again once the recheck ttl passed
+-- since `checked_at`, they were kept in `user` with an activation time of i64::MAX before
+CREATE TABLE IF NOT EXISTS inactive_user (
+    id VARCHAR(64) NOT NULL PRIMARY KEY,
+    -- unix seconds of the last check
+    checked_at BIGINT NOT NULL
+);
+CREATE INDEX idx_inactive_user_checked_at ON inactive_user (checked_at);
+
+INSERT INTO inactive_user (id, checked_at)
+SELECT id, 0 FROM user WHERE activation_time = 9223372036854775807;
+DELETE FROM user WHERE activation_time = 9223372036854775807;
@@ -0,0 +1,6 @@
+-- the transfers are ingested from etherscan, the block of the newest one tells where the next
+-- sync of its user resumes and `seq` tells apart the transfers of a token within a
+-- transaction, so a sync can run again or twice at once without duplicating them
+ALTER TABLE token_transfer ADD COLUMN block BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE token_transfer ADD COLUMN seq INT NOT NULL DEFAULT 0;
+CREATE UNIQUE INDEX idx_token_transfer_event ON token_transfer (user_id, chain, tx_hash, token_id, seq);
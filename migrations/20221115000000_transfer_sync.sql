@@ -0,0 +1,10 @@
+-- where the next transfer sync of a user resumes, `next_block` moves on even when the fetched
+-- blocks held no transfer, and when the last sync ran so the etherscan calls are throttled
+CREATE TABLE IF NOT EXISTS transfer_sync (
+    user_id VARCHAR(64) NOT NULL,
+    chain VARCHAR(32) NOT NULL,
+    next_block BIGINT NOT NULL,
+    -- unix seconds of the last sync
+    synced_at BIGINT NOT NULL,
+    PRIMARY KEY (user_id, chain)
+);
@@ -0,0 +1,4 @@
+-- the exact amount of a leaderboard entry as a decimal string, `amount` keeps its float for
+-- the queries; the entries stored before keep the digits of their float
+ALTER TABLE vote_snapshot_entry ADD COLUMN exact_amount VARCHAR(96) NOT NULL DEFAULT '0';
+UPDATE vote_snapshot_entry SET exact_amount = CAST(amount AS CHAR);
@@ -0,0 +1,12 @@
+-- tokens created through the admin api, the catalog sync keeps them even when debank doesn't
+-- know them; the ones created before are found in the audit log by their `chain:id` target
+ALTER TABLE token ADD COLUMN admin_created BOOLEAN NOT NULL DEFAULT FALSE;
+UPDATE token SET admin_created = TRUE
+WHERE EXISTS (
+    SELECT 1 FROM admin_audit
+    WHERE action = 'create_token'
+        AND LENGTH(target) = LENGTH(token.chain) + 1 + LENGTH(token.id)
+        AND SUBSTR(target, 1, LENGTH(token.chain)) = token.chain
+        AND SUBSTR(target, LENGTH(token.chain) + 1, 1) = ':'
+        AND SUBSTR(target, LENGTH(token.chain) + 2) = token.id
+);
@@ -0,0 +1,8 @@
+-- days the price provider has no price of the token for, such as the days before its listing,
+-- the backfill doesn't ask them again
+CREATE TABLE IF NOT EXISTS missing_price (
+    chain VARCHAR(32) NOT NULL,
+    token_id VARCHAR(128) NOT NULL,
+    timestamp BIGINT NOT NULL,
+    PRIMARY KEY (chain, token_id, timestamp)
+);
@@ -0,0 +1,65 @@
+//! Activity of an address.
+
+use std::env;
+
+use chrono::Utc;
+use thiserror::Error;
+
+use crate::etherscan::{EtherscanAPi, EtherscanApiError};
+use crate::storage::{StorageError, StorageProcessor};
+
+#[derive(Debug, Error)]
+pub enum AccountError {
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Etherscan(#[from] EtherscanApiError),
+}
+
+/// Seconds an address found without transaction is answered as such before etherscan is asked
+/// again, `ACCOUNT_RECHECK_SECS` or a day.
+pub fn recheck_secs_from_env() -> i64 {
+    env::var("ACCOUNT_RECHECK_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(86400)
+}
+
+/// Timestamp of the first transaction of the address, `None` when it never sent one.
+///
+/// A found time is kept in storage for good, an address without transaction is asked again
+/// once `recheck_secs` passed since its last check.
+pub async fn activation_time(
+    storage: &StorageProcessor,
+    acc_api: &EtherscanAPi,
+    address: &str,
+    recheck_secs: i64,
+) -> Result<Option<i64>, AccountError> {
+    if let Some(user_info) = storage.load_user_info(address).await? {
+        return Ok(Some(user_info.activation_time));
+    }
+    if let Some(checked_at) = storage.load_inactive_user(address).await? {
+        if Utc::now().timestamp() - checked_at < recheck_secs {
+            return Ok(None);
+        }
+    }
+    check_activation_time(storage, acc_api, address).await
+}
+
+/// Asks etherscan for the first transaction of the address and stores the answer.
+pub async fn check_activation_time(
+    storage: &StorageProcessor,
+    acc_api: &EtherscanAPi,
+    address: &str,
+) -> Result<Option<i64>, AccountError> {
+    let activation_time = acc_api.account_age(address).await?;
+    match activation_time {
+        Some(activation_time) => storage.set_user_info(address, activation_time).await?,
+        None => {
+            storage
+                .set_user_inactive(address, Utc::now().timestamp())
+                .await?
+        }
+    }
+    Ok(activation_time)
+}
@@ -0,0 +1,501 @@
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::{redirect, Url};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::debank::openapi::DebankApiError;
+use crate::storage::alert::{AlertDelivery, AlertRule};
+use crate::storage::{StorageError, StorageProcessor};
+
+/// Header of the webhook signature, `t=<unix seconds>,v1=<hex hmac>`.
+pub const SIGNATURE_HEADER: &str = "x-zportfolio-signature";
+
+#[derive(Debug, Error)]
+pub enum AlertError {
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Debank(#[from] DebankApiError),
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+    #[error("Unknown alert kind: {0}")]
+    UnknownKind(String),
+    #[error("Unknown threshold mode: {0}")]
+    UnknownThresholdMode(String),
+    #[error("Token alert without token")]
+    MissingToken,
+    #[error("Webhook url not accepted: {0}")]
+    InvalidWebhook(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    /// total usd value of the address over the supported chains
+    TotalUsd,
+    /// amount of a token held by the address
+    TokenAmount,
+}
+
+impl AlertKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertKind::TotalUsd => "total_usd",
+            AlertKind::TokenAmount => "token_amount",
+        }
+    }
+}
+
+impl FromStr for AlertKind {
+    type Err = AlertError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "total_usd" => Ok(AlertKind::TotalUsd),
+            "token_amount" => Ok(AlertKind::TokenAmount),
+            other => Err(AlertError::UnknownKind(other.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ThresholdMode {
+    /// change in usd or in token amount
+    #[default]
+    Absolute,
+    /// change relative to the previous observation
+    Percent,
+}
+
+impl ThresholdMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ThresholdMode::Absolute => "absolute",
+            ThresholdMode::Percent => "percent",
+        }
+    }
+}
+
+impl FromStr for ThresholdMode {
+    type Err = AlertError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "absolute" => Ok(ThresholdMode::Absolute),
+            "percent" => Ok(ThresholdMode::Percent),
+            other => Err(AlertError::UnknownThresholdMode(other.to_string())),
+        }
+    }
+}
+
+/// Checks if the move between two consecutive observations reaches the threshold, any move
+/// from zero reaches a percent threshold.
+pub fn is_triggered(previous: f64, current: f64, threshold: f64, mode: ThresholdMode) -> bool {
+    let change = (current - previous).abs();
+    match mode {
+        ThresholdMode::Absolute => change >= threshold,
+        ThresholdMode::Percent if previous == 0.0 => change > 0.0,
+        ThresholdMode::Percent => change / previous.abs() * 100.0 >= threshold,
+    }
+}
+
+/// Body of the webhooks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertEvent {
+    pub rule_id: i64,
+    pub address: String,
+    pub kind: AlertKind,
+    pub chain: Option<String>,
+    pub token_id: Option<String>,
+    pub previous: f64,
+    pub current: f64,
+    pub observed_at: i64,
+}
+
+/// Signs `<timestamp>.<body>` with hmac-sha256 so the receivers can check both the origin and
+/// the freshness of the webhook.
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts any key size");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    format!(
+        "t={},v1={}",
+        timestamp,
+        hex::encode(mac.finalize().into_bytes())
+    )
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeliveryAttempt {
+    pub attempt: u32,
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+    pub success: bool,
+    pub attempted_at: i64,
+}
+
+/// Whether the address is reachable from the internet, the webhooks may not reach the
+/// loopback, private, link-local (such as the cloud metadata) or other reserved networks.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                // shared address space of the carrier grade nats
+                || (a == 100 && (64..128).contains(&b))
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_unspecified()
+                    || ip.is_loopback()
+                    || ip.is_multicast()
+                    // unique local and link-local
+                    || (first & 0xfe00) == 0xfc00
+                    || (first & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+/// Address of a host given as an ip, the ipv6 ones are in brackets.
+fn host_ip(host: &str) -> Option<IpAddr> {
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .ok()
+}
+
+/// Posts the signed webhooks, failed deliveries are retried with an exponential backoff
+/// unless the receiver rejected the request itself.
+#[derive(Debug, Clone)]
+pub struct WebhookSender {
+    max_attempts: u32,
+    base_delay: Duration,
+    /// hosts the operator lets the webhooks reach whatever their address
+    allowed_hosts: Vec<String>,
+}
+
+impl Default for WebhookSender {
+    fn default() -> Self {
+        Self::new(5, Duration::from_secs(2))
+    }
+}
+
+impl WebhookSender {
+    pub fn new(max_attempts: u32, base_delay: Duration) -> Self {
+        Self {
+            max_attempts,
+            base_delay,
+            allowed_hosts: Vec::new(),
+        }
+    }
+
+    /// The default sender allowing the comma separated hosts of `WEBHOOK_ALLOWED_HOSTS`.
+    pub fn from_env() -> Self {
+        let hosts = env::var("WEBHOOK_ALLOWED_HOSTS").unwrap_or_default();
+        Self::default().allow_hosts(hosts.split(',').map(|h| h.trim().to_string()))
+    }
+
+    pub fn allow_hosts(mut self, hosts: impl IntoIterator<Item = String>) -> Self {
+        self.allowed_hosts
+            .extend(hosts.into_iter().filter(|h| !h.is_empty()));
+        self
+    }
+
+    /// Checks the url is http and resolves to public addresses only, unless its host is
+    /// allowed. Returns the address to connect to, `None` for an allowed host.
+    pub async fn check_url(&self, url: &str) -> Result<Option<SocketAddr>, AlertError> {
+        let invalid = |reason: &str| AlertError::InvalidWebhook(reason.to_string());
+        let url = Url::parse(url).map_err(|_| invalid("invalid url"))?;
+        if url.scheme() != "https" && url.scheme() != "http" {
+            return Err(invalid("not an http url"));
+        }
+        let host = url.host_str().ok_or_else(|| invalid("missing host"))?;
+        if self
+            .allowed_hosts
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(host))
+        {
+            return Ok(None);
+        }
+        let port = url.port_or_known_default().unwrap_or(80);
+        let addrs: Vec<SocketAddr> = match host_ip(host) {
+            Some(ip) => vec![SocketAddr::new(ip, port)],
+            None => tokio::net::lookup_host((host, port))
+                .await
+                .map_err(|_| invalid("unknown host"))?
+                .collect(),
+        };
+        // every address is checked, the resolver may answer any of them next time
+        if addrs.iter().any(|addr| !is_public(addr.ip())) {
+            return Err(invalid("not a public address"));
+        }
+        addrs
+            .first()
+            .map(|addr| Some(*addr))
+            .ok_or_else(|| invalid("unknown host"))
+    }
+
+    /// Client connecting to the checked address of the url, so a dns answer changed since the
+    /// check is not followed. The redirects are not followed either.
+    async fn client(&self, url: &str) -> Result<reqwest::Client, AlertError> {
+        let checked = self.check_url(url).await?;
+        let mut builder = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .redirect(redirect::Policy::none());
+        let url = Url::parse(url).map_err(|e| AlertError::InvalidWebhook(e.to_string()))?;
+        if let (Some(addr), Some(host)) = (checked, url.host_str()) {
+            if host_ip(host).is_none() {
+                builder = builder.resolve(host, addr);
+            }
+        }
+        builder
+            .build()
+            .map_err(|e| AlertError::InvalidWebhook(e.to_string()))
+    }
+
+    pub async fn send(&self, url: &str, secret: &str, body: &str) -> Vec<DeliveryAttempt> {
+        // checked again at each delivery, the host may resolve elsewhere since the rule was saved
+        let client = match self.client(url).await {
+            Ok(client) => client,
+            Err(e) => {
+                return vec![DeliveryAttempt {
+                    attempt: 1,
+                    status_code: None,
+                    error: Some(e.to_string()),
+                    success: false,
+                    attempted_at: Utc::now().timestamp(),
+                }]
+            }
+        };
+        let mut attempts = Vec::new();
+        for attempt in 1..=self.max_attempts {
+            if attempt > 1 {
+                tokio::time::sleep(self.base_delay * 2u32.pow(attempt - 2)).await;
+            }
+            let attempted_at = Utc::now().timestamp();
+            let res = client
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(SIGNATURE_HEADER, sign(secret, attempted_at, body))
+                .body(body.to_string())
+                .send()
+                .await;
+            let (status_code, error, retry) = match res {
+                Ok(resp) => {
+                    let status = resp.status();
+                    let retry = status.is_server_error()
+                        || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+                    (Some(status.as_u16()), None, retry)
+                }
+                Err(e) => (None, Some(e.to_string()), true),
+            };
+            let success = status_code.is_some_and(|c| (200..300).contains(&c));
+            attempts.push(DeliveryAttempt {
+                attempt,
+                status_code,
+                error,
+                success,
+                attempted_at,
+            });
+            if success || !retry {
+                break;
+            }
+        }
+        attempts
+    }
+}
+
+/// Delivers the event to the webhook of the rule and logs every attempt, returns whether it
+/// was delivered.
+pub async fn deliver(
+    storage: &StorageProcessor,
+    sender: &WebhookSender,
+    rule: &AlertRule,
+    event: &AlertEvent,
+) -> Result<bool, AlertError> {
+    let payload = serde_json::to_string(event)?;
+    let attempts = sender.send(&rule.webhook_url, &rule.secret, &payload).await;
+    for attempt in &attempts {
+        storage
+            .save_alert_delivery(&AlertDelivery {
+                id: 0,
+                rule_id: rule.id,
+                attempt: attempt.attempt as i64,
+                status_code: attempt.status_code.map(i64::from),
+                error: attempt.error.clone(),
+                success: attempt.success,
+                payload: payload.clone(),
+                attempted_at: attempt.attempted_at,
+            })
+            .await?;
+    }
+    Ok(attempts.last().is_some_and(|a| a.success))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use axum::Router;
+
+    use super::*;
+
+    #[test]
+    fn test_is_triggered() {
+        use ThresholdMode::*;
+        assert!(is_triggered(100.0, 150.0, 50.0, Absolute));
+        assert!(is_triggered(100.0, 50.0, 50.0, Absolute));
+        assert!(!is_triggered(100.0, 149.0, 50.0, Absolute));
+        assert!(is_triggered(200.0, 180.0, 10.0, Percent));
+        assert!(!is_triggered(200.0, 181.0, 10.0, Percent));
+        assert!(is_triggered(0.0, 1.0, 10.0, Percent));
+        assert!(!is_triggered(0.0, 0.0, 10.0, Percent));
+    }
+
+    #[test]
+    fn test_sign() {
+        // hmac-sha256("key", "1.{}")
+        let signature = sign("key", 1, "{}");
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"key").unwrap();
+        mac.update(b"1.{}");
+        assert_eq!(
+            signature,
+            format!("t=1,v1={}", hex::encode(mac.finalize().into_bytes()))
+        );
+        assert_ne!(sign("other", 1, "{}"), signature);
+    }
+
+    /// Receiver failing the first request, then recording the signature and the body.
+    async fn start_receiver(
+        first_status: StatusCode,
+    ) -> (String, Arc<Mutex<Vec<(String, String)>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let hits = Arc::new(Mutex::new(0));
+        let app = Router::new().route(
+            "/hook",
+            post({
+                let received = received.clone();
+                move |headers: HeaderMap, body: String| async move {
+                    let mut hits = hits.lock().unwrap();
+                    *hits += 1;
+                    if *hits == 1 {
+                        return first_status;
+                    }
+                    let signature = headers[SIGNATURE_HEADER].to_str().unwrap().to_string();
+                    received.lock().unwrap().push((signature, body));
+                    StatusCode::NO_CONTENT
+                }
+            }),
+        );
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+        (url, received)
+    }
+
+    #[tokio::test]
+    async fn test_webhook_retry() {
+        let (url, received) = start_receiver(StatusCode::SERVICE_UNAVAILABLE).await;
+        let sender =
+            WebhookSender::new(3, Duration::from_millis(10)).allow_hosts(["127.0.0.1".to_string()]);
+        let attempts = sender.send(&url, "s3cret", r#"{"rule_id":1}"#).await;
+        assert_eq!(attempts.len(), 2);
+        assert_eq!(attempts[0].status_code, Some(503));
+        assert!(!attempts[0].success);
+        assert!(attempts[1].success);
+
+        let received = received.lock().unwrap();
+        let (signature, body) = &received[0];
+        assert_eq!(body, r#"{"rule_id":1}"#);
+        let timestamp: i64 = signature[2..signature.find(',').unwrap()].parse().unwrap();
+        assert_eq!(*signature, sign("s3cret", timestamp, body));
+    }
+
+    #[tokio::test]
+    async fn test_webhook_rejected() {
+        let (url, received) = start_receiver(StatusCode::GONE).await;
+        let sender =
+            WebhookSender::new(3, Duration::from_millis(10)).allow_hosts(["127.0.0.1".to_string()]);
+        let attempts = sender.send(&url, "s3cret", "{}").await;
+        // the client errors are not retried
+        assert_eq!(attempts.len(), 1);
+        assert_eq!(attempts[0].status_code, Some(410));
+        assert!(received.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_is_public() {
+        for ip in [
+            "93.184.216.34",
+            "2606:2800:220:1::1",
+            "::ffff:93.184.216.34",
+        ] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_webhook_private_host() {
+        let (url, received) = start_receiver(StatusCode::NO_CONTENT).await;
+        let sender = WebhookSender::new(3, Duration::from_millis(10));
+        for url in [
+            url.as_str(),
+            "http://localhost/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://[::1]/hook",
+            "ftp://93.184.216.34/hook",
+        ] {
+            assert!(sender.check_url(url).await.is_err(), "{}", url);
+        }
+        let attempts = sender.send(&url, "s3cret", "{}").await;
+        assert_eq!(attempts.len(), 1);
+        assert!(!attempts[0].success);
+        assert_eq!(attempts[0].status_code, None);
+        assert!(received.lock().unwrap().is_empty());
+
+        // an ip is checked without lookup
+        let public = sender.check_url("https://93.184.216.34/hook").await;
+        assert_eq!(public.unwrap(), Some("93.184.216.34:443".parse().unwrap()));
+    }
+}
@@ -0,0 +1,241 @@
+//! Token amounts kept as raw integers with their decimals, so large 18 decimals balances are
+//! summed and compared without the loss of a float.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::iter::Sum;
+use std::ops::Add;
+use std::str::FromStr;
+
+use primitive_types::U256;
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::merkle::{format_units, parse_units, MerkleError};
+
+/// Largest scale of a `Decimal`.
+const MAX_DECIMAL_SCALE: u32 = 28;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Amount {
+    raw: U256,
+    decimals: u32,
+}
+
+impl Amount {
+    pub fn new(raw: U256, decimals: u32) -> Self {
+        Self { raw, decimals }
+    }
+
+    /// Raw amount expressed with more `decimals`, saturating on overflow.
+    fn rescale(&self, decimals: u32) -> U256 {
+        let shift = decimals.saturating_sub(self.decimals);
+        if shift == 0 {
+            self.raw
+        } else {
+            self.raw.saturating_mul(U256::exp10(shift as usize))
+        }
+    }
+
+    /// Nearest float, for the responses and the storage that keep a float.
+    pub fn to_f64(self) -> f64 {
+        self.to_string().parse().unwrap_or(0.0)
+    }
+
+    /// The amount as a decimal, the least significant digits are dropped when it does not fit
+    /// the 96 bits and 28 decimals of a `Decimal`.
+    pub fn to_decimal(self) -> Decimal {
+        let max = U256::from(Decimal::MAX.mantissa() as u128);
+        let (mut raw, mut scale) = (self.raw, self.decimals);
+        while raw > max || scale > MAX_DECIMAL_SCALE {
+            if scale == 0 {
+                return Decimal::MAX;
+            }
+            raw /= 10;
+            scale -= 1;
+        }
+        Decimal::from_i128_with_scale(raw.as_u128() as i128, scale)
+    }
+
+    /// Raw integer of the amount with `decimals` decimals, the digits beyond them are truncated.
+    pub fn to_raw(self, decimals: u32) -> U256 {
+        match self.decimals.checked_sub(decimals) {
+            Some(drop) if drop > 0 => self.raw / U256::exp10(drop as usize),
+            _ => self.rescale(decimals),
+        }
+    }
+
+    /// The amount multiplied by a vote weight, exactly: the decimals of the weight are added to
+    /// the ones of the amount. A weight that is negative or not a finite number gives zero.
+    pub fn weighted(self, weight: f64) -> Amount {
+        let weight = Decimal::from_f64(weight).unwrap_or_default().normalize();
+        if weight.is_sign_negative() {
+            return Amount::default();
+        }
+        Amount {
+            raw: self
+                .raw
+                .saturating_mul(U256::from(weight.mantissa() as u128)),
+            decimals: self.decimals + weight.scale(),
+        }
+    }
+
+    /// Value of the amount at `price`, zero when the price is not a finite number.
+    pub fn value_at(self, price: f64) -> Decimal {
+        let price = Decimal::from_f64(price).unwrap_or_default();
+        self.to_decimal()
+            .checked_mul(price)
+            .unwrap_or(Decimal::MAX)
+            .normalize()
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format_units(self.raw, self.decimals))
+    }
+}
+
+/// Parses a decimal amount like `12.5`, with as many decimals as it has fractional digits.
+impl FromStr for Amount {
+    type Err = MerkleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let decimals = s.split_once('.').map_or(0, |(_, frac)| frac.len() as u32);
+        Ok(Amount::new(parse_units(s, decimals)?, decimals))
+    }
+}
+
+/// Amounts are written as decimal strings, a json number would be read back as a float.
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Exact sum, the result has the larger decimals of both sides.
+impl Add for Amount {
+    type Output = Amount;
+
+    fn add(self, rhs: Amount) -> Amount {
+        let decimals = self.decimals.max(rhs.decimals);
+        Amount {
+            raw: self.rescale(decimals).saturating_add(rhs.rescale(decimals)),
+            decimals,
+        }
+    }
+}
+
+impl Sum for Amount {
+    fn sum<I: Iterator<Item = Amount>>(iter: I) -> Amount {
+        iter.fold(Amount::default(), Add::add)
+    }
+}
+
+impl Ord for Amount {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let decimals = self.decimals.max(other.decimals);
+        self.rescale(decimals).cmp(&other.rescale(decimals))
+    }
+}
+
+impl PartialOrd for Amount {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Amounts are equal whatever their decimals, `1` with 6 decimals is `1` with 18 decimals.
+impl PartialEq for Amount {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Amount {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amount(value: &str, decimals: u32) -> Amount {
+        Amount::new(parse_units(value, decimals).unwrap(), decimals)
+    }
+
+    #[test]
+    fn test_exact_sum() {
+        // a float keeps about 16 significant digits
+        let parts = [
+            amount("123456789.123456789123456789", 18),
+            amount("0.000000000000000001", 18),
+            amount("1.5", 6),
+        ];
+        let total: Amount = parts.into_iter().sum();
+        assert_eq!(total.to_string(), "123456790.62345678912345679");
+        assert_eq!(total.decimals, 18);
+        assert_eq!(Amount::default().to_string(), "0");
+    }
+
+    #[test]
+    fn test_compare_decimals() {
+        assert_eq!(amount("1", 6), amount("1", 18));
+        assert!(amount("1.000001", 6) > amount("1.000000999999999999", 18));
+        assert!(amount("0.5", 18) < amount("1", 0));
+    }
+
+    #[test]
+    fn test_value_at() {
+        let balance = amount("1000000.000000000000000001", 18);
+        assert_eq!(
+            balance.value_at(2.5).to_string(),
+            "2500000.0000000000000000025"
+        );
+        assert_eq!(amount("12.5", 6).value_at(0.1).to_string(), "1.25");
+        assert_eq!(amount("1", 18).value_at(f64::NAN), Decimal::ZERO);
+        // more digits than a decimal holds, the least significant ones are dropped
+        let whale = Amount::new(U256::MAX, 18);
+        assert!(whale.to_decimal() > Decimal::from(10u64.pow(18)));
+    }
+
+    #[test]
+    fn test_weighted() {
+        let balance = amount("123456789.123456789123456789", 18);
+        assert_eq!(
+            balance.weighted(0.5).to_string(),
+            "61728394.5617283945617283945"
+        );
+        assert_eq!(
+            balance.weighted(2.0),
+            amount("246913578.246913578246913578", 18)
+        );
+        assert_eq!(amount("3", 18).weighted(0.1).to_string(), "0.3");
+        assert_eq!(balance.weighted(-1.0), Amount::default());
+        assert_eq!(balance.weighted(f64::NAN), Amount::default());
+    }
+
+    #[test]
+    fn test_parse_and_raw() {
+        let parsed: Amount = "1234567890.123456789012345678".parse().unwrap();
+        assert_eq!(parsed.to_string(), "1234567890.123456789012345678");
+        assert_eq!(
+            parsed.to_raw(18).to_string(),
+            "1234567890123456789012345678"
+        );
+        // truncated to the smallest unit of a 6 decimals token
+        assert_eq!(parsed.to_raw(6).to_string(), "1234567890123456");
+        assert_eq!("7".parse::<Amount>().unwrap().to_raw(2), U256::from(700));
+        assert!("1e21".parse::<Amount>().is_err());
+        let json = serde_json::to_string(&parsed).unwrap();
+        assert_eq!(json, "\"1234567890.123456789012345678\"");
+        assert_eq!(serde_json::from_str::<Amount>(&json).unwrap(), parsed);
+    }
+}
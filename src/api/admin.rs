@@ -0,0 +1,452 @@
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use axum::extract::{FromRequestParts, Path, Query, State};
+use axum::http::request::Parts;
+use axum::routing::{delete, get, post, put};
+use axum::{Json, Router};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::merkle::keccak256;
+use crate::price::SECONDS_PER_DAY;
+use crate::storage::admin::AuditEntry;
+use crate::storage::api_key::{ApiClient, ApiUsage};
+use crate::storage::{chain::ChainInfo, token::TokenInfo, StorageProcessor};
+
+use super::api_key::{generate_key, hash_key, ApiKeys};
+use super::auth::bearer_token;
+use super::error::ApiError;
+use super::{Services, SupportChains};
+
+#[derive(Clone)]
+pub struct ApiAdminData {
+    storage_core: StorageProcessor,
+    support_chains: SupportChains,
+    api_keys: ApiKeys,
+    /// `(name, token)` of the admins
+    tokens: Vec<(String, String)>,
+}
+
+impl ApiAdminData {
+    pub fn new(services: &Services, support_chains: SupportChains, api_keys: ApiKeys) -> Self {
+        Self {
+            storage_core: services.storage.clone(),
+            support_chains,
+            api_keys,
+            tokens: services.admin_tokens.clone(),
+        }
+    }
+}
+
+pub(super) fn parse_admin_tokens(s: &str) -> Vec<(String, String)> {
+    s.split(',')
+        .filter_map(|pair| pair.trim().split_once(':'))
+        .filter(|(name, token)| !name.is_empty() && !token.is_empty())
+        .map(|(name, token)| (name.to_string(), token.to_string()))
+        .collect()
+}
+
+/// Name of the admin authenticated by the `Authorization: Bearer <token>` header.
+pub struct Admin(String);
+
+#[async_trait]
+impl FromRequestParts<ApiAdminData> for Admin {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ApiAdminData,
+    ) -> Result<Self, Self::Rejection> {
+        let token = bearer_token(&parts.headers).ok_or(ApiError::Unauthorized)?;
+        // compare digests so the time taken does not depend on how much of the token matches
+        let digest = keccak256(token.as_bytes());
+        state
+            .tokens
+            .iter()
+            .find(|(_, t)| keccak256(t.as_bytes()) == digest)
+            .map(|(name, _)| Admin(name.clone()))
+            .ok_or(ApiError::Unauthorized)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Disabled {
+    disabled: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VoteTokenMapping {
+    id: String,
+    /// token id of the vote token on each chain
+    tokens: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewApiKey {
+    name: String,
+    /// requests per utc day, unlimited when unset
+    daily_quota: Option<i64>,
+    /// requests per minute, unlimited when unset
+    rate_limit: Option<i64>,
+}
+
+/// The key is only returned when issued.
+#[derive(Debug, Serialize)]
+pub struct IssuedApiKey {
+    #[serde(flatten)]
+    client: ApiClient,
+    key: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryUsage {
+    /// number of past utc days, today included
+    days: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryAudit {
+    limit: Option<i64>,
+    #[serde(default)]
+    offset: i64,
+}
+
+pub fn api_scope(
+    services: &Services,
+    support_chains: SupportChains,
+    api_keys: ApiKeys,
+) -> Router<ApiAdminData> {
+    Router::with_state(ApiAdminData::new(services, support_chains, api_keys))
+        .route("/chain", post(create_chain))
+        .route("/chain/:id", put(update_chain))
+        .route("/chain/:id/disabled", put(set_chain_disabled))
+        .route("/token", post(create_token))
+        .route("/token/:chain/:id", put(update_token))
+        .route("/token/:chain/:id/disabled", put(set_token_disabled))
+        .route("/vote_token", post(create_vote_token))
+        .route("/vote_token/:id", put(update_vote_token))
+        .route("/vote_token/:id/disabled", put(set_vote_token_disabled))
+        .route("/api_key", get(api_key_list).post(issue_api_key))
+        .route("/api_key/:id", delete(revoke_api_key))
+        .route("/api_key/:id/usage", get(api_key_usage))
+        .route("/audit", get(audit_log))
+}
+
+/// Records the change made by the admin with the request body.
+async fn audit(
+    state: &ApiAdminData,
+    admin: &Admin,
+    action: &str,
+    target: &str,
+    payload: &impl Serialize,
+) -> Result<(), ApiError> {
+    tracing::info!("admin {} {} {}", admin.0, action, target);
+    state
+        .storage_core
+        .save_audit_entry(&admin.0, action, target, &serde_json::to_string(payload)?)
+        .await?;
+    Ok(())
+}
+
+fn disabled_action(kind: &str, disabled: &Disabled) -> String {
+    match disabled.disabled {
+        true => format!("disable_{}", kind),
+        false => format!("enable_{}", kind),
+    }
+}
+
+async fn create_chain(
+    State(state): State<ApiAdminData>,
+    admin: Admin,
+    Json(chain): Json<ChainInfo>,
+) -> Result<Json<ChainInfo>, ApiError> {
+    if state.storage_core.chain_exists(&chain.id).await? {
+        return Err(ApiError::Conflict(format!("chain {}", chain.id)));
+    }
+    state.storage_core.insert_chain(&chain, false).await?;
+    audit(&state, &admin, "create_chain", &chain.id, &chain).await?;
+    state.support_chains.refresh(&state.storage_core).await?;
+    Ok(Json(chain))
+}
+
+async fn update_chain(
+    State(state): State<ApiAdminData>,
+    admin: Admin,
+    Path(id): Path<String>,
+    Json(chain): Json<ChainInfo>,
+) -> Result<Json<ChainInfo>, ApiError> {
+    if chain.id != id {
+        return Err(ApiError::BadRequest(
+            "the chain id can not be changed".into(),
+        ));
+    }
+    if !state.storage_core.chain_exists(&id).await? {
+        return Err(ApiError::NotFound(format!("chain {}", id)));
+    }
+    state.storage_core.update_chain(&chain).await?;
+    audit(&state, &admin, "update_chain", &id, &chain).await?;
+    Ok(Json(chain))
+}
+
+async fn set_chain_disabled(
+    State(state): State<ApiAdminData>,
+    admin: Admin,
+    Path(id): Path<String>,
+    Json(info): Json<Disabled>,
+) -> Result<Json<Disabled>, ApiError> {
+    if !state.storage_core.chain_exists(&id).await? {
+        return Err(ApiError::NotFound(format!("chain {}", id)));
+    }
+    state
+        .storage_core
+        .set_chain_disabled(&id, info.disabled)
+        .await?;
+    audit(&state, &admin, &disabled_action("chain", &info), &id, &info).await?;
+    state.support_chains.refresh(&state.storage_core).await?;
+    Ok(Json(info))
+}
+
+async fn create_token(
+    State(state): State<ApiAdminData>,
+    admin: Admin,
+    Json(token): Json<TokenInfo>,
+) -> Result<Json<TokenInfo>, ApiError> {
+    let target = format!("{}:{}", token.chain, token.id);
+    if !state.storage_core.chain_exists(&token.chain).await? {
+        return Err(ApiError::BadRequest(format!(
+            "unknown chain {}",
+            token.chain
+        )));
+    }
+    if state
+        .storage_core
+        .token_exists(&token.chain, &token.id)
+        .await?
+    {
+        return Err(ApiError::Conflict(format!("token {}", target)));
+    }
+    state.storage_core.insert_token(&token, true).await?;
+    audit(&state, &admin, "create_token", &target, &token).await?;
+    Ok(Json(token))
+}
+
+async fn update_token(
+    State(state): State<ApiAdminData>,
+    admin: Admin,
+    Path((chain, id)): Path<(String, String)>,
+    Json(token): Json<TokenInfo>,
+) -> Result<Json<TokenInfo>, ApiError> {
+    if token.chain != chain || token.id != id {
+        return Err(ApiError::BadRequest(
+            "the token chain and id can not be changed".into(),
+        ));
+    }
+    let target = format!("{}:{}", chain, id);
+    if !state.storage_core.token_exists(&chain, &id).await? {
+        return Err(ApiError::NotFound(format!("token {}", target)));
+    }
+    state.storage_core.update_token(&token).await?;
+    audit(&state, &admin, "update_token", &target, &token).await?;
+    Ok(Json(token))
+}
+
+async fn set_token_disabled(
+    State(state): State<ApiAdminData>,
+    admin: Admin,
+    Path((chain, id)): Path<(String, String)>,
+    Json(info): Json<Disabled>,
+) -> Result<Json<Disabled>, ApiError> {
+    let target = format!("{}:{}", chain, id);
+    if !state.storage_core.token_exists(&chain, &id).await? {
+        return Err(ApiError::NotFound(format!("token {}", target)));
+    }
+    state
+        .storage_core
+        .set_token_disabled(&chain, &id, info.disabled)
+        .await?;
+    audit(
+        &state,
+        &admin,
+        &disabled_action("token", &info),
+        &target,
+        &info,
+    )
+    .await?;
+    Ok(Json(info))
+}
+
+async fn check_vote_token_chains(
+    state: &ApiAdminData,
+    mapping: &VoteTokenMapping,
+) -> Result<(), ApiError> {
+    for chain in mapping.tokens.keys() {
+        if !state.storage_core.chain_exists(chain).await? {
+            return Err(ApiError::BadRequest(format!("unknown chain {}", chain)));
+        }
+    }
+    Ok(())
+}
+
+async fn create_vote_token(
+    State(state): State<ApiAdminData>,
+    admin: Admin,
+    Json(mapping): Json<VoteTokenMapping>,
+) -> Result<Json<VoteTokenMapping>, ApiError> {
+    check_vote_token_chains(&state, &mapping).await?;
+    if state.storage_core.vote_token_exists(&mapping.id).await? {
+        return Err(ApiError::Conflict(format!("vote token {}", mapping.id)));
+    }
+    state
+        .storage_core
+        .insert_vote_token(&mapping.id, &mapping.tokens)
+        .await?;
+    audit(&state, &admin, "create_vote_token", &mapping.id, &mapping).await?;
+    Ok(Json(mapping))
+}
+
+async fn update_vote_token(
+    State(state): State<ApiAdminData>,
+    admin: Admin,
+    Path(id): Path<String>,
+    Json(mapping): Json<VoteTokenMapping>,
+) -> Result<Json<VoteTokenMapping>, ApiError> {
+    if mapping.id != id {
+        return Err(ApiError::BadRequest(
+            "the vote token id can not be changed".into(),
+        ));
+    }
+    check_vote_token_chains(&state, &mapping).await?;
+    if !state.storage_core.vote_token_exists(&id).await? {
+        return Err(ApiError::NotFound(format!("vote token {}", id)));
+    }
+    state
+        .storage_core
+        .update_vote_token(&id, &mapping.tokens)
+        .await?;
+    audit(&state, &admin, "update_vote_token", &id, &mapping).await?;
+    Ok(Json(mapping))
+}
+
+async fn set_vote_token_disabled(
+    State(state): State<ApiAdminData>,
+    admin: Admin,
+    Path(id): Path<String>,
+    Json(info): Json<Disabled>,
+) -> Result<Json<Disabled>, ApiError> {
+    if !state.storage_core.vote_token_exists(&id).await? {
+        return Err(ApiError::NotFound(format!("vote token {}", id)));
+    }
+    state
+        .storage_core
+        .set_vote_token_disabled(&id, info.disabled)
+        .await?;
+    let action = disabled_action("vote_token", &info);
+    audit(&state, &admin, &action, &id, &info).await?;
+    Ok(Json(info))
+}
+
+async fn api_key_list(
+    State(state): State<ApiAdminData>,
+    _admin: Admin,
+) -> Result<Json<Vec<ApiClient>>, ApiError> {
+    Ok(Json(state.storage_core.load_api_clients().await?))
+}
+
+async fn issue_api_key(
+    State(state): State<ApiAdminData>,
+    admin: Admin,
+    Json(info): Json<NewApiKey>,
+) -> Result<Json<IssuedApiKey>, ApiError> {
+    if info.daily_quota.is_some_and(|q| q < 0) || info.rate_limit.is_some_and(|r| r <= 0) {
+        return Err(ApiError::BadRequest("invalid quota or rate limit".into()));
+    }
+    let key = generate_key();
+    let mut client = ApiClient {
+        id: 0,
+        name: info.name.clone(),
+        key_prefix: key[..10].to_string(),
+        daily_quota: info.daily_quota,
+        rate_limit: info.rate_limit,
+        created_at: Utc::now().timestamp(),
+        revoked_at: None,
+    };
+    client.id = state
+        .storage_core
+        .insert_api_client(&client, &hash_key(&key))
+        .await?;
+    audit(
+        &state,
+        &admin,
+        "issue_api_key",
+        &client.id.to_string(),
+        &info,
+    )
+    .await?;
+    Ok(Json(IssuedApiKey { client, key }))
+}
+
+async fn revoke_api_key(
+    State(state): State<ApiAdminData>,
+    admin: Admin,
+    Path(id): Path<i64>,
+) -> Result<Json<ApiClient>, ApiError> {
+    if state.storage_core.load_api_client(id).await?.is_none() {
+        return Err(ApiError::NotFound(format!("api key {}", id)));
+    }
+    state
+        .storage_core
+        .revoke_api_client(id, Utc::now().timestamp())
+        .await?;
+    state.api_keys.forget(id);
+    audit(&state, &admin, "revoke_api_key", &id.to_string(), &()).await?;
+    let client = state
+        .storage_core
+        .load_api_client(id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("api key {}", id)))?;
+    Ok(Json(client))
+}
+
+async fn api_key_usage(
+    State(state): State<ApiAdminData>,
+    _admin: Admin,
+    Path(id): Path<i64>,
+    Query(info): Query<QueryUsage>,
+) -> Result<Json<Vec<ApiUsage>>, ApiError> {
+    let days = info.days.unwrap_or(30).clamp(1, 366);
+    let now = Utc::now().timestamp();
+    let since = now - now.rem_euclid(SECONDS_PER_DAY) - (days - 1) * SECONDS_PER_DAY;
+    let res = state.storage_core.load_api_usage(id, since).await?;
+    Ok(Json(res))
+}
+
+async fn audit_log(
+    State(state): State<ApiAdminData>,
+    _admin: Admin,
+    Query(info): Query<QueryAudit>,
+) -> Result<Json<Vec<AuditEntry>>, ApiError> {
+    let limit = info.limit.unwrap_or(100).clamp(1, 1000);
+    let res = state
+        .storage_core
+        .load_audit_entries(limit, info.offset.max(0))
+        .await?;
+    Ok(Json(res))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_admin_tokens() {
+        assert_eq!(
+            parse_admin_tokens("alice:s3cret, bob:pa:ss,broken,:empty,"),
+            vec![
+                ("alice".to_string(), "s3cret".to_string()),
+                ("bob".to_string(), "pa:ss".to_string()),
+            ]
+        );
+        assert!(parse_admin_tokens("").is_empty());
+    }
+}
@@ -0,0 +1,310 @@
+use axum::extract::{FromRef, Path, Query, State};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::Utc;
+use rand::distributions::{Alphanumeric, DistString};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::alert::{self, AlertEvent, AlertKind, ThresholdMode, WebhookSender};
+use crate::merkle::to_checksum_address;
+use crate::storage::alert::{AlertDelivery, AlertRule};
+use crate::storage::StorageProcessor;
+
+use super::auth::SessionUser;
+use super::error::ApiError;
+use super::Services;
+
+/// Every rule costs debank units at each evaluation.
+const MAX_ALERT_RULES: usize = 20;
+
+#[derive(Clone)]
+pub struct ApiAlertData {
+    storage_core: StorageProcessor,
+    sender: WebhookSender,
+}
+
+impl ApiAlertData {
+    pub fn new(services: &Services) -> Self {
+        Self {
+            storage_core: services.storage.clone(),
+            sender: WebhookSender::from_env(),
+        }
+    }
+}
+
+impl FromRef<ApiAlertData> for StorageProcessor {
+    fn from_ref(state: &ApiAlertData) -> Self {
+        state.storage_core.clone()
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewAlertRule {
+    address: String,
+    kind: AlertKind,
+    /// the token of the `token_amount` alerts
+    chain: Option<String>,
+    token_id: Option<String>,
+    threshold: f64,
+    #[serde(default)]
+    threshold_mode: ThresholdMode,
+    webhook_url: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AlertRuleUpdate {
+    threshold: f64,
+    threshold_mode: ThresholdMode,
+    webhook_url: String,
+    enabled: bool,
+}
+
+/// The secret is only returned when the rule is created.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedAlertRule {
+    #[serde(flatten)]
+    rule: AlertRule,
+    secret: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QueryDeliveries {
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TestDelivery {
+    delivered: bool,
+}
+
+pub fn api_scope(services: &Services) -> Router<ApiAlertData> {
+    let state = ApiAlertData::new(services);
+    Router::with_state(state)
+        .route("/", get(alert_list).post(create_alert))
+        .route(
+            "/:id",
+            get(alert_detail).put(update_alert).delete(delete_alert),
+        )
+        .route("/:id/deliveries", get(alert_deliveries))
+        .route("/:id/test", post(test_alert))
+}
+
+/// The webhook may only reach public addresses, checked again before every delivery.
+async fn check_settings(
+    sender: &WebhookSender,
+    threshold: f64,
+    webhook_url: &str,
+) -> Result<(), ApiError> {
+    if !threshold.is_finite() || threshold < 0.0 {
+        return Err(ApiError::BadRequest("invalid threshold".into()));
+    }
+    sender.check_url(webhook_url).await?;
+    Ok(())
+}
+
+/// Loads the rule of the user, the rules of other users are not found.
+async fn owned_rule(
+    state: &ApiAlertData,
+    user: &SessionUser,
+    id: i64,
+) -> Result<AlertRule, ApiError> {
+    state
+        .storage_core
+        .load_alert_rule(id)
+        .await?
+        .filter(|r| r.owner == user.address)
+        .ok_or_else(|| ApiError::NotFound(format!("alert {}", id)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/alert/",
+    tag = "alert",
+    security(("session" = [])),
+    responses((status = 200, body = [AlertRule]))
+)]
+async fn alert_list(
+    State(state): State<ApiAlertData>,
+    user: SessionUser,
+) -> Result<Json<Vec<AlertRule>>, ApiError> {
+    Ok(Json(
+        state.storage_core.load_alert_rules(&user.address).await?,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/alert/",
+    tag = "alert",
+    request_body = NewAlertRule,
+    security(("session" = [])),
+    responses((status = 200, body = CreatedAlertRule))
+)]
+async fn create_alert(
+    State(state): State<ApiAlertData>,
+    user: SessionUser,
+    Json(info): Json<NewAlertRule>,
+) -> Result<Json<CreatedAlertRule>, ApiError> {
+    check_settings(&state.sender, info.threshold, &info.webhook_url).await?;
+    let address = to_checksum_address(&info.address)
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?
+        .to_lowercase();
+    let (chain, token_id) = match info.kind {
+        AlertKind::TotalUsd => (None, None),
+        AlertKind::TokenAmount => match (info.chain, info.token_id) {
+            (Some(chain), Some(token_id)) => (Some(chain), Some(token_id)),
+            _ => {
+                return Err(ApiError::BadRequest(
+                    "a token alert needs the chain and the token id".into(),
+                ))
+            }
+        },
+    };
+    if state
+        .storage_core
+        .load_alert_rules(&user.address)
+        .await?
+        .len()
+        >= MAX_ALERT_RULES
+    {
+        return Err(ApiError::BadRequest(format!(
+            "a user has at most {} alerts",
+            MAX_ALERT_RULES
+        )));
+    }
+
+    let secret = Alphanumeric.sample_string(&mut rand::thread_rng(), 32);
+    let mut rule = AlertRule {
+        id: 0,
+        owner: user.address,
+        address,
+        kind: info.kind.as_str().to_string(),
+        chain,
+        token_id,
+        threshold: info.threshold,
+        threshold_mode: info.threshold_mode.as_str().to_string(),
+        webhook_url: info.webhook_url,
+        secret: secret.clone(),
+        enabled: true,
+        created_at: Utc::now().timestamp(),
+        last_value: None,
+        last_observed_at: None,
+    };
+    rule.id = state.storage_core.insert_alert_rule(&rule).await?;
+    Ok(Json(CreatedAlertRule { rule, secret }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/alert/{id}",
+    tag = "alert",
+    params(("id" = i64, Path, description = "alert id")),
+    security(("session" = [])),
+    responses((status = 200, body = AlertRule))
+)]
+async fn alert_detail(
+    State(state): State<ApiAlertData>,
+    user: SessionUser,
+    Path(id): Path<i64>,
+) -> Result<Json<AlertRule>, ApiError> {
+    Ok(Json(owned_rule(&state, &user, id).await?))
+}
+
+#[utoipa::path(
+    put,
+    path = "/alert/{id}",
+    tag = "alert",
+    params(("id" = i64, Path, description = "alert id")),
+    request_body = AlertRuleUpdate,
+    security(("session" = [])),
+    responses((status = 200, body = AlertRule))
+)]
+async fn update_alert(
+    State(state): State<ApiAlertData>,
+    user: SessionUser,
+    Path(id): Path<i64>,
+    Json(info): Json<AlertRuleUpdate>,
+) -> Result<Json<AlertRule>, ApiError> {
+    check_settings(&state.sender, info.threshold, &info.webhook_url).await?;
+    let rule = AlertRule {
+        threshold: info.threshold,
+        threshold_mode: info.threshold_mode.as_str().to_string(),
+        webhook_url: info.webhook_url,
+        enabled: info.enabled,
+        ..owned_rule(&state, &user, id).await?
+    };
+    state.storage_core.update_alert_rule(&rule).await?;
+    Ok(Json(rule))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/alert/{id}",
+    tag = "alert",
+    params(("id" = i64, Path, description = "alert id")),
+    security(("session" = [])),
+    responses((status = 200, body = ()))
+)]
+async fn delete_alert(
+    State(state): State<ApiAlertData>,
+    user: SessionUser,
+    Path(id): Path<i64>,
+) -> Result<Json<()>, ApiError> {
+    owned_rule(&state, &user, id).await?;
+    state.storage_core.delete_alert_rule(id).await?;
+    Ok(Json(()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/alert/{id}/deliveries",
+    tag = "alert",
+    params(("id" = i64, Path, description = "alert id"), QueryDeliveries),
+    security(("session" = [])),
+    responses((status = 200, body = [AlertDelivery]))
+)]
+async fn alert_deliveries(
+    State(state): State<ApiAlertData>,
+    user: SessionUser,
+    Path(id): Path<i64>,
+    Query(info): Query<QueryDeliveries>,
+) -> Result<Json<Vec<AlertDelivery>>, ApiError> {
+    owned_rule(&state, &user, id).await?;
+    let limit = info.limit.unwrap_or(50).clamp(1, 500);
+    let res = state.storage_core.load_alert_deliveries(id, limit).await?;
+    Ok(Json(res))
+}
+
+/// Sends an event repeating the last observation so the receiver can be checked.
+#[utoipa::path(
+    post,
+    path = "/alert/{id}/test",
+    tag = "alert",
+    params(("id" = i64, Path, description = "alert id")),
+    security(("session" = [])),
+    responses((status = 200, body = TestDelivery))
+)]
+async fn test_alert(
+    State(state): State<ApiAlertData>,
+    user: SessionUser,
+    Path(id): Path<i64>,
+) -> Result<Json<TestDelivery>, ApiError> {
+    let rule = owned_rule(&state, &user, id).await?;
+    let value = rule.last_value.unwrap_or_default();
+    let event = AlertEvent {
+        rule_id: rule.id,
+        address: rule.address.clone(),
+        kind: rule.kind.parse()?,
+        chain: rule.chain.clone(),
+        token_id: rule.token_id.clone(),
+        previous: value,
+        current: value,
+        observed_at: rule
+            .last_observed_at
+            .unwrap_or_else(|| Utc::now().timestamp()),
+    };
+    let delivered = alert::deliver(&state.storage_core, &state.sender, &rule, &event).await?;
+    Ok(Json(TestDelivery { delivered }))
+}
@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::{MatchedPath, Query, State};
+use axum::http::{Request, Uri};
+use axum::middleware::Next;
+use axum::response::Response;
+use chrono::Utc;
+use rand::RngCore;
+use serde::Deserialize;
+
+use crate::merkle::{keccak256, to_hex};
+use crate::price::SECONDS_PER_DAY;
+use crate::storage::api_key::ApiClient;
+use crate::storage::{StorageError, StorageProcessor};
+
+use super::error::ApiError;
+
+/// Header carrying the client key.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// How long a client stays cached, a key revoked on another instance is accepted at most
+/// this long.
+const CLIENT_CACHE_TTL: Duration = Duration::from_secs(60);
+
+pub fn generate_key() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("zp_{}", hex::encode(bytes))
+}
+
+/// Only the hash of the keys is stored, the keys are random so no salt is needed.
+pub fn hash_key(key: &str) -> String {
+    to_hex(&keccak256(key.as_bytes()))
+}
+
+/// Token bucket allowing bursts of up to a minute of requests.
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(per_minute: i64, now: Instant) -> Self {
+        Self {
+            tokens: per_minute as f64,
+            updated: now,
+        }
+    }
+
+    fn try_acquire(&mut self, per_minute: i64, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * per_minute as f64 / 60.0).min(per_minute as f64);
+        self.updated = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ApiKeysInner {
+    clients: HashMap<String, (Instant, ApiClient)>,
+    buckets: HashMap<i64, TokenBucket>,
+    /// `(day, requests)` of each client
+    daily: HashMap<i64, (i64, i64)>,
+}
+
+/// Authenticates the clients and enforces their limits, the daily counters start from the
+/// stored usage so a restart does not reset the quotas.
+#[derive(Clone)]
+pub struct ApiKeys {
+    storage: StorageProcessor,
+    inner: Arc<Mutex<ApiKeysInner>>,
+}
+
+impl ApiKeys {
+    pub fn new(storage: StorageProcessor) -> Self {
+        Self {
+            storage,
+            inner: Default::default(),
+        }
+    }
+
+    async fn client(&self, key: &str) -> Result<Option<ApiClient>, StorageError> {
+        let key_hash = hash_key(key);
+        if let Some((loaded_at, client)) = self.inner.lock().unwrap().clients.get(&key_hash) {
+            if loaded_at.elapsed() < CLIENT_CACHE_TTL {
+                return Ok(Some(client.clone()));
+            }
+        }
+        let client = self.storage.load_api_client_by_hash(&key_hash).await?;
+        let mut inner = self.inner.lock().unwrap();
+        match &client {
+            Some(client) => inner
+                .clients
+                .insert(key_hash, (Instant::now(), client.clone())),
+            None => inner.clients.remove(&key_hash),
+        };
+        Ok(client)
+    }
+
+    /// Drops the cached client so a revocation applies right away.
+    pub fn forget(&self, client_id: i64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .clients
+            .retain(|_, (_, client)| client.id != client_id);
+        inner.buckets.remove(&client_id);
+        inner.daily.remove(&client_id);
+    }
+
+    async fn acquire(&self, client: &ApiClient, day: i64) -> Result<(), ApiError> {
+        let seeded = matches!(
+            self.inner.lock().unwrap().daily.get(&client.id),
+            Some((d, _)) if *d == day
+        );
+        if !seeded && client.daily_quota.is_some() {
+            let used = self.storage.load_daily_api_usage(client.id, day).await?;
+            let mut inner = self.inner.lock().unwrap();
+            let entry = inner.daily.entry(client.id).or_insert((day, used));
+            if entry.0 != day {
+                *entry = (day, used);
+            }
+        }
+
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(per_minute) = client.rate_limit {
+            let bucket = inner
+                .buckets
+                .entry(client.id)
+                .or_insert_with(|| TokenBucket::new(per_minute, now));
+            if !bucket.try_acquire(per_minute, now) {
+                return Err(ApiError::TooManyRequests(format!(
+                    "rate limit of {} requests per minute exceeded",
+                    per_minute
+                )));
+            }
+        }
+        let daily = inner.daily.entry(client.id).or_insert((day, 0));
+        if daily.0 != day {
+            *daily = (day, 0);
+        }
+        if let Some(quota) = client.daily_quota {
+            if daily.1 >= quota {
+                return Err(ApiError::TooManyRequests(format!(
+                    "daily quota of {} requests exceeded",
+                    quota
+                )));
+            }
+        }
+        daily.1 += 1;
+        Ok(())
+    }
+}
+
+/// Query of the websocket route, the browser websockets cannot set headers.
+#[derive(Debug, Deserialize)]
+pub struct KeyQuery {
+    api_key: Option<String>,
+}
+
+fn header_key<B>(req: &Request<B>) -> Option<String> {
+    req.headers()
+        .get(API_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+/// The uri with the value of the `api_key` query parameter hidden, for the request logs.
+pub fn redacted_uri(uri: &Uri) -> String {
+    match uri.query() {
+        Some(query) => {
+            let pairs: Vec<_> = query
+                .split('&')
+                .map(|pair| match pair.starts_with("api_key=") {
+                    true => "api_key=REDACTED",
+                    false => pair,
+                })
+                .collect();
+            format!("{}?{}", uri.path(), pairs.join("&"))
+        }
+        None => uri.path().to_string(),
+    }
+}
+
+/// Middleware rejecting the requests without a valid `X-API-Key` and counting the usage of
+/// the client per endpoint.
+pub async fn require_api_key<B>(
+    State(keys): State<ApiKeys>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Result<Response, ApiError> {
+    let key = header_key(&req).ok_or(ApiError::Unauthorized)?;
+    authorize(&keys, &key, req, next).await
+}
+
+/// `require_api_key` for the websocket route, the key may also be given in the `api_key`
+/// query parameter.
+pub async fn require_ws_api_key<B>(
+    State(keys): State<ApiKeys>,
+    Query(query): Query<KeyQuery>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Result<Response, ApiError> {
+    let key = header_key(&req)
+        .or(query.api_key)
+        .ok_or(ApiError::Unauthorized)?;
+    authorize(&keys, &key, req, next).await
+}
+
+async fn authorize<B>(
+    keys: &ApiKeys,
+    key: &str,
+    req: Request<B>,
+    next: Next<B>,
+) -> Result<Response, ApiError> {
+    let client = keys.client(key).await?.ok_or(ApiError::Unauthorized)?;
+    let now = Utc::now().timestamp();
+    let day = now - now.rem_euclid(SECONDS_PER_DAY);
+    keys.acquire(&client, day).await?;
+
+    let endpoint = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| req.uri().path().to_string());
+    let storage = keys.storage.clone();
+    tokio::spawn(async move {
+        if let Err(e) = storage.record_api_usage(client.id, day, &endpoint).await {
+            tracing::warn!("failed to record the usage of client {}: {}", client.id, e);
+        }
+    });
+    Ok(next.run(req).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key() {
+        let key = generate_key();
+        assert_eq!(key.len(), 67);
+        assert_ne!(key, generate_key());
+        assert_eq!(hash_key(&key), hash_key(&key));
+        assert_eq!(hash_key(&key).len(), 66);
+    }
+
+    #[test]
+    fn test_redacted_uri() {
+        let uri: Uri = "/api/v1/ws?foo=1&api_key=zp_abc".parse().unwrap();
+        assert_eq!(redacted_uri(&uri), "/api/v1/ws?foo=1&api_key=REDACTED");
+        let uri: Uri = "/api/v1/ws?foo=1".parse().unwrap();
+        assert_eq!(redacted_uri(&uri), "/api/v1/ws?foo=1");
+        assert_eq!(redacted_uri(&"/api/v1/ws".parse().unwrap()), "/api/v1/ws");
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(60, start);
+        for _ in 0..60 {
+            assert!(bucket.try_acquire(60, start));
+        }
+        assert!(!bucket.try_acquire(60, start));
+        // one token per second
+        assert!(bucket.try_acquire(60, start + Duration::from_millis(1000)));
+        assert!(!bucket.try_acquire(60, start + Duration::from_millis(1500)));
+        // never more than a minute of requests
+        let later = start + Duration::from_secs(3600);
+        for _ in 0..60 {
+            assert!(bucket.try_acquire(60, later));
+        }
+        assert!(!bucket.try_acquire(60, later));
+    }
+}
@@ -0,0 +1,183 @@
+use std::env;
+
+use async_trait::async_trait;
+use axum::extract::{FromRef, FromRequestParts, State};
+use axum::http::{header, request::Parts, HeaderMap};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::Utc;
+use rand::distributions::{Alphanumeric, DistString};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::siwe;
+use crate::storage::session::Session;
+use crate::storage::StorageProcessor;
+
+use super::api_key::{generate_key, hash_key};
+use super::error::ApiError;
+use super::Services;
+
+/// How long an issued nonce can be used to sign in.
+const NONCE_TTL_SECS: i64 = 10 * 60;
+
+#[derive(Clone)]
+pub struct ApiAuthData {
+    storage_core: StorageProcessor,
+    /// domain the messages must be signed for
+    domain: String,
+    session_ttl: i64,
+}
+
+impl ApiAuthData {
+    pub fn new(services: &Services) -> Self {
+        Self {
+            storage_core: services.storage.clone(),
+            domain: env::var("SIWE_DOMAIN").expect("SIWE_DOMAIN must be set"),
+            session_ttl: env::var("SESSION_TTL_SECS")
+                .map(|s| s.parse().expect("SESSION_TTL_SECS must be a number"))
+                .unwrap_or(7 * 24 * 60 * 60),
+        }
+    }
+}
+
+/// Token of the `Authorization: Bearer <token>` header.
+pub(super) fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+/// Address signed in with the session token of the `Authorization` header, usable by the
+/// handlers of any scope whose state gives access to the storage.
+#[derive(Debug, Clone)]
+pub struct SessionUser {
+    /// lowercase address
+    pub address: String,
+    pub expires_at: i64,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for SessionUser
+where
+    StorageProcessor: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let token = bearer_token(&parts.headers).ok_or(ApiError::Unauthorized)?;
+        let storage = StorageProcessor::from_ref(state);
+        let session = storage
+            .load_session(&hash_key(token), Utc::now().timestamp())
+            .await?
+            .ok_or(ApiError::Unauthorized)?;
+        Ok(SessionUser {
+            address: session.address,
+            expires_at: session.expires_at,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Nonce {
+    nonce: String,
+    expires_at: i64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct LoginRequest {
+    /// the EIP-4361 message
+    message: String,
+    /// `personal_sign` signature of the message
+    signature: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LoginResponse {
+    token: String,
+    address: String,
+    expires_at: i64,
+}
+
+pub fn api_scope(services: &Services) -> Router<ApiAuthData> {
+    Router::with_state(ApiAuthData::new(services))
+        .route("/nonce", get(nonce))
+        .route("/login", post(login))
+        .route("/logout", post(logout))
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/nonce",
+    tag = "auth",
+    responses((status = 200, body = Nonce))
+)]
+async fn nonce(State(state): State<ApiAuthData>) -> Result<Json<Nonce>, ApiError> {
+    let now = Utc::now().timestamp();
+    state.storage_core.delete_expired_sessions(now).await?;
+    let nonce = Alphanumeric.sample_string(&mut rand::thread_rng(), 17);
+    let expires_at = now + NONCE_TTL_SECS;
+    state
+        .storage_core
+        .save_siwe_nonce(&nonce, expires_at)
+        .await?;
+    Ok(Json(Nonce { nonce, expires_at }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses((status = 200, body = LoginResponse))
+)]
+async fn login(
+    State(state): State<ApiAuthData>,
+    Json(info): Json<LoginRequest>,
+) -> Result<Json<LoginResponse>, ApiError> {
+    let now = Utc::now().timestamp();
+    let message = siwe::verify(&info.message, &info.signature)?;
+    message.validate(&state.domain, now)?;
+    if !state
+        .storage_core
+        .take_siwe_nonce(&message.nonce, now)
+        .await?
+    {
+        return Err(ApiError::Unauthorized);
+    }
+
+    let token = generate_key();
+    let session = Session {
+        address: message.address.to_lowercase(),
+        created_at: now,
+        // a message expiring earlier bounds the session
+        expires_at: (now + state.session_ttl).min(message.expiration_time.unwrap_or(i64::MAX)),
+    };
+    state
+        .storage_core
+        .save_session(&hash_key(&token), &session)
+        .await?;
+    Ok(Json(LoginResponse {
+        token,
+        address: session.address,
+        expires_at: session.expires_at,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/logout",
+    tag = "auth",
+    security(("session" = [])),
+    responses((status = 200, body = ()))
+)]
+async fn logout(
+    State(state): State<ApiAuthData>,
+    headers: HeaderMap,
+) -> Result<Json<()>, ApiError> {
+    let token = bearer_token(&headers).ok_or(ApiError::Unauthorized)?;
+    state.storage_core.delete_session(&hash_key(token)).await?;
+    Ok(Json(()))
+}
@@ -0,0 +1,94 @@
+use axum::extract::{Path, Query, State};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::distribution;
+use crate::merkle::{to_checksum_address, Claim};
+use crate::storage::distribution::DistributionInfo;
+use crate::storage::StorageProcessor;
+
+use super::error::ApiError;
+use super::Services;
+
+#[derive(Clone)]
+pub struct ApiDistributionData {
+    storage_core: StorageProcessor,
+}
+
+impl ApiDistributionData {
+    pub fn new(services: &Services) -> Self {
+        Self {
+            storage_core: services.storage.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QueryWithAddress {
+    address: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ClaimProof {
+    merkle_root: String,
+    address: String,
+    #[serde(flatten)]
+    claim: Claim,
+}
+
+pub fn api_scope(services: &Services) -> Router<ApiDistributionData> {
+    Router::with_state(ApiDistributionData::new(services))
+        .route("/:id", get(distribution_info))
+        .route("/:id/proof", get(claim_proof))
+}
+
+async fn load_info(state: &ApiDistributionData, id: i64) -> Result<DistributionInfo, ApiError> {
+    state
+        .storage_core
+        .load_distribution(id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("distribution {}", id)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/distribution/{id}",
+    tag = "distribution",
+    params(("id" = i64, Path, description = "distribution id")),
+    responses((status = 200, body = DistributionInfo))
+)]
+async fn distribution_info(
+    State(state): State<ApiDistributionData>,
+    Path(id): Path<i64>,
+) -> Result<Json<DistributionInfo>, ApiError> {
+    Ok(Json(load_info(&state, id).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/distribution/{id}/proof",
+    tag = "distribution",
+    params(("id" = i64, Path, description = "distribution id"), QueryWithAddress),
+    responses((status = 200, body = ClaimProof))
+)]
+async fn claim_proof(
+    State(state): State<ApiDistributionData>,
+    Path(id): Path<i64>,
+    Query(info): Query<QueryWithAddress>,
+) -> Result<Json<ClaimProof>, ApiError> {
+    let distribution = load_info(&state, id).await?;
+    let claim = state
+        .storage_core
+        .load_distribution_claim(id, &info.address)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("claim of {}", info.address)))?;
+    Ok(Json(ClaimProof {
+        merkle_root: distribution.merkle_root,
+        address: to_checksum_address(&claim.address)
+            .map_err(distribution::DistributionError::from)?,
+        claim: distribution::to_claim(&claim)?,
+    }))
+}
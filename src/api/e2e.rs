@@ -0,0 +1,1701 @@
+//! End to end tests of the routes: the full router on a seeded sqlite database, with the
+//! providers answered by the fixture server of `crate::mock`.
+//!
+//! The schema is the one of `migrations/`, translated from mysql where sqlite differs.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use k256::ecdsa::SigningKey;
+use reqwest::{Method, StatusCode};
+use serde_json::{json, Value};
+use sqlx::any::{AnyPool, AnyPoolOptions};
+use sqlx::Executor;
+
+use crate::debank::openapi::DebankApiError;
+use crate::distribution;
+use crate::fx::{CachedFxProvider, StaticFxProvider};
+use crate::hub::BalanceHub;
+use crate::job::{account_refresher, price_backfill, token_sync, vote_leaderboard};
+use crate::merkle::to_hex;
+use crate::mock::{MockServer, VALID_KEY};
+use crate::price::{PriceError, PriceProvider, SECONDS_PER_DAY};
+use crate::siwe::{personal_message_hash, public_key_address};
+use crate::storage::api_key::ApiClient;
+use crate::storage::chain::ChainInfo;
+use crate::storage::price::TokenPrice;
+use crate::storage::token::TokenInfo;
+use crate::storage::vote::VoteSnapshotEntry;
+use crate::storage::StorageProcessor;
+
+use super::api_key::{hash_key, API_KEY_HEADER};
+use super::{app, Services, SupportChains};
+
+const API_KEY: &str = "zp_e2e";
+/// Domain the sign-in messages are checked against.
+const SIWE_DOMAIN: &str = "app.zportfolio.test";
+const ADMIN_TOKEN: &str = "admin-secret";
+/// Has a debank fixture and a stored activation time.
+const DEBANK_USER: &str = "0xa749cdefd2d9590549df709bbffec04a9bd35b42";
+const DEBANK_USER_ACTIVATION: i64 = 1600000000;
+/// Has an etherscan fixture and nothing stored.
+const ETHERSCAN_USER: &str = "0xddbd2b932c763ba5b1b7ae3b362eac3e8d40121a";
+/// Never sent a transaction.
+const INACTIVE_USER: &str = "0x0000000000000000000000000000000000000001";
+const ZKS_ETH: &str = "0xa3fb9e7e9f2d4fa0b7c5b2de1c47a0f7c0e6f8e1";
+/// An airdrop debank knows nothing of.
+const SPAM_TOKEN: &str = "0xdeadbeef00000000000000000000000000000001";
+const ZKS_BSC: &str = "0x3b3a1de07439eeb04492fa64a889ee25a130cdc3";
+
+/// The statements of the migrations written for mysql, translated to sqlite.
+fn sqlite_schema() -> String {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/migrations");
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    files.sort();
+    files
+        .iter()
+        .map(|file| to_sqlite(&fs::read_to_string(file).unwrap()))
+        .collect()
+}
+
+fn to_sqlite(sql: &str) -> String {
+    let sql = sql.replace(
+        "BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY",
+        "INTEGER PRIMARY KEY AUTOINCREMENT",
+    );
+    // the inline indexes of mysql are left out, the column before loses its comma
+    let mut lines: Vec<String> = Vec::new();
+    for line in sql.lines() {
+        if line.trim_start().starts_with("INDEX ") {
+            if let Some(previous) = lines.last_mut() {
+                previous.pop();
+            }
+        } else {
+            lines.push(line.to_string());
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+fn chain(id: &str, community_id: i32, name: &str) -> ChainInfo {
+    ChainInfo {
+        id: id.into(),
+        community_id,
+        name: name.into(),
+        native_token_id: id.into(),
+        logo_url: String::new(),
+    }
+}
+
+fn token(chain: &str, id: &str, symbol: &str) -> TokenInfo {
+    TokenInfo {
+        id: id.into(),
+        chain: chain.into(),
+        name: symbol.into(),
+        symbol: symbol.into(),
+        decimals: 18,
+        logo_url: String::new(),
+        protocol_id: String::new(),
+        is_core: true,
+    }
+}
+
+/// The app served on a free local port, on a database of its own.
+struct TestApp {
+    url: String,
+    mock: MockServer,
+    /// the storage of the app, for the jobs
+    storage: StorageProcessor,
+    /// the database of the app, for what the storage has no method for
+    db: AnyPool,
+    path: PathBuf,
+    http: reqwest::Client,
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl TestApp {
+    async fn start() -> Self {
+        static DATABASES: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "zportfolio-e2e-{}-{}.db",
+            std::process::id(),
+            DATABASES.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_file(&path);
+        let db_url = format!("sqlite://{}?mode=rwc", path.display());
+        let db = AnyPoolOptions::new().connect(&db_url).await.unwrap();
+        db.execute(sqlite_schema().as_str()).await.unwrap();
+
+        let storage = StorageProcessor::connect(&db_url).await.unwrap();
+        // the same for every app, whatever its port
+        std::env::set_var("SIWE_DOMAIN", SIWE_DOMAIN);
+        // the webhooks are received by the mock
+        std::env::set_var("WEBHOOK_ALLOWED_HOSTS", "127.0.0.1");
+        seed(&storage, &db).await;
+        let mock = MockServer::start();
+        let fx = StaticFxProvider::from_file(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/fx_rates.json"
+        ))
+        .unwrap();
+        let services = Services {
+            storage: storage.clone(),
+            ass_api: mock.debank(VALID_KEY),
+            acc_api: mock.etherscan(VALID_KEY),
+            fx: Arc::new(CachedFxProvider::new(
+                Box::new(fx),
+                Duration::from_secs(600),
+            )),
+            admin_tokens: vec![("root".into(), ADMIN_TOKEN.into())],
+            account_recheck_secs: 86400,
+            hub: BalanceHub::new(1000),
+        };
+        let support_chains = SupportChains::load(&storage).await.unwrap();
+        let router = app(services, support_chains);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(router.into_make_service()),
+        );
+        Self {
+            url,
+            mock,
+            storage,
+            db,
+            path,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    fn request(&self, method: Method, path: &str) -> reqwest::RequestBuilder {
+        self.http
+            .request(method, format!("{}{}", self.url, path))
+            .header(API_KEY_HEADER, API_KEY)
+    }
+
+    async fn get(&self, path: &str) -> (StatusCode, Value) {
+        read(self.request(Method::GET, path)).await
+    }
+
+    async fn text(&self, path: &str) -> (StatusCode, String) {
+        let resp = self.request(Method::GET, path).send().await.unwrap();
+        (resp.status(), resp.text().await.unwrap())
+    }
+
+    /// Request with `body` as json, signed in with the `session` token or as admin.
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        bearer: Option<&str>,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let mut req = self.request(method, path);
+        if let Some(token) = bearer {
+            req = req.bearer_auth(token);
+        }
+        if let Some(body) = body {
+            req = req.json(&body);
+        }
+        read(req).await
+    }
+
+    async fn graphql(&self, query: &str) -> Value {
+        let body = json!({ "query": query });
+        let (status, res) = self
+            .send(Method::POST, "/api/v2/graphql", None, Some(body))
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        res
+    }
+
+    /// Signs in with the address of `key` and returns the session token.
+    async fn login(&self, key: &SigningKey) -> String {
+        let (status, nonce) = self.get("/api/v1/auth/nonce").await;
+        assert_eq!(status, StatusCode::OK);
+        let message = siwe_message(SIWE_DOMAIN, key, nonce["nonce"].as_str().unwrap());
+        let body = json!({ "message": message, "signature": sign(key, &message) });
+        let (status, res) = self
+            .send(Method::POST, "/api/v1/auth/login", None, Some(body))
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", res);
+        res["token"].as_str().unwrap().to_string()
+    }
+}
+
+async fn read(req: reqwest::RequestBuilder) -> (StatusCode, Value) {
+    let resp = req.send().await.unwrap();
+    let status = resp.status();
+    let text = resp.text().await.unwrap();
+    (
+        status,
+        serde_json::from_str(&text).unwrap_or(Value::String(text)),
+    )
+}
+
+/// Chains, tokens, the zks vote token with a leaderboard and its distribution, a user, prices,
+/// a transfer and the api key of the requests.
+async fn seed(storage: &StorageProcessor, db: &AnyPool) {
+    for chain in [
+        chain("eth", 1, "Ethereum"),
+        chain("bsc", 56, "BNB Chain"),
+        chain("arb", 42161, "Arbitrum"),
+    ] {
+        storage.insert_chain(&chain, false).await.unwrap();
+    }
+    for token in [
+        token("eth", "eth", "ETH"),
+        token("bsc", "bsc", "BNB"),
+        token("arb", "arb", "ETH"),
+        token("eth", ZKS_ETH, "ZKS"),
+        token("bsc", ZKS_BSC, "ZKS"),
+    ] {
+        storage.insert_token(&token, false).await.unwrap();
+    }
+    let zks = BTreeMap::from([
+        ("eth".to_string(), ZKS_ETH.to_string()),
+        ("bsc".to_string(), ZKS_BSC.to_string()),
+    ]);
+    storage.insert_vote_token("zks", &zks).await.unwrap();
+    let entries = [
+        VoteSnapshotEntry {
+            rank: 1,
+            address: DEBANK_USER.into(),
+            amount: "1500.5".parse().unwrap(),
+        },
+        VoteSnapshotEntry {
+            rank: 2,
+            address: ETHERSCAN_USER.into(),
+            amount: "20".parse().unwrap(),
+        },
+    ];
+    let snapshot_id = storage
+        .save_vote_snapshot("zks", 1666051200, &entries)
+        .await
+        .unwrap();
+    distribution::create_from_snapshot(storage, snapshot_id, 18)
+        .await
+        .unwrap();
+
+    storage
+        .set_user_info(DEBANK_USER, DEBANK_USER_ACTIVATION)
+        .await
+        .unwrap();
+    let prices: Vec<TokenPrice> = (0..3)
+        .map(|day| TokenPrice {
+            chain: "eth".into(),
+            token_id: "eth".into(),
+            timestamp: 1666051200 + day * 86400,
+            price: 1200.0 + day as f64 * 50.0,
+        })
+        .collect();
+    storage.save_token_prices(&prices).await.unwrap();
+    sqlx::query(
+        "INSERT INTO token_transfer (user_id, chain, token_id, tx_hash, timestamp, amount, price)
+        VALUES (?, 'eth', 'eth', '0x01', 1666051200, 2, 1000)",
+    )
+    .bind(DEBANK_USER)
+    .execute(db)
+    .await
+    .unwrap();
+
+    let client = ApiClient {
+        id: 0,
+        name: "e2e".into(),
+        key_prefix: API_KEY.into(),
+        daily_quota: None,
+        rate_limit: None,
+        created_at: Utc::now().timestamp(),
+        revoked_at: None,
+    };
+    storage
+        .insert_api_client(&client, &hash_key(API_KEY))
+        .await
+        .unwrap();
+}
+
+fn signing_key(n: u8) -> SigningKey {
+    let mut secret = [0u8; 32];
+    secret[31] = n;
+    SigningKey::from_slice(&secret).unwrap()
+}
+
+fn siwe_message(domain: &str, key: &SigningKey, nonce: &str) -> String {
+    let issued_at = Utc::now().format("%Y-%m-%dT%H:%M:%SZ");
+    let expiration = Utc
+        .timestamp_opt(Utc::now().timestamp() + 3600, 0)
+        .unwrap()
+        .format("%Y-%m-%dT%H:%M:%SZ");
+    format!(
+        "{domain} wants you to sign in with your Ethereum account:
+{address}
+
+Sign in to zportfolio
+
+URI: http://{domain}/login
+Version: 1
+Chain ID: 1
+Nonce: {nonce}
+Issued At: {issued_at}
+Expiration Time: {expiration}",
+        address = public_key_address(key.verifying_key()),
+    )
+}
+
+fn sign(key: &SigningKey, message: &str) -> String {
+    let (sig, id) = key
+        .sign_prehash_recoverable(&personal_message_hash(message))
+        .unwrap();
+    let mut bytes = sig.to_bytes().to_vec();
+    bytes.push(id.to_byte() + 27);
+    to_hex(&bytes)
+}
+
+/// `(event, data)` of a server sent events body.
+fn events(body: &str) -> Vec<(String, Value)> {
+    body.split("\n\n")
+        .filter(|e| !e.is_empty())
+        .map(|e| {
+            let mut lines = e.lines();
+            let name = lines.next().unwrap().strip_prefix("event:").unwrap();
+            let data = lines.next().unwrap().strip_prefix("data:").unwrap();
+            (name.to_string(), serde_json::from_str(data).unwrap())
+        })
+        .collect()
+}
+
+#[test]
+fn test_to_sqlite() {
+    let sql = "CREATE TABLE t (
+    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    a BIGINT NOT NULL,
+    INDEX idx_t_a (a)
+);";
+    assert_eq!(
+        to_sqlite(sql),
+        "CREATE TABLE t (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    a BIGINT NOT NULL
+);
+"
+    );
+}
+
+#[tokio::test]
+async fn test_api_key() {
+    let app = TestApp::start().await;
+    let resp = app
+        .http
+        .get(format!("{}/api/v1/chain/list", app.url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let (status, body) = read(
+        app.http
+            .get(format!("{}/api/v2/chain/list", app.url))
+            .header(API_KEY_HEADER, "zp_unknown"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body, json!({ "status": 401, "message": "Unauthorized" }));
+
+    // the documents are public
+    for path in ["/api/v1/openapi.json", "/api/v2/openapi.json"] {
+        let resp = app
+            .http
+            .get(format!("{}{}", app.url, path))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK, "{}", path);
+    }
+    let (status, docs) = app.text("/api/v1/docs").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(docs.contains("swagger-ui"));
+    assert_eq!(app.text("/api/v1/favicon").await.0, StatusCode::OK);
+
+    // the first requests of a day may be counted at once
+    let client = app
+        .storage
+        .load_api_client_by_hash(&hash_key(API_KEY))
+        .await;
+    let client = client.unwrap().unwrap();
+    let day = 1666051200;
+    let counts = (0..8).map(|_| app.storage.record_api_usage(client.id, day, "/race"));
+    for result in futures_util::future::join_all(counts).await {
+        result.unwrap();
+    }
+    let usage = app.storage.load_api_usage(client.id, day).await.unwrap();
+    let race = usage.iter().find(|u| u.endpoint == "/race").unwrap();
+    assert_eq!(race.requests, 8);
+}
+
+#[tokio::test]
+async fn test_catalog() {
+    let app = TestApp::start().await;
+    let resp = app
+        .request(Method::GET, "/api/v1/chain/list?limit=2")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.headers()["x-total-count"], "3");
+    let chains: Value = resp.json().await.unwrap();
+    assert_eq!(chains.as_array().unwrap().len(), 2);
+    assert_eq!(chains[0]["id"], "arb");
+
+    let (status, chain) = app.get("/api/v1/chain/bsc").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(chain["community_id"], 56);
+    let (status, err) = app.get("/api/v1/chain/sol").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(err["status"], 404);
+
+    let (_, tokens) = app.get("/api/v1/token/list?chain=eth").await;
+    assert_eq!(tokens.as_array().unwrap().len(), 2);
+    let (_, tokens) = app.get("/api/v1/token/list?q=zk").await;
+    assert_eq!(tokens.as_array().unwrap().len(), 2);
+
+    let resp = app
+        .request(
+            Method::GET,
+            "/api/v1/token/eth/eth?with_price=true&currency=EUR",
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.headers()["x-fx-currency"], "EUR");
+    let detail: Value = resp.json().await.unwrap();
+    assert_eq!(detail["fx"]["currency"], "EUR");
+    assert_eq!(detail["fx"]["rate"], 0.98);
+    assert_eq!(detail["data"]["symbol"], "ETH");
+    assert_eq!(detail["data"]["price"], 1274.0);
+    let (status, _) = app.get("/api/v1/token/eth/0xmissing").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app.get("/api/v1/token/eth/eth?currency=XXX").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, candles) = app
+        .get(
+            "/api/v1/token/price_history?chain_id=eth&token_id=eth&start=1666051200&end=1666310400",
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(candles.as_array().unwrap().len(), 3);
+    assert_eq!(candles[2]["close"], 1300.0);
+
+    let (_, chains) = app.get("/api/v2/chain/list?q=b").await;
+    assert_eq!(
+        chains,
+        json!([{ "id": "bsc", "name": "BNB Chain", "logo_url": "",
+        "native_token_id": "bsc", "community_id": 56 }])
+    );
+    let (status, _) = app.get("/api/v2/chain/sol").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, tokens) = app.get("/api/v2/token/list?chain=bsc&is_core=true").await;
+    assert_eq!(tokens.as_array().unwrap().len(), 2);
+    let (status, quote) = app.get("/api/v2/token/bsc/bsc?with_price=true").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(quote["price"], "270");
+}
+
+#[tokio::test]
+async fn test_vote_token_new_chain() {
+    let app = TestApp::start().await;
+    let load = || async {
+        let loads = (0..4).map(|_| app.storage.load_token_ids_by_name("zks".to_string()));
+        futures_util::future::join_all(loads).await
+    };
+    // the statement is prepared on several pooled connections
+    for token_ids in load().await {
+        assert_eq!(token_ids.unwrap().unwrap().len(), 2);
+    }
+    let tokens = BTreeMap::from([
+        ("eth".to_string(), ZKS_ETH.to_string()),
+        ("bsc".to_string(), ZKS_BSC.to_string()),
+        (
+            "arb".to_string(),
+            "0x00000000000000000000000000000000000000a2".to_string(),
+        ),
+    ]);
+    app.storage.update_vote_token("zks", &tokens).await.unwrap();
+    for token_ids in load().await {
+        let token_ids = token_ids.unwrap().unwrap();
+        assert_eq!(token_ids["arb"], tokens["arb"]);
+    }
+    let references = app.storage.load_vote_token_references().await.unwrap();
+    assert!(references.contains(&("arb".to_string(), tokens["arb"].clone())));
+}
+
+#[tokio::test]
+async fn test_catalog_sync() {
+    let app = TestApp::start().await;
+    // none of them is known to debank
+    let admin_token = token("eth", "0x00000000000000000000000000000000000000ad", "ADM");
+    let stale = token("eth", "0x0000000000000000000000000000000000000051", "OLD");
+    let retired = token("arb", "0x00000000000000000000000000000000000000a1", "ZKS");
+    let (status, _) = app
+        .send(
+            Method::POST,
+            "/api/v1/admin/token",
+            Some(ADMIN_TOKEN),
+            Some(serde_json::to_value(&admin_token).unwrap()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    for token in [&stale, &retired] {
+        app.storage.insert_token(token, false).await.unwrap();
+    }
+    // referenced by a disabled vote token on a disabled chain
+    let references = BTreeMap::from([("arb".to_string(), retired.id.clone())]);
+    app.storage
+        .insert_vote_token("zks_v1", &references)
+        .await
+        .unwrap();
+    app.storage
+        .set_vote_token_disabled("zks_v1", true)
+        .await
+        .unwrap();
+    app.storage.set_chain_disabled("arb", true).await.unwrap();
+
+    let report = token_sync::sync_catalog(&app.storage, &app.mock.debank(VALID_KEY))
+        .await
+        .unwrap();
+    assert_eq!(report.chains_inserted, 1);
+    for (token, kept) in [
+        (&admin_token, true),
+        (&retired, true),
+        (&stale, false),
+        (&token("eth", ZKS_ETH, "ZKS"), true),
+    ] {
+        let exists = app.storage.token_exists(&token.chain, &token.id).await;
+        assert_eq!(exists.unwrap(), kept, "{}", token.id);
+    }
+    // the new chain waits for an admin to enable it
+    assert!(app.storage.chain_exists("matic").await.unwrap());
+    let supported = app.storage.load_support_chain_ids().await.unwrap();
+    assert!(!supported.contains(&"matic".to_string()));
+}
+
+#[tokio::test]
+async fn test_user() {
+    let app = TestApp::start().await;
+    // the stored activation time is answered without etherscan
+    let (status, info) = app.get(&format!("/api/v1/user/?id={}", DEBANK_USER)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(info["activation_time"], DEBANK_USER_ACTIVATION);
+    assert!(app.mock.requests().is_empty());
+    // fetched once then stored
+    for _ in 0..2 {
+        let (_, info) = app
+            .get(&format!("/api/v1/user/?id={}", ETHERSCAN_USER))
+            .await;
+        assert_eq!(info["activation_time"], 1506669224);
+    }
+    assert_eq!(app.mock.requests().len(), 1);
+
+    let (status, balance) = app
+        .get(&format!("/api/v1/user/total_balance?id={}", DEBANK_USER))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(balance["total_usd_value"], 1542.5);
+    let (status, err) = app
+        .get(&format!("/api/v1/user/total_balance?id={}", ETHERSCAN_USER))
+        .await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(err["status"], 500);
+
+    let (status, token) = app
+        .get(&format!(
+            "/api/v1/user/token?id={}&chain_id=eth&token_id={}",
+            DEBANK_USER, ZKS_ETH
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(token["symbol"], "ZKS");
+
+    let (_, amount) = app
+        .get(&format!(
+            "/api/v1/user/vote_token_amount?id={}&token_name=zks",
+            DEBANK_USER
+        ))
+        .await;
+    assert_eq!(amount["amount"], 123456789.1234568);
+    let (status, _) = app
+        .get(&format!(
+            "/api/v1/user/vote_token_amount?id={}&token_name=unknown",
+            DEBANK_USER
+        ))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app
+        .get(&format!(
+            "/api/v1/user/vote_token_amount/stream?id={}&token_name=unknown",
+            DEBANK_USER
+        ))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, body) = app
+        .text(&format!(
+            "/api/v1/user/total_balance/stream?id={}",
+            DEBANK_USER
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let parts = events(&body);
+    assert_eq!(parts.len(), 4);
+    assert_eq!(parts[3].0, "summary");
+    assert_eq!(parts[3].1["total_usd_value"], 1542.5);
+    let (_, body) = app
+        .text(&format!(
+            "/api/v1/user/vote_token_amount/stream?id={}&token_name=zks",
+            DEBANK_USER
+        ))
+        .await;
+    let (name, summary) = events(&body).pop().unwrap();
+    assert_eq!(name, "summary");
+    assert_eq!(summary["completed"], 2);
+
+    // bought 2 eth at 1000, worth 1300 now
+    let (status, pnl) = app
+        .get(&format!("/api/v1/user/pnl?id={}", DEBANK_USER))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(pnl[0]["token_id"], "eth");
+    assert_eq!(pnl[0]["unrealized_pnl"], 600.0);
+
+    let (status, _) = app.get("/api/v1/user/session").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_account_info_storage_error() {
+    let app = TestApp::start().await;
+    app.db
+        .execute(
+            "CREATE TRIGGER user_read_only BEFORE INSERT ON user
+            BEGIN SELECT RAISE(ABORT, 'read only'); END",
+        )
+        .await
+        .unwrap();
+    let (status, err) = app
+        .get(&format!("/api/v1/user/?id={}", ETHERSCAN_USER))
+        .await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(err["message"].as_str().unwrap().contains("read only"));
+    // the server is still up
+    let (status, _) = app.get(&format!("/api/v1/user/?id={}", DEBANK_USER)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_pnl_ingests_transfers() {
+    let app = TestApp::start().await;
+    app.storage
+        .save_token_prices(&[TokenPrice {
+            chain: "eth".into(),
+            token_id: ZKS_ETH.into(),
+            timestamp: 1666051200,
+            price: 0.4,
+        }])
+        .await
+        .unwrap();
+    let path = format!("/api/v1/user/pnl?id={}", ETHERSCAN_USER);
+    let (status, first) = app.get(&path).await;
+    assert_eq!(status, StatusCode::OK);
+    // received 3 zks at 0.4, sent 1 on a day without price
+    assert_eq!(first[0]["token_id"], ZKS_ETH);
+    assert_eq!(first[0]["amount"], 2.0);
+    assert_eq!(first[0]["cost_basis"], 0.8);
+    assert_eq!(first[0]["priced"], true);
+    // an airdrop debank has no price for doesn't fail the others
+    assert_eq!(first[1]["token_id"], SPAM_TOKEN);
+    assert_eq!(first[1]["amount"], 1000.0);
+    assert_eq!(first[1]["priced"], false);
+    assert_eq!(first[1]["unrealized_pnl"], 0.0);
+    // received 0.25 eth by a transaction without known price and 0.5 by a contract call at
+    // 1200, worth 1300 now
+    assert_eq!(first[2]["token_id"], "eth");
+    assert_eq!(first[2]["amount"], 0.75);
+    assert_eq!(first[2]["cost_basis"], 600.0);
+    assert_eq!(first[2]["unrealized_pnl"], 375.0);
+    let etherscan_calls = || {
+        let requests = app.mock.requests();
+        requests.iter().filter(|r| r.contains("module=")).count()
+    };
+    let calls = etherscan_calls();
+    // nothing is fetched again within the sync interval
+    let (_, second) = app.get(&path).await;
+    assert_eq!(second, first);
+    assert_eq!(etherscan_calls(), calls);
+    // the next sync starts after the fetched blocks, nothing is stored twice
+    app.db
+        .execute("UPDATE transfer_sync SET synced_at = 0")
+        .await
+        .unwrap();
+    let (_, third) = app.get(&path).await;
+    assert_eq!(third, first);
+    let stored = app
+        .storage
+        .load_token_transfers(ETHERSCAN_USER)
+        .await
+        .unwrap();
+    assert_eq!(stored.len(), 5);
+    let requests = app.mock.requests();
+    for action in ["txlist", "txlistinternal", "tokentx"] {
+        assert!(requests.iter().any(
+            |r| r.contains(&format!("action={}&", action)) && r.contains("startblock=15700101")
+        ));
+    }
+}
+
+/// Prices every day at 1, the day before yesterday fails while `down` is set.
+struct FlakyPrices {
+    down: AtomicBool,
+}
+
+#[async_trait]
+impl PriceProvider for FlakyPrices {
+    async fn current_price(&self, _: &str, _: &str) -> Result<f64, PriceError> {
+        Ok(1.0)
+    }
+
+    async fn daily_price(&self, _: &str, _: &str, timestamp: i64) -> Result<f64, PriceError> {
+        let today = Utc::now().timestamp() / SECONDS_PER_DAY * SECONDS_PER_DAY;
+        if self.down.load(Ordering::SeqCst) && timestamp == today - 2 * SECONDS_PER_DAY {
+            return Err(PriceError::Debank(DebankApiError::RateLimitExceeded));
+        }
+        Ok(1.0)
+    }
+}
+
+/// Prices every day at 1 from `listed_at`, none before.
+struct ListedPrices {
+    listed_at: i64,
+    calls: AtomicUsize,
+}
+
+#[async_trait]
+impl PriceProvider for ListedPrices {
+    async fn current_price(&self, _: &str, _: &str) -> Result<f64, PriceError> {
+        Ok(1.0)
+    }
+
+    async fn daily_price(&self, _: &str, _: &str, timestamp: i64) -> Result<f64, PriceError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        if timestamp < self.listed_at {
+            return Err(PriceError::Debank(DebankApiError::NotFound));
+        }
+        Ok(1.0)
+    }
+}
+
+#[tokio::test]
+async fn test_backfill_retries_failed_day() {
+    let app = TestApp::start().await;
+    let prices = FlakyPrices {
+        down: AtomicBool::new(true),
+    };
+    let today = Utc::now().timestamp() / SECONDS_PER_DAY * SECONDS_PER_DAY;
+    let stored = || async {
+        app.storage
+            .load_token_prices("eth", ZKS_ETH, today - 3 * SECONDS_PER_DAY, today)
+            .await
+            .unwrap()
+            .len()
+    };
+    price_backfill::backfill_prices(&app.storage, &prices, 3)
+        .await
+        .unwrap();
+    // yesterday is not stored past the failed day
+    assert_eq!(stored().await, 1);
+    prices.down.store(false, Ordering::SeqCst);
+    price_backfill::backfill_prices(&app.storage, &prices, 3)
+        .await
+        .unwrap();
+    assert_eq!(stored().await, 3);
+}
+
+#[tokio::test]
+async fn test_backfill_token_listed_in_window() {
+    let app = TestApp::start().await;
+    let today = Utc::now().timestamp() / SECONDS_PER_DAY * SECONDS_PER_DAY;
+    let prices = ListedPrices {
+        listed_at: today - 2 * SECONDS_PER_DAY,
+        calls: AtomicUsize::new(0),
+    };
+    price_backfill::backfill_prices(&app.storage, &prices, 5)
+        .await
+        .unwrap();
+    // the days before the listing are skipped, the later ones stored
+    let stored = app
+        .storage
+        .load_token_prices("eth", ZKS_ETH, today - 5 * SECONDS_PER_DAY, today)
+        .await
+        .unwrap();
+    let days: Vec<i64> = stored.iter().map(|p| p.timestamp).collect();
+    assert_eq!(days, [today - 2 * SECONDS_PER_DAY, today - SECONDS_PER_DAY]);
+    let missing = app
+        .storage
+        .load_latest_missing_price_timestamp("eth", ZKS_ETH)
+        .await
+        .unwrap();
+    assert_eq!(missing, Some(today - 3 * SECONDS_PER_DAY));
+    // the missing days are not asked again
+    let calls = prices.calls.load(Ordering::SeqCst);
+    price_backfill::backfill_prices(&app.storage, &prices, 5)
+        .await
+        .unwrap();
+    assert_eq!(prices.calls.load(Ordering::SeqCst), calls);
+}
+
+#[tokio::test]
+async fn test_inactive_account() {
+    let app = TestApp::start().await;
+    let path = format!("/api/v1/user/?id={}", INACTIVE_USER);
+    // the concurrent first requests both store the answer
+    let (first, second) = tokio::join!(app.get(&path), app.get(&path));
+    for (status, info) in [first, second] {
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(info["activation_time"], Value::Null);
+    }
+    let asked = app.mock.requests().len();
+    // answered from storage until the recheck ttl passes
+    app.get(&path).await;
+    assert_eq!(app.mock.requests().len(), asked);
+    app.db
+        .execute("UPDATE inactive_user SET checked_at = 0")
+        .await
+        .unwrap();
+    let (_, info) = app.get(&path).await;
+    assert_eq!(info["activation_time"], Value::Null);
+    assert_eq!(app.mock.requests().len(), asked + 1);
+    let checked_at = app.storage.load_inactive_user(INACTIVE_USER).await.unwrap();
+    assert!(checked_at.unwrap() > 0);
+
+    // an address found inactive earlier which transacted since
+    app.storage
+        .set_user_inactive(ETHERSCAN_USER, 0)
+        .await
+        .unwrap();
+    let activated =
+        account_refresher::refresh_inactive(&app.storage, &app.mock.etherscan(VALID_KEY), 86400)
+            .await
+            .unwrap();
+    assert_eq!(activated, 1);
+    let (_, info) = app
+        .get(&format!("/api/v1/user/?id={}", ETHERSCAN_USER))
+        .await;
+    assert_eq!(info["activation_time"], 1506669224);
+    assert_eq!(app.mock.requests().len(), asked + 2);
+    assert_eq!(
+        app.storage
+            .load_inactive_user(ETHERSCAN_USER)
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_v2_user() {
+    let app = TestApp::start().await;
+    let (status, balance) = app
+        .get(&format!(
+            "/api/v2/user/total_balance?address={}",
+            DEBANK_USER
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(balance["total_usd_value"], "1542.5");
+    let (_, token) = app
+        .get(&format!(
+            "/api/v2/user/token?address={}&chain=bsc&token_id={}",
+            DEBANK_USER, ZKS_BSC
+        ))
+        .await;
+    assert_eq!(token["amount"], "0.000000000000000001");
+    let (_, amount) = app
+        .get(&format!(
+            "/api/v2/user/vote_token_amount?address={}&token_name=zks",
+            DEBANK_USER
+        ))
+        .await;
+    assert_eq!(amount["amount"], "123456789.12345678912345679");
+    let (status, _) = app
+        .get(&format!(
+            "/api/v2/user/vote_token_amount?address={}&token_name=unknown",
+            DEBANK_USER
+        ))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app
+        .get(&format!(
+            "/api/v2/user/total_balance?address={}",
+            ETHERSCAN_USER
+        ))
+        .await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[tokio::test]
+async fn test_graphql() {
+    let app = TestApp::start().await;
+    let res = app
+        .graphql(&format!(
+            r#"{{
+                chains(limit: 2) {{ id tokens {{ symbol price }} }}
+                token(chain: "eth", id: "{}") {{ symbol chainInfo {{ name }} }}
+                user(address: "{}") {{
+                    activationTime
+                    totalBalance {{ totalUsdValue }}
+                    protocols {{ id }}
+                    voteTokenAmount(tokenName: "zks")
+                }}
+            }}"#,
+            ZKS_ETH, DEBANK_USER
+        ))
+        .await;
+    assert!(res.get("errors").is_none(), "{}", res);
+    let data = &res["data"];
+    assert_eq!(data["chains"][1]["id"], "bsc");
+    assert_eq!(data["chains"][1]["tokens"][0]["price"], "0.5");
+    assert_eq!(data["chains"][1]["tokens"][1]["price"], "270");
+    assert_eq!(data["token"]["chainInfo"]["name"], "Ethereum");
+    let user = &data["user"];
+    assert_eq!(user["activationTime"], DEBANK_USER_ACTIVATION);
+    assert_eq!(user["totalBalance"]["totalUsdValue"], "1542.5");
+    assert_eq!(user["protocols"].as_array().unwrap().len(), 3);
+    assert_eq!(user["voteTokenAmount"], "123456789.12345678912345679");
+
+    let res = app
+        .graphql(&format!(
+            r#"{{ user(address: "{}") {{ totalBalance {{ totalUsdValue }} }} }}"#,
+            ETHERSCAN_USER
+        ))
+        .await;
+    assert!(res["errors"][0]["message"].is_string());
+    let res = app
+        .graphql(&format!(
+            r#"{{ user(address: "{}") {{ voteTokenAmount(tokenName: "unknown") }} }}"#,
+            DEBANK_USER
+        ))
+        .await;
+    assert!(res.get("errors").is_none(), "{}", res);
+    assert_eq!(res["data"]["user"]["voteTokenAmount"], Value::Null);
+    let res = app
+        .graphql(&format!(
+            r#"{{ user(address: "{}") {{ activationTime }} }}"#,
+            INACTIVE_USER
+        ))
+        .await;
+    assert!(res.get("errors").is_none(), "{}", res);
+    assert_eq!(res["data"]["user"]["activationTime"], Value::Null);
+
+    let (status, sdl) = app.text("/api/v2/graphql/schema.graphql").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(sdl.contains("type Query"));
+}
+
+#[tokio::test]
+async fn test_session_scopes() {
+    let app = TestApp::start().await;
+    let key = signing_key(1);
+    let (status, _) = app.get("/api/v1/watchlist/").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // a message signed by another key
+    let (_, nonce) = app.get("/api/v1/auth/nonce").await;
+    let message = siwe_message(SIWE_DOMAIN, &key, nonce["nonce"].as_str().unwrap());
+    let body = json!({ "message": message, "signature": sign(&signing_key(2), &message) });
+    let (status, _) = app
+        .send(Method::POST, "/api/v1/auth/login", None, Some(body))
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    // signed for the host of the request instead of the configured domain
+    let host = app.url.trim_start_matches("http://");
+    let message = siwe_message(host, &key, nonce["nonce"].as_str().unwrap());
+    let body = json!({ "message": message, "signature": sign(&key, &message) });
+    let (status, _) = app
+        .send(Method::POST, "/api/v1/auth/login", None, Some(body))
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    // issued further in the future than the clock skew allowed
+    let issued_at = Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string();
+    let future = Utc
+        .timestamp_opt(Utc::now().timestamp() + 600, 0)
+        .unwrap()
+        .format("%Y-%m-%dT%H:%M:%SZ")
+        .to_string();
+    let message = siwe_message(SIWE_DOMAIN, &key, nonce["nonce"].as_str().unwrap()).replace(
+        &format!("Issued At: {}", issued_at),
+        &format!("Issued At: {}", future),
+    );
+    assert!(message.contains(&future));
+    let body = json!({ "message": message, "signature": sign(&key, &message) });
+    let (status, _) = app
+        .send(Method::POST, "/api/v1/auth/login", None, Some(body))
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let session = app.login(&key).await;
+    let token = Some(session.as_str());
+    let (status, info) = app
+        .send(Method::GET, "/api/v1/user/session", token, None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        info["address"],
+        "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf"
+    );
+
+    let (status, list) = app
+        .send(
+            Method::POST,
+            "/api/v1/watchlist/",
+            token,
+            Some(json!({ "name": "whales" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", list);
+    let id = list["id"].as_i64().unwrap();
+    let entry = json!({ "address": DEBANK_USER, "label": "debank" });
+    let (status, _) = app
+        .send(
+            Method::POST,
+            &format!("/api/v1/watchlist/{}/entry", id),
+            token,
+            Some(entry.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app
+        .send(
+            Method::POST,
+            &format!("/api/v1/watchlist/{}/entry", id),
+            token,
+            Some(entry),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = app
+        .send(
+            Method::PUT,
+            &format!("/api/v1/watchlist/{}/entry/{}", id, DEBANK_USER),
+            token,
+            Some(json!({ "label": "whale", "note": null })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, detail) = app
+        .send(
+            Method::GET,
+            &format!("/api/v1/watchlist/{}", id),
+            token,
+            None,
+        )
+        .await;
+    assert_eq!(detail["entries"][0]["label"], "whale");
+    let (status, overview) = app
+        .send(
+            Method::GET,
+            &format!("/api/v1/watchlist/{}/overview", id),
+            token,
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(overview["total_usd_value"], 1542.5);
+    let resp = app
+        .request(
+            Method::GET,
+            &format!("/api/v1/watchlist/{}/overview/stream", id),
+        )
+        .bearer_auth(&session)
+        .send()
+        .await
+        .unwrap();
+    let (name, _) = events(&resp.text().await.unwrap()).pop().unwrap();
+    assert_eq!(name, "summary");
+    let (status, _) = app
+        .send(
+            Method::PUT,
+            &format!("/api/v1/watchlist/{}", id),
+            token,
+            Some(json!({ "name": "friends" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, lists) = app
+        .send(Method::GET, "/api/v1/watchlist/", token, None)
+        .await;
+    assert_eq!(lists[0]["name"], "friends");
+    // the watchlists of other users are not found
+    let other = app.login(&signing_key(3)).await;
+    let (status, _) = app
+        .send(
+            Method::GET,
+            &format!("/api/v1/watchlist/{}", id),
+            Some(&other),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app
+        .send(
+            Method::DELETE,
+            &format!("/api/v1/watchlist/{}/entry/{}", id, DEBANK_USER),
+            token,
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app
+        .send(
+            Method::DELETE,
+            &format!("/api/v1/watchlist/{}", id),
+            token,
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let rule = json!({
+        "address": DEBANK_USER,
+        "kind": "total_usd",
+        "threshold": 10.0,
+        "webhook_url": app.mock.webhook_url(),
+    });
+    let (status, created) = app
+        .send(Method::POST, "/api/v1/alert/", token, Some(rule))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", created);
+    assert!(created["secret"].is_string());
+    let id = created["id"].as_i64().unwrap();
+    for webhook_url in [
+        "http://169.254.169.254/latest/meta-data",
+        "http://localhost:8080/admin",
+        "http://10.0.0.1/hook",
+    ] {
+        let internal = json!({
+            "address": DEBANK_USER,
+            "kind": "total_usd",
+            "threshold": 10.0,
+            "webhook_url": webhook_url,
+        });
+        let (status, _) = app
+            .send(Method::POST, "/api/v1/alert/", token, Some(internal))
+            .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", webhook_url);
+    }
+    let invalid = json!({
+        "address": DEBANK_USER,
+        "kind": "token_amount",
+        "threshold": 10.0,
+        "webhook_url": app.mock.webhook_url(),
+    });
+    let (status, _) = app
+        .send(Method::POST, "/api/v1/alert/", token, Some(invalid))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let update = json!({
+        "threshold": 20.0,
+        "threshold_mode": "percent",
+        "webhook_url": app.mock.webhook_url(),
+        "enabled": true,
+    });
+    let (status, _) = app
+        .send(
+            Method::PUT,
+            &format!("/api/v1/alert/{}", id),
+            token,
+            Some(update),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, rule) = app
+        .send(Method::GET, &format!("/api/v1/alert/{}", id), token, None)
+        .await;
+    assert_eq!(rule["threshold_mode"], "percent");
+    let (status, delivery) = app
+        .send(
+            Method::POST,
+            &format!("/api/v1/alert/{}/test", id),
+            token,
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(delivery["delivered"], true);
+    assert!(app.mock.requests().contains(&"/webhook".to_string()));
+    let (_, deliveries) = app
+        .send(
+            Method::GET,
+            &format!("/api/v1/alert/{}/deliveries", id),
+            token,
+            None,
+        )
+        .await;
+    assert_eq!(deliveries.as_array().unwrap().len(), 1);
+    let (_, rules) = app.send(Method::GET, "/api/v1/alert/", token, None).await;
+    assert_eq!(rules.as_array().unwrap().len(), 1);
+    let (status, _) = app
+        .send(
+            Method::DELETE,
+            &format!("/api/v1/alert/{}", id),
+            token,
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = app
+        .send(Method::POST, "/api/v1/auth/logout", token, None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app
+        .send(Method::GET, "/api/v1/user/session", token, None)
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_distribution_exact_amounts() {
+    let app = TestApp::start().await;
+    // more digits than a float keeps
+    let entries = [VoteSnapshotEntry {
+        rank: 1,
+        address: DEBANK_USER.into(),
+        amount: "1234567890.123456789012345678".parse().unwrap(),
+    }];
+    let snapshot_id = app
+        .storage
+        .save_vote_snapshot("zks", 1666137600, &entries)
+        .await
+        .unwrap();
+    let id = distribution::create_from_snapshot(&app.storage, snapshot_id, 18)
+        .await
+        .unwrap();
+    let tree = distribution::load(&app.storage, id).await.unwrap();
+    let claim = tree.claims.values().next().unwrap();
+    assert_eq!(
+        claim.amount,
+        format!("{:#x}", 1234567890123456789012345678u128)
+    );
+}
+
+#[tokio::test]
+async fn test_leaderboard_skips_failed_addresses() {
+    let app = TestApp::start().await;
+    let ass_api = app.mock.debank(VALID_KEY);
+    // debank knows nothing of the etherscan user and answers 404
+    for address in [DEBANK_USER, ETHERSCAN_USER] {
+        sqlx::query("INSERT INTO vote_address (vote_token, address) VALUES ('zks', ?)")
+            .bind(address)
+            .execute(&app.db)
+            .await
+            .unwrap();
+    }
+    let id = vote_leaderboard::compute_leaderboard(&app.storage, &ass_api, "zks")
+        .await
+        .unwrap();
+    let entries = app
+        .storage
+        .load_vote_snapshot_entries(id, 10, 0)
+        .await
+        .unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].address, DEBANK_USER);
+
+    // nothing fetched, the previous snapshot stays the latest
+    sqlx::query("DELETE FROM vote_address WHERE address = ?")
+        .bind(DEBANK_USER)
+        .execute(&app.db)
+        .await
+        .unwrap();
+    assert!(
+        vote_leaderboard::compute_leaderboard(&app.storage, &ass_api, "zks")
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_vote_and_distribution() {
+    let app = TestApp::start().await;
+    let (status, board) = app.get("/api/v1/vote/zks/leaderboard?page_size=1").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(board["total"], 2);
+    assert_eq!(board["entries"][0]["address"], DEBANK_USER);
+    // the exact amount is read back as a decimal string
+    assert_eq!(board["entries"][0]["amount"], "1500.5");
+    let (status, _) = app.get("/api/v1/vote/unknown/leaderboard").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app.get("/api/v1/vote/unknown/export").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app
+        .get(&format!(
+            "/api/v1/vote/zks/leaderboard?page={}&page_size=500",
+            i64::MAX
+        ))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let resp = app
+        .request(Method::GET, "/api/v1/vote/zks/export?format=csv")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.headers()["content-type"], "text/csv");
+    let csv = resp.text().await.unwrap();
+    assert_eq!(csv.lines().count(), 3);
+    let (_, entries) = app.get("/api/v1/vote/zks/export").await;
+    assert_eq!(entries[1]["address"], ETHERSCAN_USER);
+    // no rpc is configured for the home chain
+    let (status, _) = app
+        .get(&format!(
+            "/api/v1/vote/power?token_name=zks&address={}",
+            DEBANK_USER
+        ))
+        .await;
+    assert!(!status.is_success());
+    let (status, _) = app
+        .get(&format!(
+            "/api/v1/vote/power?token_name=zks&address={}&snapshot=yesterday",
+            DEBANK_USER
+        ))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = app
+        .get(&format!(
+            "/api/v1/vote/power?token_name=unknown&address={}",
+            DEBANK_USER
+        ))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, info) = app.get("/api/v1/distribution/1").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(info["decimals"], 18);
+    let (status, proof) = app
+        .get(&format!(
+            "/api/v1/distribution/1/proof?address={}",
+            DEBANK_USER
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(proof["merkle_root"], info["merkle_root"]);
+    assert_eq!(proof["amount"], "0x51579ee002a1a20000");
+    let (status, _) = app
+        .get("/api/v1/distribution/1/proof?address=0x7e5f4552091a69125d5dfcb7b8c2659029395bdf")
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app.get("/api/v1/distribution/2").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_admin() {
+    let app = TestApp::start().await;
+    let admin = Some(ADMIN_TOKEN);
+    let optimism = json!({ "id": "op", "community_id": 10, "name": "Optimism",
+        "native_token_id": "op", "logo_url": "" });
+    let (status, _) = app
+        .send(
+            Method::POST,
+            "/api/v1/admin/chain",
+            None,
+            Some(optimism.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = app
+        .send(
+            Method::POST,
+            "/api/v1/admin/chain",
+            Some("wrong"),
+            Some(optimism.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = app
+        .send(
+            Method::POST,
+            "/api/v1/admin/chain",
+            admin,
+            Some(optimism.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app
+        .send(
+            Method::POST,
+            "/api/v1/admin/chain",
+            admin,
+            Some(optimism.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let mut renamed = optimism.clone();
+    renamed["name"] = json!("OP Mainnet");
+    let (status, _) = app
+        .send(Method::PUT, "/api/v1/admin/chain/op", admin, Some(renamed))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app
+        .send(
+            Method::PUT,
+            "/api/v1/admin/chain/eth",
+            admin,
+            Some(optimism),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (_, chain) = app.get("/api/v1/chain/op").await;
+    assert_eq!(chain["name"], "OP Mainnet");
+    let (status, _) = app
+        .send(
+            Method::PUT,
+            "/api/v1/admin/chain/arb/disabled",
+            admin,
+            Some(json!({ "disabled": true })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.get("/api/v2/chain/arb").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    // the disabled chain is not asked for anymore
+    let (_, body) = app
+        .text(&format!(
+            "/api/v1/user/total_balance/stream?id={}",
+            DEBANK_USER
+        ))
+        .await;
+    assert_eq!(events(&body).len(), 4);
+
+    let op = json!({ "id": "op", "chain": "op", "name": "Optimism", "symbol": "OP",
+        "decimals": 18, "logo_url": "", "protocol_id": "", "is_core": true });
+    let (status, _) = app
+        .send(Method::POST, "/api/v1/admin/token", admin, Some(op.clone()))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let mut unknown_chain = op.clone();
+    unknown_chain["chain"] = json!("sol");
+    let (status, _) = app
+        .send(
+            Method::POST,
+            "/api/v1/admin/token",
+            admin,
+            Some(unknown_chain),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let mut updated = op;
+    updated["is_core"] = json!(false);
+    let (status, _) = app
+        .send(
+            Method::PUT,
+            "/api/v1/admin/token/op/op",
+            admin,
+            Some(updated),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, token) = app.get("/api/v1/token/op/op").await;
+    assert_eq!(token["is_core"], false);
+    let (status, _) = app
+        .send(
+            Method::PUT,
+            "/api/v1/admin/token/op/op/disabled",
+            admin,
+            Some(json!({ "disabled": true })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.get("/api/v1/token/op/op").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let mapping = json!({ "id": "op", "tokens": { "op": "op" } });
+    let (status, _) = app
+        .send(
+            Method::POST,
+            "/api/v1/admin/vote_token",
+            admin,
+            Some(mapping),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let mapping = json!({ "id": "zks", "tokens": { "eth": ZKS_ETH } });
+    let (status, _) = app
+        .send(
+            Method::PUT,
+            "/api/v1/admin/vote_token/zks",
+            admin,
+            Some(mapping),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, amount) = app
+        .get(&format!(
+            "/api/v2/user/vote_token_amount?address={}&token_name=zks",
+            DEBANK_USER
+        ))
+        .await;
+    assert_eq!(amount["amount"], "123456789.123456789123456789");
+    let (status, _) = app
+        .send(
+            Method::PUT,
+            "/api/v1/admin/vote_token/zks/disabled",
+            admin,
+            Some(json!({ "disabled": true })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let mapping = json!({ "id": "zks", "tokens": { "sol": "zks" } });
+    let (status, _) = app
+        .send(
+            Method::PUT,
+            "/api/v1/admin/vote_token/zks",
+            admin,
+            Some(mapping),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, issued) = app
+        .send(
+            Method::POST,
+            "/api/v1/admin/api_key",
+            admin,
+            Some(json!({ "name": "partner", "daily_quota": 1 })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let key = issued["key"].as_str().unwrap();
+    let id = issued["id"].as_i64().unwrap();
+    let partner = |key: &str| {
+        app.http
+            .get(format!("{}/api/v2/chain/list", app.url))
+            .header(API_KEY_HEADER, key)
+            .send()
+    };
+    assert_eq!(partner(key).await.unwrap().status(), StatusCode::OK);
+    assert_eq!(
+        partner(key).await.unwrap().status(),
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    // the usage is recorded off the request path
+    let mut usage = Value::Null;
+    for _ in 0..50 {
+        usage = app
+            .send(
+                Method::GET,
+                &format!("/api/v1/admin/api_key/{}/usage", id),
+                admin,
+                None,
+            )
+            .await
+            .1;
+        if usage[0].is_object() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(usage[0]["endpoint"], "/api/v2/chain/list");
+    assert_eq!(usage[0]["requests"], 1, "{}", usage);
+    let (_, clients) = app
+        .send(Method::GET, "/api/v1/admin/api_key", admin, None)
+        .await;
+    assert_eq!(clients.as_array().unwrap().len(), 2);
+    let (status, revoked) = app
+        .send(
+            Method::DELETE,
+            &format!("/api/v1/admin/api_key/{}", id),
+            admin,
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(revoked["revoked_at"].is_i64());
+    assert_eq!(
+        partner(key).await.unwrap().status(),
+        StatusCode::UNAUTHORIZED
+    );
+    let (status, _) = app
+        .send(Method::DELETE, "/api/v1/admin/api_key/99", admin, None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, audit) = app
+        .send(Method::GET, "/api/v1/admin/audit?limit=100", admin, None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let actions: Vec<&str> = audit
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["action"].as_str().unwrap())
+        .collect();
+    assert!(actions.contains(&"create_chain"));
+    assert!(actions.contains(&"revoke_api_key"));
+    assert!(audit
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|e| e["actor"] == "root"));
+}
+
+#[tokio::test]
+async fn test_ws_upgrade() {
+    let app = TestApp::start().await;
+    let resp = app
+        .request(Method::GET, "/api/v1/ws/")
+        .header("connection", "upgrade")
+        .header("upgrade", "websocket")
+        .header("sec-websocket-version", "13")
+        .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::SWITCHING_PROTOCOLS);
+    let (status, _) = app.get("/api/v1/ws/").await;
+    assert!(status.is_client_error());
+
+    // the browsers give the key in the query, it is decoded
+    let resp = app
+        .http
+        .get(format!("{}/api/v1/ws/?api_key=zp%5Fe2e", app.url))
+        .header("connection", "upgrade")
+        .header("upgrade", "websocket")
+        .header("sec-websocket-version", "13")
+        .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::SWITCHING_PROTOCOLS);
+    // only the websocket takes it
+    let resp = app
+        .http
+        .get(format!("{}/api/v1/chain/list?api_key={}", app.url, API_KEY))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
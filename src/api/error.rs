@@ -1,14 +1,26 @@
-use serde_json;
+use serde::Serialize;
 use thiserror::Error;
+use utoipa::ToSchema;
 
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
+    Json,
 };
 
+use crate::account::AccountError;
+use crate::alert::AlertError;
+use crate::distribution::DistributionError;
+use crate::fx::FxError;
+use crate::price::PriceError;
+use crate::siwe::SiweError;
 use crate::storage::StorageError;
+use crate::transfer::TransferError;
+use crate::vote::VoteError;
 use crate::{debank::openapi::DebankApiError, etherscan::EtherscanApiError};
 
+// the upstream variants keep the names they were deployed with
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Error)]
 pub enum ApiError {
     #[error(transparent)]
@@ -18,12 +30,74 @@ pub enum ApiError {
     #[error(transparent)]
     AccountApiError(#[from] EtherscanApiError),
     #[error(transparent)]
+    Account(#[from] AccountError),
+    #[error(transparent)]
+    Price(#[from] PriceError),
+    #[error(transparent)]
+    Fx(#[from] FxError),
+    #[error(transparent)]
+    Vote(#[from] VoteError),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error(transparent)]
+    Distribution(#[from] DistributionError),
+    #[error(transparent)]
+    Alert(#[from] AlertError),
+    #[error(transparent)]
+    Transfer(#[from] TransferError),
+    #[error(transparent)]
+    Siwe(#[from] SiweError),
+    #[error(transparent)]
     SerdeJson(#[from] serde_json::error::Error),
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    #[error("Unauthorized")]
+    Unauthorized,
+    #[error("Too many requests: {0}")]
+    TooManyRequests(String),
+}
+
+/// Body of every error response.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    /// http status code
+    pub status: u16,
+    pub message: String,
+}
+
+impl ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound(_)
+            | ApiError::Distribution(DistributionError::NotFound(_))
+            | ApiError::Vote(VoteError::UnknownToken(_)) => StatusCode::NOT_FOUND,
+            ApiError::BadRequest(_)
+            | ApiError::StorageError(StorageError::InvalidIdentifier(_))
+            | ApiError::Fx(FxError::UnsupportedCurrency(_))
+            | ApiError::Alert(AlertError::InvalidWebhook(_))
+            | ApiError::Vote(VoteError::InvalidSnapshot(_) | VoteError::UnknownKind(_)) => {
+                StatusCode::BAD_REQUEST
+            }
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Siwe(SiweError::InvalidMessage(_)) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized | ApiError::Siwe(_) => StatusCode::UNAUTHORIZED,
+            ApiError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
-        let body = format!("error: {:?}", self);
-        (StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
+        let status = self.status_code();
+        let body = ErrorBody {
+            status: status.as_u16(),
+            message: self.to_string(),
+        };
+        (status, Json(body)).into_response()
     }
 }
@@ -0,0 +1,63 @@
+use axum::http::header::{self, HeaderMap, HeaderValue};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use sha3::{Digest, Keccak256};
+
+use super::error::ApiError;
+
+/// Serializes the value into a json response tagged with an `ETag` of its content, an empty
+/// `304 Not Modified` is returned when the client already holds the same content.
+pub fn etag_json<T: Serialize>(
+    request_headers: &HeaderMap,
+    value: &T,
+) -> Result<Response, ApiError> {
+    let body = serde_json::to_vec(value)?;
+    let digest = Keccak256::digest(&body);
+    let etag = format!("\"{}\"", hex::encode(&digest[..16]));
+    let etag_value = HeaderValue::from_str(&etag).expect("hex is a valid header value");
+
+    let not_modified = request_headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|v| v.trim().trim_start_matches("W/"))
+        .any(|v| v == etag || v == "*");
+    if not_modified {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag_value)]).into_response());
+    }
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            ),
+            (header::ETAG, etag_value),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_etag_json() {
+        let value = vec!["eth", "bsc"];
+        let resp = etag_json(&HeaderMap::new(), &value).unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let etag = resp.headers()[header::ETAG].clone();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, etag.clone());
+        let resp = etag_json(&headers, &value).unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(resp.headers()[header::ETAG], etag);
+
+        let resp = etag_json(&headers, &vec!["eth"]).unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
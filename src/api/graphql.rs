@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+
+use async_graphql::dataloader::{DataLoader, Loader};
+use async_graphql::{
+    ComplexObject, Context, EmptyMutation, EmptySubscription, Object, Schema, SchemaBuilder,
+};
+use axum::extract::State;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+
+use crate::account;
+use crate::debank::convert;
+use crate::debank::openapi::{DebankApiError, DebankOpenAPI};
+use crate::etherscan::EtherscanAPi;
+use crate::fx::{CachedFxProvider, FiatValue};
+use crate::portfolio::{decimal_string, Balance, Chain, Protocol, Token, TokenBalance};
+use crate::storage::token::TokenFilter;
+use crate::storage::{StorageError, StorageProcessor};
+
+use super::{Services, SupportChains, MAX_PAGE_SIZE};
+
+pub type PortfolioSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+/// Complexity of a field calling a provider, a plain field costs 1.
+const UPSTREAM_COMPLEXITY: usize = 10;
+/// Items assumed in the lists a provider returns whole, like the tokens of an address.
+const UPSTREAM_LIST_SIZE: usize = 20;
+/// Page of the catalog lists without `limit`.
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const DEFAULT_MAX_COMPLEXITY: usize = 2000;
+const MAX_DEPTH: usize = 8;
+/// Ids debank accepts in one `token/list_by_ids` call.
+const PRICE_BATCH_SIZE: usize = 100;
+
+fn page_size(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+}
+
+/// What the resolvers share, the loaders are stored next to it.
+struct GraphqlData {
+    storage: StorageProcessor,
+    support_chains: SupportChains,
+    ass_api: DebankOpenAPI,
+    acc_api: EtherscanAPi,
+    fx: Arc<CachedFxProvider>,
+    account_recheck_secs: i64,
+}
+
+impl GraphqlData {
+    async fn fiat<T: FiatValue>(
+        &self,
+        currency: Option<&str>,
+        mut value: T,
+    ) -> async_graphql::Result<T> {
+        let rate = self.fx.rate(currency).await?;
+        value.convert(rate.rate);
+        Ok(value)
+    }
+}
+
+/// Batches the current prices the query asks for into one debank call per chain.
+struct PriceLoader {
+    ass_api: DebankOpenAPI,
+}
+
+impl Loader<(String, String)> for PriceLoader {
+    type Value = f64;
+    type Error = Arc<DebankApiError>;
+
+    async fn load(
+        &self,
+        keys: &[(String, String)],
+    ) -> Result<HashMap<(String, String), f64>, Self::Error> {
+        let mut by_chain: HashMap<&str, Vec<String>> = HashMap::new();
+        for (chain, id) in keys {
+            by_chain.entry(chain).or_default().push(id.clone());
+        }
+        let mut prices = HashMap::new();
+        for (chain, ids) in by_chain {
+            for ids in ids.chunks(PRICE_BATCH_SIZE) {
+                let tokens = self
+                    .ass_api
+                    .token_list_by_ids(chain, ids)
+                    .await
+                    .map_err(Arc::new)?;
+                for token in tokens {
+                    prices.insert((chain.to_string(), token.id), token.price);
+                }
+            }
+        }
+        Ok(prices)
+    }
+}
+
+/// Resolves the chains of the tokens with a single query however many tokens are listed.
+struct ChainLoader {
+    storage: StorageProcessor,
+}
+
+impl Loader<String> for ChainLoader {
+    type Value = Chain;
+    type Error = Arc<StorageError>;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Chain>, Self::Error> {
+        let chains = self.storage.load_chains().await.map_err(Arc::new)?;
+        Ok(chains
+            .into_iter()
+            .filter(|chain| keys.contains(&chain.id))
+            .map(|chain| (chain.id.clone(), chain.into()))
+            .collect())
+    }
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    async fn chain(&self, ctx: &Context<'_>, id: String) -> async_graphql::Result<Option<Chain>> {
+        let data = ctx.data_unchecked::<GraphqlData>();
+        Ok(data.storage.load_chain(&id).await?.map(Chain::from))
+    }
+
+    /// chains matching the prefix `q` of their id or name
+    #[graphql(complexity = "page_size(limit) as usize * child_complexity")]
+    async fn chains(
+        &self,
+        ctx: &Context<'_>,
+        q: Option<String>,
+        limit: Option<i64>,
+        #[graphql(default)] offset: i64,
+    ) -> async_graphql::Result<Vec<Chain>> {
+        let data = ctx.data_unchecked::<GraphqlData>();
+        let chains = data
+            .storage
+            .load_chains_filtered(q.as_deref(), Some(page_size(limit)), offset.max(0))
+            .await?;
+        Ok(chains.into_iter().map(Chain::from).collect())
+    }
+
+    async fn token(
+        &self,
+        ctx: &Context<'_>,
+        chain: String,
+        id: String,
+    ) -> async_graphql::Result<Option<Token>> {
+        let data = ctx.data_unchecked::<GraphqlData>();
+        Ok(data.storage.load_token(&chain, &id).await?.map(Token::from))
+    }
+
+    /// the fields of the address are only fetched when selected
+    async fn user(&self, address: String) -> User {
+        User {
+            address: address.to_lowercase(),
+        }
+    }
+}
+
+#[ComplexObject]
+impl Chain {
+    #[graphql(complexity = "page_size(limit) as usize * child_complexity")]
+    async fn tokens(
+        &self,
+        ctx: &Context<'_>,
+        is_core: Option<bool>,
+        limit: Option<i64>,
+        #[graphql(default)] offset: i64,
+    ) -> async_graphql::Result<Vec<Token>> {
+        let data = ctx.data_unchecked::<GraphqlData>();
+        let filter = TokenFilter {
+            chain: Some(self.id.clone()),
+            is_core,
+            protocol_id: None,
+            search: None,
+        };
+        let tokens = data
+            .storage
+            .load_tokens_filtered(&filter, Some(page_size(limit)), offset.max(0))
+            .await?;
+        Ok(tokens.into_iter().map(Token::from).collect())
+    }
+}
+
+#[ComplexObject]
+impl Token {
+    /// current price, null when debank does not price the token
+    #[graphql(complexity = "UPSTREAM_COMPLEXITY")]
+    async fn price(
+        &self,
+        ctx: &Context<'_>,
+        currency: Option<String>,
+    ) -> async_graphql::Result<Option<String>> {
+        let data = ctx.data_unchecked::<GraphqlData>();
+        let rate = data.fx.rate(currency.as_deref()).await?;
+        let price = ctx
+            .data_unchecked::<DataLoader<PriceLoader>>()
+            .load_one((self.chain.clone(), self.id.clone()))
+            .await?;
+        Ok(price.map(|price| decimal_string(price * rate.rate)))
+    }
+
+    async fn chain_info(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Chain>> {
+        Ok(ctx
+            .data_unchecked::<DataLoader<ChainLoader>>()
+            .load_one(self.chain.clone())
+            .await?)
+    }
+}
+
+pub struct User {
+    address: String,
+}
+
+#[Object]
+impl User {
+    async fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// unix seconds of the first transaction, null for an address which never sent one
+    #[graphql(complexity = "UPSTREAM_COMPLEXITY")]
+    async fn activation_time(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<i64>> {
+        let data = ctx.data_unchecked::<GraphqlData>();
+        let activation_time = account::activation_time(
+            &data.storage,
+            &data.acc_api,
+            &self.address,
+            data.account_recheck_secs,
+        )
+        .await?;
+        Ok(activation_time)
+    }
+
+    /// value held over the supported chains
+    #[graphql(complexity = "UPSTREAM_COMPLEXITY + child_complexity")]
+    async fn total_balance(
+        &self,
+        ctx: &Context<'_>,
+        currency: Option<String>,
+    ) -> async_graphql::Result<Balance> {
+        let data = ctx.data_unchecked::<GraphqlData>();
+        let balance = data
+            .ass_api
+            .muti_chain_balance(&self.address, &data.support_chains.get())
+            .await?;
+        let balance = data.fiat(currency.as_deref(), balance).await?;
+        Ok(convert::balance(&self.address, balance))
+    }
+
+    /// core tokens held on the supported chains
+    #[graphql(complexity = "UPSTREAM_COMPLEXITY + UPSTREAM_LIST_SIZE * child_complexity")]
+    async fn tokens(
+        &self,
+        ctx: &Context<'_>,
+        currency: Option<String>,
+    ) -> async_graphql::Result<Vec<TokenBalance>> {
+        let data = ctx.data_unchecked::<GraphqlData>();
+        let tokens = data
+            .ass_api
+            .all_token_list(&self.address, &data.support_chains.get())
+            .await?;
+        let tokens = data.fiat(currency.as_deref(), tokens).await?;
+        Ok(tokens.into_iter().map(convert::token_balance).collect())
+    }
+
+    /// positions in the protocols of the supported chains
+    #[graphql(complexity = "UPSTREAM_COMPLEXITY + UPSTREAM_LIST_SIZE * child_complexity")]
+    async fn protocols(
+        &self,
+        ctx: &Context<'_>,
+        currency: Option<String>,
+    ) -> async_graphql::Result<Vec<Protocol>> {
+        let data = ctx.data_unchecked::<GraphqlData>();
+        let protocols = data
+            .ass_api
+            .all_simple_protocol_list(&self.address, &data.support_chains.get())
+            .await?;
+        let protocols = data.fiat(currency.as_deref(), protocols).await?;
+        Ok(protocols.into_iter().map(convert::protocol).collect())
+    }
+
+    /// exact amount of the vote token summed over its chains, null for an unknown vote token
+    #[graphql(complexity = "UPSTREAM_COMPLEXITY")]
+    async fn vote_token_amount(
+        &self,
+        ctx: &Context<'_>,
+        token_name: String,
+    ) -> async_graphql::Result<Option<String>> {
+        let data = ctx.data_unchecked::<GraphqlData>();
+        let Some(token_ids) = data.storage.load_token_ids_by_name(token_name).await? else {
+            return Ok(None);
+        };
+        let amount = data
+            .ass_api
+            .token_total_amount(&self.address, &token_ids)
+            .await?;
+        Ok(Some(amount.to_string()))
+    }
+}
+
+/// The schema without its data, with the limits checked before anything is resolved. The
+/// complexity limit is read from `GRAPHQL_MAX_COMPLEXITY`.
+fn builder() -> SchemaBuilder<Query, EmptyMutation, EmptySubscription> {
+    let max_complexity = env::var("GRAPHQL_MAX_COMPLEXITY")
+        .map(|v| v.parse().expect("GRAPHQL_MAX_COMPLEXITY must be a number"))
+        .unwrap_or(DEFAULT_MAX_COMPLEXITY);
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .limit_complexity(max_complexity)
+        .limit_depth(MAX_DEPTH)
+}
+
+async fn graphql(
+    State(schema): State<PortfolioSchema>,
+    Json(req): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    Json(schema.execute(req).await)
+}
+
+pub fn api_scope(services: &Services, support_chains: SupportChains) -> Router<PortfolioSchema> {
+    let storage = services.storage.clone();
+    let ass_api = services.ass_api.clone();
+    let schema = builder()
+        .data(DataLoader::new(
+            PriceLoader {
+                ass_api: ass_api.clone(),
+            },
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            ChainLoader {
+                storage: storage.clone(),
+            },
+            tokio::spawn,
+        ))
+        .data(GraphqlData {
+            storage,
+            support_chains,
+            ass_api,
+            acc_api: services.acc_api.clone(),
+            account_recheck_secs: services.account_recheck_secs,
+            fx: services.fx.clone(),
+        })
+        .finish();
+    Router::with_state(schema)
+        .route("/graphql", post(graphql))
+        .route(
+            "/graphql/schema.graphql",
+            get(|State(schema): State<PortfolioSchema>| async move { schema.sdl() }),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SDL_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/docs/schema.graphql");
+
+    /// Regenerate with `UPDATE_GRAPHQL=1 cargo test` after changing the schema.
+    #[test]
+    fn test_sdl_up_to_date() {
+        let sdl = builder().finish().sdl();
+        if env::var("UPDATE_GRAPHQL").is_ok() {
+            std::fs::write(SDL_PATH, &sdl).unwrap();
+        }
+        let committed = std::fs::read_to_string(SDL_PATH).unwrap_or_default();
+        assert!(
+            committed == sdl,
+            "docs/schema.graphql is outdated, run `UPDATE_GRAPHQL=1 cargo test`"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_limits() {
+        // the limits are checked before any resolver runs, no data is needed
+        let schema = builder().finish();
+        let res = schema
+            .execute("{ chains(limit: 100) { tokens(limit: 100) { price } } }")
+            .await;
+        assert_eq!(res.errors[0].message, "Query is too complex.");
+
+        let res = schema
+            .execute(
+                "{ token(chain: \"eth\", id: \"eth\") { chainInfo { tokens(limit: 1) { \
+                    chainInfo { tokens(limit: 1) { chainInfo { tokens(limit: 1) { \
+                    chainInfo { id } } } } } } } } }",
+            )
+            .await;
+        assert_eq!(res.errors[0].message, "Query is nested too deep.");
+    }
+
+    #[test]
+    fn test_page_size() {
+        assert_eq!(page_size(None), DEFAULT_PAGE_SIZE);
+        assert_eq!(page_size(Some(0)), 1);
+        assert_eq!(page_size(Some(5000)), MAX_PAGE_SIZE);
+    }
+}
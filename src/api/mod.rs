@@ -1,48 +1,490 @@
-use axum::extract::State;
-use axum::{routing::get, Json, Router};
+use std::env;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, HeaderValue, Request};
+use axum::response::Response;
+use axum::{middleware, routing::get, Json, Router};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
 use tower_http::trace::TraceLayer;
+use utoipa::{IntoParams, ToSchema};
 
-use crate::storage::{chain::ChainInfo, token::TokenInfo, StorageProcessor};
+use crate::account;
+use crate::alert::WebhookSender;
+use crate::debank::openapi::DebankOpenAPI;
+use crate::etherscan::EtherscanAPi;
+use crate::fx::{CachedFxProvider, Fiat, FiatValue};
+use crate::hub::BalanceHub;
+use crate::job::{
+    account_refresher, alert_evaluator, balance_refresher, price_backfill, token_sync,
+    vote_leaderboard,
+};
+use crate::price::{self, PriceCandle, PriceInterval, PriceProvider, SECONDS_PER_DAY};
+use crate::storage::{
+    chain::ChainInfo,
+    token::{TokenFilter, TokenInfo},
+    StorageError, StorageProcessor,
+};
 
+use self::api_key::ApiKeys;
 use self::error::ApiError;
+use self::etag::etag_json;
 
+mod admin;
+mod alert;
+mod api_key;
+mod auth;
+mod distribution;
+#[cfg(test)]
+mod e2e;
 mod error;
+mod etag;
+mod graphql;
+mod openapi;
+mod sse;
 mod user;
+mod v2;
+mod vote;
+mod watchlist;
+mod ws;
+
+/// Storage and providers shared by every scope, the tests inject a seeded database and the
+/// fixture servers.
+#[derive(Clone)]
+pub struct Services {
+    pub storage: StorageProcessor,
+    pub ass_api: DebankOpenAPI,
+    pub acc_api: EtherscanAPi,
+    pub fx: Arc<CachedFxProvider>,
+    /// `(name, token)` of the admins, configured by `ADMIN_TOKENS` as `name:token` pairs
+    /// separated by commas, every admin request is rejected without them
+    pub admin_tokens: Vec<(String, String)>,
+    /// seconds before an address found without transaction is checked again
+    pub account_recheck_secs: i64,
+    /// balances of the addresses watched over the websocket, at most `WS_MAX_ADDRESSES`
+    pub hub: BalanceHub,
+}
+
+impl Services {
+    pub async fn from_env() -> Self {
+        Self {
+            storage: StorageProcessor::new_from_pool().await,
+            ass_api: DebankOpenAPI::from_env(),
+            acc_api: EtherscanAPi::from_env(),
+            fx: Arc::new(CachedFxProvider::from_env()),
+            admin_tokens: admin::parse_admin_tokens(&env::var("ADMIN_TOKENS").unwrap_or_default()),
+            account_recheck_secs: account::recheck_secs_from_env(),
+            hub: BalanceHub::new(
+                env::var("WS_MAX_ADDRESSES")
+                    .map(|s| s.parse().expect("WS_MAX_ADDRESSES must be a number"))
+                    .unwrap_or(1000),
+            ),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct ApiV1State {
     pub storage_core: StorageProcessor,
+    pub price_provider: Arc<dyn PriceProvider>,
+    pub fx: Arc<CachedFxProvider>,
 }
 impl ApiV1State {
-    pub async fn new() -> Self {
+    pub fn new(services: &Services) -> Self {
         Self {
-            storage_core: StorageProcessor::new_from_pool().await,
+            storage_core: services.storage.clone(),
+            price_provider: Arc::new(services.ass_api.clone()),
+            fx: services.fx.clone(),
         }
     }
 }
 
-async fn chain_list(State(state): State<ApiV1State>) -> Result<Json<Vec<ChainInfo>>, ApiError> {
-    let res = state.storage_core.load_chains().await?;
-    Ok(Json(res))
+/// Ids of the enabled chains, shared between the scopes so admin changes apply right away.
+#[derive(Clone)]
+pub struct SupportChains(Arc<RwLock<Vec<String>>>);
+
+impl SupportChains {
+    pub async fn load(storage: &StorageProcessor) -> Result<Self, StorageError> {
+        let chains = storage.load_support_chain_ids().await?;
+        Ok(Self(Arc::new(RwLock::new(chains))))
+    }
+
+    pub fn get(&self) -> Vec<String> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub async fn refresh(&self, storage: &StorageProcessor) -> Result<(), StorageError> {
+        let chains = storage.load_support_chain_ids().await?;
+        *self.0.write().unwrap() = chains;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QueryPriceHistory {
+    chain_id: String,
+    token_id: String,
+    /// unix seconds, 30 days before `end` by default
+    start: Option<i64>,
+    /// unix seconds, now by default
+    end: Option<i64>,
+    /// `day` by default, the stored prices are daily closes
+    interval: Option<PriceInterval>,
+}
+
+/// Every endpoint returning usd values accepts `currency` to convert them.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QueryCurrency {
+    /// iso code the usd values are converted to, a converted value is answered as
+    /// `{"data": <value>, "fx": <FxRate>}` and the rate is echoed in the `X-Fx-*` headers
+    pub currency: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QueryChainList {
+    /// prefix of the chain id or name
+    q: Option<String>,
+    limit: Option<i64>,
+    #[serde(default)]
+    offset: i64,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QueryTokenList {
+    chain: Option<String>,
+    is_core: Option<bool>,
+    protocol_id: Option<String>,
+    /// prefix of the token symbol or name
+    q: Option<String>,
+    limit: Option<i64>,
+    #[serde(default)]
+    offset: i64,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QueryTokenDetail {
+    /// enrich the token with its current price
+    #[serde(default)]
+    with_price: bool,
 }
 
-async fn token_list(State(state): State<ApiV1State>) -> Result<Json<Vec<TokenInfo>>, ApiError> {
-    let res = state.storage_core.load_tokens().await?;
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TokenDetail {
+    #[serde(flatten)]
+    token: TokenInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    price: Option<f64>,
+}
+
+impl FiatValue for TokenDetail {
+    fn convert(&mut self, rate: f64) {
+        if let Some(price) = self.price.as_mut() {
+            *price *= rate;
+        }
+    }
+}
+
+/// Largest page of the catalog lists.
+const MAX_PAGE_SIZE: i64 = 1000;
+
+/// The catalog lists return the whole page as a json array with the number of matching items
+/// in `X-Total-Count`, without `limit` every matching item is returned.
+fn with_total_count(mut resp: Response, total: i64) -> Response {
+    resp.headers_mut()
+        .insert("x-total-count", HeaderValue::from(total));
+    resp
+}
+
+#[utoipa::path(
+    get,
+    path = "/chain/list",
+    tag = "catalog",
+    params(QueryChainList),
+    responses((status = 200, body = [ChainInfo], headers(("x-total-count" = i64))))
+)]
+async fn chain_list(
+    State(state): State<ApiV1State>,
+    headers: HeaderMap,
+    Query(info): Query<QueryChainList>,
+) -> Result<Response, ApiError> {
+    let limit = info.limit.map(|l| l.clamp(1, MAX_PAGE_SIZE));
+    let search = info.q.as_deref();
+    let total = state.storage_core.count_chains(search).await?;
+    let res: Vec<ChainInfo> = state
+        .storage_core
+        .load_chains_filtered(search, limit, info.offset.max(0))
+        .await?;
+    Ok(with_total_count(etag_json(&headers, &res)?, total))
+}
+
+#[utoipa::path(
+    get,
+    path = "/token/list",
+    tag = "catalog",
+    params(QueryTokenList),
+    responses((status = 200, body = [TokenInfo], headers(("x-total-count" = i64))))
+)]
+async fn token_list(
+    State(state): State<ApiV1State>,
+    headers: HeaderMap,
+    Query(info): Query<QueryTokenList>,
+) -> Result<Response, ApiError> {
+    let limit = info.limit.map(|l| l.clamp(1, MAX_PAGE_SIZE));
+    let filter = TokenFilter {
+        chain: info.chain,
+        is_core: info.is_core,
+        protocol_id: info.protocol_id,
+        search: info.q,
+    };
+    let total = state.storage_core.count_tokens(&filter).await?;
+    let res: Vec<TokenInfo> = state
+        .storage_core
+        .load_tokens_filtered(&filter, limit, info.offset.max(0))
+        .await?;
+    Ok(with_total_count(etag_json(&headers, &res)?, total))
+}
+
+#[utoipa::path(
+    get,
+    path = "/chain/{id}",
+    tag = "catalog",
+    params(("id" = String, Path, description = "chain id")),
+    responses((status = 200, body = ChainInfo))
+)]
+async fn chain_info(
+    State(state): State<ApiV1State>,
+    Path(id): Path<String>,
+) -> Result<Json<ChainInfo>, ApiError> {
+    let res = state
+        .storage_core
+        .load_chain(&id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("chain {}", id)))?;
     Ok(Json(res))
 }
 
-async fn api_v1_scope() -> Router<ApiV1State> {
-    Router::with_state(ApiV1State::new().await)
-        .nest("/user", user::api_scope().await)
+#[utoipa::path(
+    get,
+    path = "/token/{chain}/{id}",
+    tag = "catalog",
+    params(
+        ("chain" = String, Path, description = "chain id"),
+        ("id" = String, Path, description = "token id"),
+        QueryTokenDetail,
+        QueryCurrency
+    ),
+    responses((status = 200, body = TokenDetail))
+)]
+async fn token_info(
+    State(state): State<ApiV1State>,
+    Path((chain, id)): Path<(String, String)>,
+    Query(info): Query<QueryTokenDetail>,
+    Query(fiat): Query<QueryCurrency>,
+) -> Result<Fiat<TokenDetail>, ApiError> {
+    let rate = state.fx.rate(fiat.currency.as_deref()).await?;
+    let token = state
+        .storage_core
+        .load_token(&chain, &id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("token {} on {}", id, chain)))?;
+    let price = if info.with_price {
+        Some(state.price_provider.current_price(&chain, &id).await?)
+    } else {
+        None
+    };
+    Ok(Fiat::new(TokenDetail { token, price }, rate))
+}
+
+#[utoipa::path(
+    get,
+    path = "/token/price_history",
+    tag = "catalog",
+    params(QueryPriceHistory, QueryCurrency),
+    responses((status = 200, body = [PriceCandle]))
+)]
+async fn token_price_history(
+    State(state): State<ApiV1State>,
+    Query(info): Query<QueryPriceHistory>,
+    Query(fiat): Query<QueryCurrency>,
+) -> Result<Fiat<Vec<PriceCandle>>, ApiError> {
+    let rate = state.fx.rate(fiat.currency.as_deref()).await?;
+    let end = info.end.unwrap_or_else(|| Utc::now().timestamp());
+    let start = info.start.unwrap_or(end - 30 * SECONDS_PER_DAY);
+    let prices = state
+        .storage_core
+        .load_token_prices(&info.chain_id, &info.token_id, start, end)
+        .await?;
+    let points: Vec<(i64, f64)> = prices.iter().map(|p| (p.timestamp, p.price)).collect();
+    let candles = price::aggregate(&points, info.interval.unwrap_or(PriceInterval::Day));
+    Ok(Fiat::new(candles, rate))
+}
+
+fn api_v1_scope(
+    services: &Services,
+    support_chains: SupportChains,
+    api_keys: ApiKeys,
+) -> Router<ApiV1State> {
+    let state = ApiV1State::new(services);
+    // the key is checked by every scope itself so the usage is counted per matched route
+    let require_key = middleware::from_fn_with_state(api_keys.clone(), api_key::require_api_key);
+    Router::with_state(state)
         .route("/token/list", get(token_list))
+        .route("/token/price_history", get(token_price_history))
+        .route("/token/:chain/:id", get(token_info))
+        .route("/chain/:id", get(chain_info))
         .route("/chain/list", get(chain_list))
+        .route_layer(require_key.clone())
+        .nest(
+            "/alert",
+            alert::api_scope(services).route_layer(require_key.clone()),
+        )
+        .nest(
+            "/auth",
+            auth::api_scope(services).route_layer(require_key.clone()),
+        )
+        .nest(
+            "/user",
+            user::api_scope(services, support_chains.clone()).route_layer(require_key.clone()),
+        )
+        .nest(
+            "/vote",
+            vote::api_scope(services).route_layer(require_key.clone()),
+        )
+        .nest(
+            "/watchlist",
+            watchlist::api_scope(services, support_chains.clone()).route_layer(require_key.clone()),
+        )
+        .nest(
+            "/ws",
+            ws::api_scope(services).route_layer(middleware::from_fn_with_state(
+                api_keys.clone(),
+                api_key::require_ws_api_key,
+            )),
+        )
+        .nest(
+            "/distribution",
+            distribution::api_scope(services).route_layer(require_key),
+        )
+        .nest(
+            "/admin",
+            admin::api_scope(services, support_chains, api_keys),
+        )
+        .merge(openapi::api_scope())
         .route("/favicon", get(|| async { "Hello, World!" }))
 }
 
+/// Starts the background jobs enabled by the environment, they are kept out of the router so
+/// building it has no side effect.
+pub fn spawn_jobs(services: &Services, support_chains: SupportChains) {
+    let refresh_secs = env::var("WS_REFRESH_SECS")
+        .map(|s| s.parse().expect("WS_REFRESH_SECS must be a number"))
+        .unwrap_or(15);
+    // only the watched addresses are polled, nothing is spent without subscriber
+    balance_refresher::spawn(
+        services.hub.clone(),
+        services.ass_api.clone(),
+        support_chains,
+        Duration::from_secs(refresh_secs),
+    );
+    // daily price backfill is opt-in as it spends debank units for every token
+    if let Ok(days) = env::var("PRICE_BACKFILL_DAYS") {
+        let days = days.parse().expect("PRICE_BACKFILL_DAYS must be a number");
+        price_backfill::spawn(
+            services.storage.clone(),
+            Arc::new(services.ass_api.clone()),
+            days,
+        );
+    }
+    if let Ok(secs) = env::var("TOKEN_SYNC_INTERVAL_SECS") {
+        let secs = secs
+            .parse()
+            .expect("TOKEN_SYNC_INTERVAL_SECS must be a number");
+        token_sync::spawn(
+            services.storage.clone(),
+            services.ass_api.clone(),
+            Duration::from_secs(secs),
+        );
+    }
+    // rechecking the addresses found without transaction spends etherscan calls
+    if let Ok(secs) = env::var("ACCOUNT_REFRESH_INTERVAL_SECS") {
+        let secs = secs
+            .parse()
+            .expect("ACCOUNT_REFRESH_INTERVAL_SECS must be a number");
+        account_refresher::spawn(
+            services.storage.clone(),
+            services.acc_api.clone(),
+            services.account_recheck_secs,
+            Duration::from_secs(secs),
+        );
+    }
+    // recomputing the leaderboards spends debank units for every tracked address
+    if let Ok(secs) = env::var("VOTE_LEADERBOARD_INTERVAL_SECS") {
+        let secs = secs
+            .parse()
+            .expect("VOTE_LEADERBOARD_INTERVAL_SECS must be a number");
+        vote_leaderboard::spawn(
+            services.storage.clone(),
+            services.ass_api.clone(),
+            Duration::from_secs(secs),
+        );
+    }
+    // the evaluation spends debank units for every rule
+    if let Ok(secs) = env::var("ALERT_INTERVAL_SECS") {
+        let secs = secs.parse().expect("ALERT_INTERVAL_SECS must be a number");
+        alert_evaluator::spawn(
+            services.storage.clone(),
+            services.ass_api.clone(),
+            WebhookSender::from_env(),
+            Duration::from_secs(secs),
+        );
+    }
+}
+
+/// Every route of both versions on top of `services`.
+pub fn app(services: Services, support_chains: SupportChains) -> Router {
+    // the versions share the keys so the rate limits and the quotas apply to both
+    let api_keys = ApiKeys::new(services.storage.clone());
+    let require_key = middleware::from_fn_with_state(api_keys.clone(), api_key::require_api_key);
+    Router::new()
+        .nest(
+            "/api/v1",
+            api_v1_scope(&services, support_chains.clone(), api_keys),
+        )
+        .nest(
+            "/api/v2",
+            v2::api_scope(&services, support_chains.clone())
+                .merge(graphql::api_scope(&services, support_chains))
+                .route_layer(require_key)
+                .merge(v2::docs_scope()),
+        )
+        // the websocket key may be in the query, it is kept out of the logs
+        .layer(
+            TraceLayer::new_for_http().make_span_with(|req: &Request<Body>| {
+                tracing::debug_span!(
+                    "request",
+                    method = %req.method(),
+                    uri = %api_key::redacted_uri(req.uri()),
+                    version = ?req.version(),
+                )
+            }),
+        )
+}
+
 pub async fn start_server() {
-    let app = Router::new()
-        .nest("/api/v1", api_v1_scope().await)
-        .layer(TraceLayer::new_for_http());
+    let services = Services::from_env().await;
+    let support_chains = SupportChains::load(&services.storage)
+        .await
+        .expect("fail in db");
+    spawn_jobs(&services, support_chains.clone());
+    let app = app(services, support_chains);
 
     axum::Server::bind(&"0.0.0.0:8080".parse().unwrap())
         .serve(app.into_make_service())
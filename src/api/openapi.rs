@@ -0,0 +1,255 @@
+use axum::response::Html;
+use axum::routing::get;
+use axum::{Json, Router};
+use utoipa::openapi::security::{
+    ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityRequirement, SecurityScheme,
+};
+use utoipa::openapi::{ContentBuilder, RefOr, ResponseBuilder};
+use utoipa::{Modify, OpenApi};
+
+use crate::alert::{AlertKind, ThresholdMode};
+use crate::debank::openapi::{
+    ChainBalance, DebankChainBalance, DebankTokenBalance, DebankTotalBalance,
+};
+use crate::fx::FxRate;
+use crate::merkle::Claim;
+use crate::pnl::{CostBasisMethod, TokenPnl};
+use crate::price::{PriceCandle, PriceInterval};
+use crate::storage::alert::{AlertDelivery, AlertRule};
+use crate::storage::chain::ChainInfo;
+use crate::storage::distribution::DistributionInfo;
+use crate::storage::token::TokenInfo;
+use crate::storage::vote::{VoteSnapshot, VoteSnapshotEntry};
+use crate::storage::watchlist::{Watchlist, WatchlistEntry};
+use crate::vote::{VotePower, VotePowerItem, VoteTokenKind};
+
+use super::api_key::API_KEY_HEADER;
+use super::error::ErrorBody;
+use super::{alert, auth, distribution, user, vote, watchlist, TokenDetail};
+
+/// Document of the public endpoints, the admin scope and the websocket are left out.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "zportfolio", description = "Portfolio, vote and distribution api"),
+    servers((url = "/api/v1")),
+    paths(
+        super::chain_list,
+        super::chain_info,
+        super::token_list,
+        super::token_info,
+        super::token_price_history,
+        user::account_info,
+        user::token_balance,
+        user::total_balance,
+        user::total_balance_stream,
+        user::token_total_amount,
+        user::token_total_amount_stream,
+        user::token_pnl,
+        user::session_info,
+        auth::nonce,
+        auth::login,
+        auth::logout,
+        vote::vote_power,
+        vote::leaderboard,
+        vote::export_leaderboard,
+        distribution::distribution_info,
+        distribution::claim_proof,
+        watchlist::watchlist_list,
+        watchlist::create_watchlist,
+        watchlist::watchlist_detail,
+        watchlist::rename_watchlist,
+        watchlist::delete_watchlist,
+        watchlist::add_entry,
+        watchlist::update_entry,
+        watchlist::remove_entry,
+        watchlist::watchlist_overview,
+        watchlist::watchlist_overview_stream,
+        alert::alert_list,
+        alert::create_alert,
+        alert::alert_detail,
+        alert::update_alert,
+        alert::delete_alert,
+        alert::alert_deliveries,
+        alert::test_alert,
+    ),
+    components(schemas(
+        ErrorBody,
+        ChainInfo,
+        TokenInfo,
+        TokenDetail,
+        PriceCandle,
+        PriceInterval,
+        ChainBalance,
+        DebankChainBalance,
+        DebankTotalBalance,
+        DebankTokenBalance,
+        CostBasisMethod,
+        TokenPnl,
+        user::AccountInfo,
+        user::SessionInfo,
+        user::TokenPnlInfo,
+        user::TotalUsdValue,
+        FxRate,
+        user::ChainTokenAmount,
+        user::TotalAmount,
+        auth::Nonce,
+        auth::LoginRequest,
+        auth::LoginResponse,
+        VoteTokenKind,
+        VotePowerItem,
+        VotePower,
+        VoteSnapshot,
+        VoteSnapshotEntry,
+        vote::ExportFormat,
+        vote::Leaderboard,
+        DistributionInfo,
+        Claim,
+        distribution::ClaimProof,
+        Watchlist,
+        WatchlistEntry,
+        watchlist::WatchlistName,
+        watchlist::NewEntry,
+        watchlist::EntryUpdate,
+        watchlist::WatchlistDetail,
+        watchlist::EntryOverview,
+        watchlist::WatchlistOverview,
+        watchlist::OverviewSummary,
+        AlertKind,
+        ThresholdMode,
+        AlertRule,
+        AlertDelivery,
+        alert::NewAlertRule,
+        alert::AlertRuleUpdate,
+        alert::CreatedAlertRule,
+        alert::TestDelivery,
+    )),
+    modifiers(&Conventions),
+    tags(
+        (name = "catalog", description = "Chains, tokens and prices"),
+        (name = "user", description = "Balances and pnl of an address"),
+        (name = "auth", description = "Sign-In with Ethereum sessions"),
+        (name = "vote", description = "Voting power and leaderboards"),
+        (name = "distribution", description = "Merkle distributions and claim proofs"),
+        (name = "watchlist", description = "Watchlists of the signed in user"),
+        (name = "alert", description = "Balance alerts of the signed in user"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Applies what every endpoint shares: the api key, the session on top of it for the
+/// operations marked with `session`, and the error body.
+pub(super) struct Conventions;
+
+impl Modify for Conventions {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(API_KEY_HEADER))),
+        );
+        components.add_security_scheme(
+            "session",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+
+        let api_key = SecurityRequirement::new("api_key", Vec::<String>::new());
+        // a requirement naming both schemes needs both of them
+        let with_session: SecurityRequirement =
+            serde_json::from_value(serde_json::json!({ "api_key": [], "session": [] }))
+                .expect("valid security requirement");
+        let error = ResponseBuilder::new()
+            .description("error with its status code and message")
+            .content(
+                "application/json",
+                ContentBuilder::new()
+                    .schema(utoipa::openapi::Ref::from_schema_name("ErrorBody"))
+                    .build(),
+            )
+            .build();
+        for item in openapi.paths.paths.values_mut() {
+            for operation in item.operations.values_mut() {
+                let session = operation.security.as_ref().is_some_and(|s| !s.is_empty());
+                operation.security = Some(vec![if session {
+                    with_session.clone()
+                } else {
+                    api_key.clone()
+                }]);
+                operation
+                    .responses
+                    .responses
+                    .insert("default".into(), RefOr::T(error.clone()));
+            }
+        }
+    }
+}
+
+pub(super) const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8" />
+  <title>zportfolio api</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@4/swagger-ui.css" />
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@4/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##;
+
+/// The document and its swagger ui, served without api key.
+pub fn api_scope() -> Router {
+    Router::new()
+        .route("/openapi.json", get(|| async { Json(ApiDoc::openapi()) }))
+        .route("/docs", get(|| async { Html(SWAGGER_UI) }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/docs/openapi.json");
+
+    /// The committed document is what the frontend generates its client from, regenerate it
+    /// with `UPDATE_OPENAPI=1 cargo test` after changing an endpoint.
+    #[test]
+    fn test_spec_up_to_date() {
+        let spec = ApiDoc::openapi().to_pretty_json().unwrap() + "\n";
+        if std::env::var("UPDATE_OPENAPI").is_ok() {
+            std::fs::write(SPEC_PATH, &spec).unwrap();
+        }
+        let committed = std::fs::read_to_string(SPEC_PATH).unwrap_or_default();
+        assert!(
+            committed == spec,
+            "docs/openapi.json is outdated, run `UPDATE_OPENAPI=1 cargo test`"
+        );
+    }
+
+    #[test]
+    fn test_spec_conventions() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let schemas = spec["components"]["schemas"].as_object().unwrap();
+        for (path, item) in spec["paths"].as_object().unwrap() {
+            for (method, operation) in item.as_object().unwrap() {
+                let security = &operation["security"][0];
+                assert!(security.get("api_key").is_some(), "{} {}", method, path);
+                assert!(operation["responses"].get("default").is_some());
+                // every referenced schema is declared
+                let text = operation.to_string();
+                for name in text.split("#/components/schemas/").skip(1) {
+                    let name = &name[..name.find('"').unwrap()];
+                    assert!(schemas.contains_key(name), "{} {}: {}", method, path, name);
+                }
+            }
+        }
+        assert!(spec["paths"]["/watchlist/{id}"]["get"]["security"][0]
+            .get("session")
+            .is_some());
+        assert!(spec["paths"]["/chain/list"]["get"]["security"][0]
+            .get("session")
+            .is_none());
+    }
+}
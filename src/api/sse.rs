@@ -0,0 +1,175 @@
+use std::convert::Infallible;
+use std::future::Future;
+
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use super::error::ApiError;
+
+/// Upstream calls of a stream running at once.
+const MAX_CONCURRENT: usize = 8;
+
+/// `result` event, one per completed part.
+#[derive(Debug, Serialize)]
+struct PartResult<'a, K, T> {
+    key: &'a K,
+    value: &'a T,
+}
+
+/// `error` event, a failed part does not end the stream.
+#[derive(Debug, Serialize)]
+struct PartError<'a, K> {
+    key: &'a K,
+    error: String,
+}
+
+/// `summary` event, always the last one.
+#[derive(Debug, Serialize)]
+struct Summary<S> {
+    #[serde(flatten)]
+    summary: S,
+    completed: usize,
+    failed: usize,
+}
+
+fn event<T: Serialize>(name: &str, data: &T) -> Result<Event, Infallible> {
+    Ok(Event::default()
+        .event(name)
+        .json_data(data)
+        .expect("sse events are serializable"))
+}
+
+/// Fetches the parts of an aggregate concurrently and streams them in their completion order,
+/// followed by the summary of the completed ones. The fetches still running are dropped when
+/// the client goes away.
+pub fn fan_out<K, T, S, F, Fut, Sm>(
+    keys: Vec<K>,
+    mut fetch: F,
+    summarize: Sm,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>>
+where
+    K: Serialize + Clone + Send + 'static,
+    T: Serialize + Send + 'static,
+    S: Serialize + Send,
+    F: FnMut(K) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<T, ApiError>> + Send + 'static,
+    Sm: FnOnce(Vec<(K, T)>) -> S + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(MAX_CONCURRENT);
+    tokio::spawn(async move {
+        let mut parts = stream::iter(keys)
+            .map(move |key| {
+                let part = fetch(key.clone());
+                async move { (key, part.await) }
+            })
+            .buffer_unordered(MAX_CONCURRENT);
+        let mut completed = Vec::new();
+        let mut failed = 0;
+        while let Some((key, res)) = parts.next().await {
+            let event = match res {
+                Ok(value) => {
+                    let event = event(
+                        "result",
+                        &PartResult {
+                            key: &key,
+                            value: &value,
+                        },
+                    );
+                    completed.push((key, value));
+                    event
+                }
+                Err(e) => {
+                    failed += 1;
+                    event(
+                        "error",
+                        &PartError {
+                            key: &key,
+                            error: e.to_string(),
+                        },
+                    )
+                }
+            };
+            if tx.send(event).await.is_err() {
+                return;
+            }
+        }
+        let summary = Summary {
+            completed: completed.len(),
+            failed,
+            summary: summarize(completed),
+        };
+        let _ = tx.send(event("summary", &summary)).await;
+    });
+    let events = stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|e| (e, rx)) });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::routing::get;
+    use axum::Router;
+
+    use super::*;
+
+    #[derive(Debug, Serialize)]
+    struct Total {
+        total: u64,
+    }
+
+    #[tokio::test]
+    async fn test_fan_out() {
+        let app = Router::new().route(
+            "/stream",
+            get(|| async {
+                fan_out(
+                    vec![3u64, 1, 2, 0],
+                    |key| async move {
+                        // the slowest part completes last
+                        tokio::time::sleep(Duration::from_millis(key * 20)).await;
+                        if key == 2 {
+                            return Err(ApiError::NotFound("part 2".into()));
+                        }
+                        Ok(key * 10)
+                    },
+                    |parts| Total {
+                        total: parts.iter().map(|(_, v)| v).sum(),
+                    },
+                )
+            }),
+        );
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/stream", listener.local_addr().unwrap());
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+
+        let resp = reqwest::get(&url).await.unwrap();
+        assert_eq!(resp.headers()["content-type"], "text/event-stream");
+        let body = resp.text().await.unwrap();
+        let events: Vec<(&str, serde_json::Value)> = body
+            .split("\n\n")
+            .filter(|e| !e.is_empty())
+            .map(|e| {
+                let mut lines = e.lines();
+                let name = lines.next().unwrap().strip_prefix("event:").unwrap();
+                let data = lines.next().unwrap().strip_prefix("data:").unwrap();
+                (name, serde_json::from_str(data).unwrap())
+            })
+            .collect();
+        let keys: Vec<_> = events.iter().map(|(_, d)| d["key"].clone()).collect();
+        assert_eq!(keys[..4], [0, 1, 2, 3]);
+        assert_eq!(events[2].0, "error");
+        assert_eq!(events[3].1["value"], 30);
+        assert_eq!(events[4].0, "summary");
+        assert_eq!(
+            events[4].1,
+            serde_json::json!({"total": 40, "completed": 3, "failed": 1})
+        );
+    }
+}
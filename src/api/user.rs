@@ -1,129 +1,397 @@
-use std::env;
+use std::collections::BTreeMap;
+use std::sync::Arc;
 
-use axum::extract::{Query, State};
+use axum::extract::{FromRef, Query, State};
+use axum::response::{IntoResponse, Response};
 use axum::routing::get;
 use axum::{Json, Router};
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
+use utoipa::{IntoParams, ToSchema};
 
+use crate::account;
+use crate::amount::Amount;
+use crate::pnl::{self, CostBasisMethod, TokenPnl, Trade};
+use crate::price::{PriceError, PriceProvider};
 use crate::storage::StorageProcessor;
+use crate::transfer;
 
+use super::auth::SessionUser;
 use super::error::ApiError;
+use super::sse;
+use super::{QueryCurrency, Services, SupportChains};
 
-use crate::debank::openapi::{DebankOpenAPI, DebankTokenBalance, DebankTotalBalance};
+use crate::debank::openapi::{
+    DebankApiError, DebankOpenAPI, DebankTokenBalance, DebankTotalBalance,
+};
 use crate::etherscan;
+use crate::fx::{CachedFxProvider, Fiat, FiatValue, FxRate};
 
 #[derive(Clone)]
 pub struct ApiUserData {
     storage_core: StorageProcessor,
-    support_chains: Vec<String>,
+    support_chains: SupportChains,
     acc_api: etherscan::EtherscanAPi,
     ass_api: DebankOpenAPI,
+    fx: Arc<CachedFxProvider>,
+    account_recheck_secs: i64,
 }
 
 impl ApiUserData {
-    pub async fn new() -> Self {
-        let storage = StorageProcessor::new_from_pool().await;
-        let chains = storage.load_support_chain_ids().await.expect("fail in db");
-
-        let etherscan_key = env::var("ETHERSCAN_KEY").expect("no valid etherscan key");
-        let debank_key = env::var("DEBANK_KEY").expect("no valid debank key");
-
-        let acc_api = etherscan::EtherscanAPi::new(&etherscan_key, "https://api.etherscan.io/api");
-        let ass_api = DebankOpenAPI::new("https://pro-openapi.debank.com/v1", &debank_key);
+    pub fn new(services: &Services, support_chains: SupportChains) -> Self {
         Self {
-            storage_core: storage,
-            support_chains: chains,
-            acc_api,
-            ass_api,
+            storage_core: services.storage.clone(),
+            support_chains,
+            acc_api: services.acc_api.clone(),
+            ass_api: services.ass_api.clone(),
+            fx: services.fx.clone(),
+            account_recheck_secs: services.account_recheck_secs,
         }
     }
 }
-#[derive(Debug, Deserialize)]
+impl FromRef<ApiUserData> for StorageProcessor {
+    fn from_ref(state: &ApiUserData) -> Self {
+        state.storage_core.clone()
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct QueryWithId {
     id: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct QueryWithTokenName {
     id: String,
     token_name: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct QueryTokenWithId {
     id: String,
     token_id: String,
     chain_id: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QueryPnl {
+    id: String,
+    #[serde(default)]
+    method: CostBasisMethod,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct AccountInfo {
-    activation_time: i64,
+    /// unix seconds of the first transaction, null for an address which never sent one
+    activation_time: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SessionInfo {
+    address: String,
+    expires_at: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TokenPnlInfo {
+    chain: String,
+    token_id: String,
+    /// false when the token has no current price, like the spam tokens sent to an address,
+    /// its unrealized pnl is left at zero
+    priced: bool,
+    #[serde(flatten)]
+    pnl: TokenPnl,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TotalUsdValue {
+    total_usd_value: f64,
+    /// the applied rate when the values were converted out of usd
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fx: Option<FxRate>,
 }
 
-pub async fn api_scope() -> Router<ApiUserData> {
-    Router::with_state(ApiUserData::new().await)
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ChainTokenAmount {
+    token_id: String,
+    amount: f64,
+    #[serde(skip)]
+    exact: Amount,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TotalAmount {
+    amount: f64,
+}
+
+impl FiatValue for TokenPnlInfo {
+    fn convert(&mut self, rate: f64) {
+        self.pnl.convert(rate);
+    }
+}
+
+pub fn api_scope(services: &Services, support_chains: SupportChains) -> Router<ApiUserData> {
+    let state = ApiUserData::new(services, support_chains);
+    Router::with_state(state)
         .route("/", get(account_info))
         .route("/token", get(token_balance))
         .route("/total_balance", get(total_balance))
+        .route("/total_balance/stream", get(total_balance_stream))
         .route("/vote_token_amount", get(token_total_amount))
+        .route("/vote_token_amount/stream", get(token_total_amount_stream))
+        .route("/pnl", get(token_pnl))
+        .route("/session", get(session_info))
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/session",
+    tag = "user",
+    security(("session" = [])),
+    responses((status = 200, body = SessionInfo))
+)]
+async fn session_info(user: SessionUser) -> Json<SessionInfo> {
+    Json(SessionInfo {
+        address: user.address,
+        expires_at: user.expires_at,
+    })
 }
 
+#[utoipa::path(
+    get,
+    path = "/user/",
+    tag = "user",
+    params(QueryWithId),
+    responses((status = 200, body = AccountInfo))
+)]
 async fn account_info(
     State(state): State<ApiUserData>,
     Query(info): Query<QueryWithId>,
 ) -> Result<Json<AccountInfo>, ApiError> {
-    let activation_time = match state.storage_core.load_user_info(&info.id).await {
-        Ok(user_info) => user_info.activation_time,
-        _ => {
-            let activation_time = state.acc_api.account_age(&info.id).await?;
-            state
-                .storage_core
-                .set_user_info(&info.id, activation_time)
-                .await
-                .unwrap();
-            activation_time
-        }
-    };
+    let activation_time = account::activation_time(
+        &state.storage_core,
+        &state.acc_api,
+        &info.id,
+        state.account_recheck_secs,
+    )
+    .await?;
     Ok(Json(AccountInfo { activation_time }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/user/total_balance",
+    tag = "user",
+    params(QueryWithId, QueryCurrency),
+    responses((status = 200, body = DebankTotalBalance))
+)]
 async fn total_balance(
     State(state): State<ApiUserData>,
     Query(info): Query<QueryWithId>,
-) -> Result<Json<DebankTotalBalance>, ApiError> {
+    Query(fiat): Query<QueryCurrency>,
+) -> Result<Fiat<DebankTotalBalance>, ApiError> {
+    let rate = state.fx.rate(fiat.currency.as_deref()).await?;
     let res = state
         .ass_api
-        .muti_chain_balance(&info.id, &state.support_chains)
+        .muti_chain_balance(&info.id, &state.support_chains.get())
         .await?;
 
-    Ok(Json(res))
+    Ok(Fiat::new(res, rate))
 }
 
+/// Streams the balance of every supported chain, then the total.
+#[utoipa::path(
+    get,
+    path = "/user/total_balance/stream",
+    tag = "user",
+    params(QueryWithId, QueryCurrency),
+    responses((
+        status = 200,
+        description = "`result` events with a `DebankChainBalance` per chain, then a `summary` \
+            event with `total_usd_value`",
+        content_type = "text/event-stream",
+        body = String
+    ))
+)]
+async fn total_balance_stream(
+    State(state): State<ApiUserData>,
+    Query(info): Query<QueryWithId>,
+    Query(fiat): Query<QueryCurrency>,
+) -> Result<Response, ApiError> {
+    let rate = state.fx.rate(fiat.currency.as_deref()).await?;
+    let (ass_api, fx_rate) = (state.ass_api, rate.rate);
+    let fx = rate.is_converted().then(|| rate.clone());
+    let stream = sse::fan_out(
+        state.support_chains.get(),
+        move |chain: String| {
+            let (ass_api, id) = (ass_api.clone(), info.id.clone());
+            async move {
+                let mut balance = ass_api.chain_balance(&id, &chain).await?;
+                balance.convert(fx_rate);
+                Ok(balance)
+            }
+        },
+        move |chains| TotalUsdValue {
+            total_usd_value: chains.iter().map(|(_, b)| b.usd_value).sum(),
+            fx,
+        },
+    );
+    Ok((rate.headers(), stream).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/token",
+    tag = "user",
+    params(QueryTokenWithId, QueryCurrency),
+    responses((status = 200, body = DebankTokenBalance))
+)]
 async fn token_balance(
     State(state): State<ApiUserData>,
     Query(info): Query<QueryTokenWithId>,
-) -> Result<Json<DebankTokenBalance>, ApiError> {
+    Query(fiat): Query<QueryCurrency>,
+) -> Result<Fiat<DebankTokenBalance>, ApiError> {
+    let rate = state.fx.rate(fiat.currency.as_deref()).await?;
     let res = state
         .ass_api
         .token_balance(&info.id, &info.chain_id, &info.token_id)
         .await?;
-    Ok(Json(res))
+    Ok(Fiat::new(res, rate))
 }
 
+#[utoipa::path(
+    get,
+    path = "/user/vote_token_amount",
+    tag = "user",
+    params(QueryWithTokenName),
+    responses((status = 200, body = TotalAmount))
+)]
 async fn token_total_amount(
     State(state): State<ApiUserData>,
     Query(info): Query<QueryWithTokenName>,
-) -> Result<Json<Value>, ApiError> {
+) -> Result<Json<TotalAmount>, ApiError> {
     let token_ids = state
         .storage_core
-        .load_token_ids_by_name(info.token_name)
+        .load_token_ids_by_name(info.token_name.clone())
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("vote token {}", info.token_name)))?;
+    let amount = state
+        .ass_api
+        .token_total_amount(&info.id, &token_ids)
         .await?;
-    let mut token_amount = 0.0;
-    for (k, v) in &token_ids {
-        let balance = state.ass_api.token_balance(&info.id, &k, &v).await?;
-        token_amount += balance.amount;
+    Ok(Json(TotalAmount {
+        amount: amount.to_f64(),
+    }))
+}
+
+/// Streams the amount held on every chain of the vote token, then the total.
+#[utoipa::path(
+    get,
+    path = "/user/vote_token_amount/stream",
+    tag = "user",
+    params(QueryWithTokenName),
+    responses((
+        status = 200,
+        description = "`result` events with a `ChainTokenAmount` per chain, then a `summary` \
+            event with the total `amount`",
+        content_type = "text/event-stream",
+        body = String
+    ))
+)]
+async fn token_total_amount_stream(
+    State(state): State<ApiUserData>,
+    Query(info): Query<QueryWithTokenName>,
+) -> Result<Response, ApiError> {
+    let token_ids = state
+        .storage_core
+        .load_token_ids_by_name(info.token_name.clone())
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("vote token {}", info.token_name)))?;
+    let chains = token_ids.keys().cloned().collect();
+    let ass_api = state.ass_api;
+    let stream = sse::fan_out(
+        chains,
+        move |chain: String| {
+            let (ass_api, id) = (ass_api.clone(), info.id.clone());
+            let token_id = token_ids[&chain].clone();
+            async move {
+                let balance = ass_api.token_balance(&id, &chain, &token_id).await?;
+                Ok(ChainTokenAmount {
+                    token_id,
+                    amount: balance.amount,
+                    exact: balance.exact_amount(),
+                })
+            }
+        },
+        |chains| TotalAmount {
+            amount: chains.iter().map(|(_, a)| a.exact).sum::<Amount>().to_f64(),
+        },
+    );
+    Ok(stream.into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/pnl",
+    tag = "user",
+    params(QueryPnl, QueryCurrency),
+    responses((status = 200, body = [TokenPnlInfo]))
+)]
+async fn token_pnl(
+    State(state): State<ApiUserData>,
+    Query(info): Query<QueryPnl>,
+    Query(fiat): Query<QueryCurrency>,
+) -> Result<Fiat<Vec<TokenPnlInfo>>, ApiError> {
+    let rate = state.fx.rate(fiat.currency.as_deref()).await?;
+    transfer::sync_transfers(
+        &state.storage_core,
+        &state.acc_api,
+        &state.ass_api,
+        &info.id,
+    )
+    .await?;
+    let transfers = state.storage_core.load_token_transfers(&info.id).await?;
+    let mut trades = BTreeMap::<(String, String), Vec<Trade>>::new();
+    for transfer in transfers {
+        trades
+            .entry((transfer.chain, transfer.token_id))
+            .or_default()
+            .push(Trade {
+                timestamp: transfer.timestamp,
+                amount: transfer.amount,
+                price: transfer.price,
+            });
+    }
+
+    let mut res = Vec::new();
+    for ((chain, token_id), token_trades) in trades {
+        let mut pnl = pnl::calculate(&token_trades, 0.0, info.method);
+        let mut priced = true;
+        // the current price only matters for the amount still held
+        if pnl.amount > 0.0 {
+            match state.ass_api.current_price(&chain, &token_id).await {
+                Ok(price) => pnl.revalue(price),
+                Err(PriceError::Debank(
+                    e @ (DebankApiError::Unauthorized
+                    | DebankApiError::RateLimitExceeded
+                    | DebankApiError::CapacityLimitExceeded),
+                )) => return Err(e.into()),
+                Err(e) => {
+                    tracing::debug!("no current price for {} on {}: {}", token_id, chain, e);
+                    pnl.unrealized_pnl = 0.0;
+                    priced = false;
+                }
+            }
+        }
+        res.push(TokenPnlInfo {
+            priced,
+            pnl,
+            chain,
+            token_id,
+        });
     }
-    Ok(Json(json!({ "amount": token_amount })))
+    Ok(Fiat::new(res, rate))
 }
@@ -0,0 +1,327 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use axum::http::HeaderMap;
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Deserialize;
+use utoipa::{IntoParams, OpenApi};
+
+use crate::debank::convert;
+use crate::debank::openapi::DebankOpenAPI;
+use crate::fx::{CachedFxProvider, Fiat, FiatValue, FxRate};
+use crate::portfolio::{
+    decimal_string, Balance, Chain, ChainValue, Token, TokenAmount, TokenBalance, TokenQuote,
+};
+use crate::price::PriceProvider;
+use crate::storage::token::TokenFilter;
+use crate::storage::StorageProcessor;
+
+use super::error::{ApiError, ErrorBody};
+use super::etag::etag_json;
+use super::openapi::{Conventions, SWAGGER_UI};
+use super::{
+    with_total_count, QueryChainList, QueryCurrency, QueryTokenDetail, QueryTokenList, Services,
+    SupportChains, MAX_PAGE_SIZE,
+};
+
+/// Second version of the api, every response is a zportfolio type whatever the provider.
+#[derive(Clone)]
+pub struct ApiV2State {
+    storage_core: StorageProcessor,
+    support_chains: SupportChains,
+    ass_api: DebankOpenAPI,
+    fx: Arc<CachedFxProvider>,
+}
+
+impl ApiV2State {
+    pub fn new(services: &Services, support_chains: SupportChains) -> Self {
+        Self {
+            storage_core: services.storage.clone(),
+            support_chains,
+            ass_api: services.ass_api.clone(),
+            fx: services.fx.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QueryAddress {
+    address: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QueryTokenBalance {
+    address: String,
+    chain: String,
+    token_id: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QueryVoteTokenAmount {
+    address: String,
+    token_name: String,
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "zportfolio", description = "Portfolio api, version 2"),
+    servers((url = "/api/v2")),
+    paths(
+        chain_list,
+        chain_info,
+        token_list,
+        token_info,
+        total_balance,
+        token_balance,
+        vote_token_amount,
+    ),
+    components(schemas(
+        ErrorBody,
+        Chain,
+        Token,
+        TokenQuote,
+        ChainValue,
+        Balance,
+        TokenBalance,
+        TokenAmount,
+        FxRate,
+    )),
+    modifiers(&Conventions),
+    tags(
+        (name = "catalog", description = "Chains and tokens"),
+        (name = "user", description = "Balances of an address"),
+    )
+)]
+pub struct ApiDocV2;
+
+pub fn api_scope(services: &Services, support_chains: SupportChains) -> Router<ApiV2State> {
+    Router::with_state(ApiV2State::new(services, support_chains))
+        .route("/chain/list", get(chain_list))
+        .route("/chain/:id", get(chain_info))
+        .route("/token/list", get(token_list))
+        .route("/token/:chain/:id", get(token_info))
+        .route("/user/total_balance", get(total_balance))
+        .route("/user/token", get(token_balance))
+        .route("/user/vote_token_amount", get(vote_token_amount))
+}
+
+/// The document and its swagger ui, served without api key.
+pub fn docs_scope() -> Router {
+    Router::new()
+        .route("/openapi.json", get(|| async { Json(ApiDocV2::openapi()) }))
+        .route("/docs", get(|| async { Html(SWAGGER_UI) }))
+}
+
+/// Converts the provider value before it is mapped, the mapped value is answered as a
+/// [`Fiat`] like in the first version.
+async fn fiat<T: FiatValue>(
+    state: &ApiV2State,
+    currency: Option<&str>,
+    mut value: T,
+) -> Result<(FxRate, T), ApiError> {
+    let rate = state.fx.rate(currency).await?;
+    value.convert(rate.rate);
+    Ok((rate, value))
+}
+
+#[utoipa::path(
+    get,
+    path = "/chain/list",
+    tag = "catalog",
+    params(QueryChainList),
+    responses((status = 200, body = [Chain], headers(("x-total-count" = i64))))
+)]
+async fn chain_list(
+    State(state): State<ApiV2State>,
+    headers: HeaderMap,
+    Query(info): Query<QueryChainList>,
+) -> Result<Response, ApiError> {
+    let limit = info.limit.map(|l| l.clamp(1, MAX_PAGE_SIZE));
+    let search = info.q.as_deref();
+    let total = state.storage_core.count_chains(search).await?;
+    let res: Vec<Chain> = state
+        .storage_core
+        .load_chains_filtered(search, limit, info.offset.max(0))
+        .await?
+        .into_iter()
+        .map(Chain::from)
+        .collect();
+    Ok(with_total_count(etag_json(&headers, &res)?, total))
+}
+
+#[utoipa::path(
+    get,
+    path = "/chain/{id}",
+    tag = "catalog",
+    params(("id" = String, Path, description = "chain id")),
+    responses((status = 200, body = Chain))
+)]
+async fn chain_info(
+    State(state): State<ApiV2State>,
+    Path(id): Path<String>,
+) -> Result<Json<Chain>, ApiError> {
+    let res = state
+        .storage_core
+        .load_chain(&id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("chain {}", id)))?;
+    Ok(Json(res.into()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/token/list",
+    tag = "catalog",
+    params(QueryTokenList),
+    responses((status = 200, body = [Token], headers(("x-total-count" = i64))))
+)]
+async fn token_list(
+    State(state): State<ApiV2State>,
+    headers: HeaderMap,
+    Query(info): Query<QueryTokenList>,
+) -> Result<Response, ApiError> {
+    let limit = info.limit.map(|l| l.clamp(1, MAX_PAGE_SIZE));
+    let filter = TokenFilter {
+        chain: info.chain,
+        is_core: info.is_core,
+        protocol_id: info.protocol_id,
+        search: info.q,
+    };
+    let total = state.storage_core.count_tokens(&filter).await?;
+    let res: Vec<Token> = state
+        .storage_core
+        .load_tokens_filtered(&filter, limit, info.offset.max(0))
+        .await?
+        .into_iter()
+        .map(Token::from)
+        .collect();
+    Ok(with_total_count(etag_json(&headers, &res)?, total))
+}
+
+#[utoipa::path(
+    get,
+    path = "/token/{chain}/{id}",
+    tag = "catalog",
+    params(
+        ("chain" = String, Path, description = "chain id"),
+        ("id" = String, Path, description = "token id"),
+        QueryTokenDetail,
+        QueryCurrency
+    ),
+    responses((status = 200, body = TokenQuote))
+)]
+async fn token_info(
+    State(state): State<ApiV2State>,
+    Path((chain, id)): Path<(String, String)>,
+    Query(info): Query<QueryTokenDetail>,
+    Query(fiat_query): Query<QueryCurrency>,
+) -> Result<Response, ApiError> {
+    let token = state
+        .storage_core
+        .load_token(&chain, &id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("token {} on {}", id, chain)))?;
+    let rate = state.fx.rate(fiat_query.currency.as_deref()).await?;
+    let price = if info.with_price {
+        let price = state.ass_api.current_price(&chain, &id).await?;
+        Some(decimal_string(price * rate.rate))
+    } else {
+        None
+    };
+    let quote = TokenQuote {
+        token: token.into(),
+        price,
+    };
+    Ok(Fiat(quote, rate).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/total_balance",
+    tag = "user",
+    params(QueryAddress, QueryCurrency),
+    responses((status = 200, body = Balance))
+)]
+async fn total_balance(
+    State(state): State<ApiV2State>,
+    Query(info): Query<QueryAddress>,
+    Query(fiat_query): Query<QueryCurrency>,
+) -> Result<Response, ApiError> {
+    let balance = state
+        .ass_api
+        .muti_chain_balance(&info.address, &state.support_chains.get())
+        .await?;
+    let (rate, balance) = fiat(&state, fiat_query.currency.as_deref(), balance).await?;
+    Ok(Fiat(convert::balance(&info.address, balance), rate).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/token",
+    tag = "user",
+    params(QueryTokenBalance, QueryCurrency),
+    responses((status = 200, body = TokenBalance))
+)]
+async fn token_balance(
+    State(state): State<ApiV2State>,
+    Query(info): Query<QueryTokenBalance>,
+    Query(fiat_query): Query<QueryCurrency>,
+) -> Result<Response, ApiError> {
+    let balance = state
+        .ass_api
+        .token_balance(&info.address, &info.chain, &info.token_id)
+        .await?;
+    let (rate, balance) = fiat(&state, fiat_query.currency.as_deref(), balance).await?;
+    Ok(Fiat(convert::token_balance(balance), rate).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/vote_token_amount",
+    tag = "user",
+    params(QueryVoteTokenAmount),
+    responses((status = 200, body = TokenAmount))
+)]
+async fn vote_token_amount(
+    State(state): State<ApiV2State>,
+    Query(info): Query<QueryVoteTokenAmount>,
+) -> Result<Json<TokenAmount>, ApiError> {
+    let token_ids = state
+        .storage_core
+        .load_token_ids_by_name(info.token_name.clone())
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("vote token {}", info.token_name)))?;
+    let amount = state
+        .ass_api
+        .token_total_amount(&info.address, &token_ids)
+        .await?;
+    Ok(Json(TokenAmount {
+        amount: amount.to_string(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/docs/openapi-v2.json");
+
+    /// Regenerate with `UPDATE_OPENAPI=1 cargo test` after changing an endpoint.
+    #[test]
+    fn test_spec_up_to_date() {
+        let spec = ApiDocV2::openapi().to_pretty_json().unwrap() + "\n";
+        if std::env::var("UPDATE_OPENAPI").is_ok() {
+            std::fs::write(SPEC_PATH, &spec).unwrap();
+        }
+        let committed = std::fs::read_to_string(SPEC_PATH).unwrap_or_default();
+        assert!(
+            committed == spec,
+            "docs/openapi-v2.json is outdated, run `UPDATE_OPENAPI=1 cargo test`"
+        );
+    }
+}
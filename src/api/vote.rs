@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::env;
+
+use axum::extract::{Path, Query, State};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::rpc::JsonRpcClient;
+use crate::storage::vote::{VoteSnapshot, VoteSnapshotEntry};
+use crate::storage::StorageProcessor;
+use crate::vote::{Snapshot, VotePower, VotePowerCalculator};
+
+use super::error::ApiError;
+use super::Services;
+
+#[derive(Clone)]
+pub struct ApiVoteData {
+    storage_core: StorageProcessor,
+    rpcs: HashMap<String, JsonRpcClient>,
+    home_chain: String,
+}
+
+impl ApiVoteData {
+    pub fn new(services: &Services) -> Self {
+        Self {
+            storage_core: services.storage.clone(),
+            rpcs: JsonRpcClient::from_env(),
+            home_chain: env::var("VOTE_HOME_CHAIN").unwrap_or_else(|_| "eth".into()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QueryVotePower {
+    token_name: String,
+    address: String,
+    /// `latest`, a home chain block number (`block:<n>` or `<n>`) or `time:<unix seconds>`
+    #[serde(default)]
+    snapshot: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QueryPage {
+    page: Option<i64>,
+    page_size: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QueryExport {
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Leaderboard {
+    vote_token: String,
+    /// the latest computed snapshot, none before the first computation
+    snapshot: Option<VoteSnapshot>,
+    total: i64,
+    page: i64,
+    page_size: i64,
+    entries: Vec<VoteSnapshotEntry>,
+}
+
+pub fn api_scope(services: &Services) -> Router<ApiVoteData> {
+    let state = ApiVoteData::new(services);
+    Router::with_state(state)
+        .route("/power", get(vote_power))
+        .route("/:token/leaderboard", get(leaderboard))
+        .route("/:token/export", get(export_leaderboard))
+}
+
+#[utoipa::path(
+    get,
+    path = "/vote/power",
+    tag = "vote",
+    params(QueryVotePower),
+    responses((status = 200, body = VotePower))
+)]
+async fn vote_power(
+    State(state): State<ApiVoteData>,
+    Query(info): Query<QueryVotePower>,
+) -> Result<Json<VotePower>, ApiError> {
+    let snapshot: Snapshot = info.snapshot.parse()?;
+    ensure_vote_token(&state.storage_core, &info.token_name).await?;
+    let calculator = VotePowerCalculator {
+        storage: &state.storage_core,
+        rpcs: &state.rpcs,
+        home_chain: &state.home_chain,
+    };
+    let res = calculator
+        .voting_power(&info.token_name, &info.address, snapshot)
+        .await?;
+    Ok(Json(res))
+}
+
+async fn ensure_vote_token(storage: &StorageProcessor, vote_token: &str) -> Result<(), ApiError> {
+    match storage.vote_token_exists(vote_token).await? {
+        true => Ok(()),
+        false => Err(ApiError::NotFound(format!("vote token {}", vote_token))),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/vote/{token}/leaderboard",
+    tag = "vote",
+    params(("token" = String, Path, description = "vote token name"), QueryPage),
+    responses((status = 200, body = Leaderboard))
+)]
+async fn leaderboard(
+    State(state): State<ApiVoteData>,
+    Path(vote_token): Path<String>,
+    Query(info): Query<QueryPage>,
+) -> Result<Json<Leaderboard>, ApiError> {
+    let page = info.page.unwrap_or(1).max(1);
+    let page_size = info.page_size.unwrap_or(50).clamp(1, 500);
+    let offset = (page - 1)
+        .checked_mul(page_size)
+        .ok_or_else(|| ApiError::BadRequest(format!("page {} is out of range", page)))?;
+    ensure_vote_token(&state.storage_core, &vote_token).await?;
+    let snapshot = state
+        .storage_core
+        .load_latest_vote_snapshot(&vote_token)
+        .await?;
+    let (total, entries) = match &snapshot {
+        Some(snapshot) => (
+            state
+                .storage_core
+                .count_vote_snapshot_entries(snapshot.id)
+                .await?,
+            state
+                .storage_core
+                .load_vote_snapshot_entries(snapshot.id, page_size, offset)
+                .await?,
+        ),
+        None => (0, Vec::new()),
+    };
+    Ok(Json(Leaderboard {
+        vote_token,
+        snapshot,
+        total,
+        page,
+        page_size,
+        entries,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/vote/{token}/export",
+    tag = "vote",
+    params(("token" = String, Path, description = "vote token name"), QueryExport),
+    responses((status = 200, description = "every entry of the latest snapshot as an attachment",
+            body = [VoteSnapshotEntry], content_type = ["application/json", "text/csv"]))
+)]
+async fn export_leaderboard(
+    State(state): State<ApiVoteData>,
+    Path(vote_token): Path<String>,
+    Query(info): Query<QueryExport>,
+) -> Result<Response, ApiError> {
+    ensure_vote_token(&state.storage_core, &vote_token).await?;
+    let snapshot = state
+        .storage_core
+        .load_latest_vote_snapshot(&vote_token)
+        .await?;
+    let (snapshot_id, entries) = match snapshot {
+        Some(snapshot) => {
+            let total = state
+                .storage_core
+                .count_vote_snapshot_entries(snapshot.id)
+                .await?;
+            let entries = state
+                .storage_core
+                .load_vote_snapshot_entries(snapshot.id, total, 0)
+                .await?;
+            (snapshot.id, entries)
+        }
+        None => (0, Vec::new()),
+    };
+
+    let (content_type, extension, body) = match info.format {
+        ExportFormat::Json => ("application/json", "json", serde_json::to_vec(&entries)?),
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for entry in &entries {
+                writer.serialize(entry)?;
+            }
+            let body = writer
+                .into_inner()
+                .map_err(|e| csv::Error::from(e.into_error()))?;
+            ("text/csv", "csv", body)
+        }
+    };
+    let disposition = format!(
+        "attachment; filename=\"{}-leaderboard-{}.{}\"",
+        vote_token, snapshot_id, extension
+    );
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response())
+}
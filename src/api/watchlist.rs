@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::extract::{FromRef, Path, Query, State};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::debank::openapi::{DebankOpenAPI, DebankTotalBalance};
+use crate::fx::{CachedFxProvider, Fiat, FiatValue, FxRate};
+use crate::merkle::to_checksum_address;
+use crate::storage::watchlist::{Watchlist, WatchlistEntry};
+use crate::storage::StorageProcessor;
+
+use super::auth::SessionUser;
+use super::error::ApiError;
+use super::sse;
+use super::{QueryCurrency, Services, SupportChains};
+
+/// Every entry of the overview costs debank units.
+const MAX_WATCHLIST_ENTRIES: usize = 50;
+
+#[derive(Clone)]
+pub struct ApiWatchlistData {
+    storage_core: StorageProcessor,
+    support_chains: SupportChains,
+    ass_api: DebankOpenAPI,
+    fx: Arc<CachedFxProvider>,
+}
+
+impl ApiWatchlistData {
+    pub fn new(services: &Services, support_chains: SupportChains) -> Self {
+        Self {
+            storage_core: services.storage.clone(),
+            support_chains,
+            ass_api: services.ass_api.clone(),
+            fx: services.fx.clone(),
+        }
+    }
+}
+
+impl FromRef<ApiWatchlistData> for StorageProcessor {
+    fn from_ref(state: &ApiWatchlistData) -> Self {
+        state.storage_core.clone()
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct WatchlistName {
+    name: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewEntry {
+    address: String,
+    label: Option<String>,
+    note: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct EntryUpdate {
+    label: Option<String>,
+    note: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WatchlistDetail {
+    #[serde(flatten)]
+    watchlist: Watchlist,
+    entries: Vec<WatchlistEntry>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct EntryOverview {
+    #[serde(flatten)]
+    entry: WatchlistEntry,
+    balance: DebankTotalBalance,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WatchlistOverview {
+    id: i64,
+    name: String,
+    /// sum of the entries
+    total_usd_value: f64,
+    entries: Vec<EntryOverview>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OverviewSummary {
+    id: i64,
+    name: String,
+    total_usd_value: f64,
+    /// the applied rate when the values were converted out of usd
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fx: Option<FxRate>,
+}
+
+impl FiatValue for WatchlistOverview {
+    fn convert(&mut self, rate: f64) {
+        self.total_usd_value *= rate;
+        for entry in self.entries.iter_mut() {
+            entry.balance.convert(rate);
+        }
+    }
+}
+
+pub fn api_scope(services: &Services, support_chains: SupportChains) -> Router<ApiWatchlistData> {
+    Router::with_state(ApiWatchlistData::new(services, support_chains))
+        .route("/", get(watchlist_list).post(create_watchlist))
+        .route(
+            "/:id",
+            get(watchlist_detail)
+                .put(rename_watchlist)
+                .delete(delete_watchlist),
+        )
+        .route("/:id/entry", post(add_entry))
+        .route(
+            "/:id/entry/:address",
+            put(update_entry).delete(remove_entry),
+        )
+        .route("/:id/overview", get(watchlist_overview))
+        .route("/:id/overview/stream", get(watchlist_overview_stream))
+}
+
+/// Lowercases the address after checking it is valid.
+pub(super) fn normalize_address(address: &str) -> Result<String, ApiError> {
+    to_checksum_address(address)
+        .map(|a| a.to_lowercase())
+        .map_err(|e| ApiError::BadRequest(e.to_string()))
+}
+
+/// Loads the watchlist of the user, the watchlists of other users are not found so their ids
+/// are not disclosed.
+async fn owned_watchlist(
+    state: &ApiWatchlistData,
+    user: &SessionUser,
+    id: i64,
+) -> Result<Watchlist, ApiError> {
+    state
+        .storage_core
+        .load_watchlist(id)
+        .await?
+        .filter(|w| w.owner == user.address)
+        .ok_or_else(|| ApiError::NotFound(format!("watchlist {}", id)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/watchlist/",
+    tag = "watchlist",
+    security(("session" = [])),
+    responses((status = 200, body = [Watchlist]))
+)]
+async fn watchlist_list(
+    State(state): State<ApiWatchlistData>,
+    user: SessionUser,
+) -> Result<Json<Vec<Watchlist>>, ApiError> {
+    Ok(Json(
+        state.storage_core.load_watchlists(&user.address).await?,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/watchlist/",
+    tag = "watchlist",
+    request_body = WatchlistName,
+    security(("session" = [])),
+    responses((status = 200, body = Watchlist))
+)]
+async fn create_watchlist(
+    State(state): State<ApiWatchlistData>,
+    user: SessionUser,
+    Json(info): Json<WatchlistName>,
+) -> Result<Json<Watchlist>, ApiError> {
+    let created_at = Utc::now().timestamp();
+    let id = state
+        .storage_core
+        .insert_watchlist(&user.address, &info.name, created_at)
+        .await?;
+    Ok(Json(Watchlist {
+        id,
+        owner: user.address,
+        name: info.name,
+        created_at,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/watchlist/{id}",
+    tag = "watchlist",
+    params(("id" = i64, Path, description = "watchlist id")),
+    security(("session" = [])),
+    responses((status = 200, body = WatchlistDetail))
+)]
+async fn watchlist_detail(
+    State(state): State<ApiWatchlistData>,
+    user: SessionUser,
+    Path(id): Path<i64>,
+) -> Result<Json<WatchlistDetail>, ApiError> {
+    let watchlist = owned_watchlist(&state, &user, id).await?;
+    let entries = state.storage_core.load_watchlist_entries(id).await?;
+    Ok(Json(WatchlistDetail { watchlist, entries }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/watchlist/{id}",
+    tag = "watchlist",
+    params(("id" = i64, Path, description = "watchlist id")),
+    request_body = WatchlistName,
+    security(("session" = [])),
+    responses((status = 200, body = Watchlist))
+)]
+async fn rename_watchlist(
+    State(state): State<ApiWatchlistData>,
+    user: SessionUser,
+    Path(id): Path<i64>,
+    Json(info): Json<WatchlistName>,
+) -> Result<Json<Watchlist>, ApiError> {
+    let watchlist = owned_watchlist(&state, &user, id).await?;
+    state.storage_core.rename_watchlist(id, &info.name).await?;
+    Ok(Json(Watchlist {
+        name: info.name,
+        ..watchlist
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/watchlist/{id}",
+    tag = "watchlist",
+    params(("id" = i64, Path, description = "watchlist id")),
+    security(("session" = [])),
+    responses((status = 200, body = ()))
+)]
+async fn delete_watchlist(
+    State(state): State<ApiWatchlistData>,
+    user: SessionUser,
+    Path(id): Path<i64>,
+) -> Result<Json<()>, ApiError> {
+    owned_watchlist(&state, &user, id).await?;
+    state.storage_core.delete_watchlist(id).await?;
+    Ok(Json(()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/watchlist/{id}/entry",
+    tag = "watchlist",
+    params(("id" = i64, Path, description = "watchlist id")),
+    request_body = NewEntry,
+    security(("session" = [])),
+    responses((status = 200, body = WatchlistEntry))
+)]
+async fn add_entry(
+    State(state): State<ApiWatchlistData>,
+    user: SessionUser,
+    Path(id): Path<i64>,
+    Json(info): Json<NewEntry>,
+) -> Result<Json<WatchlistEntry>, ApiError> {
+    owned_watchlist(&state, &user, id).await?;
+    let address = normalize_address(&info.address)?;
+    let entries = state.storage_core.load_watchlist_entries(id).await?;
+    if entries.iter().any(|e| e.address == address) {
+        return Err(ApiError::Conflict(format!("address {}", address)));
+    }
+    if entries.len() >= MAX_WATCHLIST_ENTRIES {
+        return Err(ApiError::BadRequest(format!(
+            "a watchlist has at most {} entries",
+            MAX_WATCHLIST_ENTRIES
+        )));
+    }
+    let entry = WatchlistEntry {
+        address,
+        label: info.label,
+        note: info.note,
+        created_at: Utc::now().timestamp(),
+    };
+    state
+        .storage_core
+        .insert_watchlist_entry(id, &entry)
+        .await?;
+    Ok(Json(entry))
+}
+
+#[utoipa::path(
+    put,
+    path = "/watchlist/{id}/entry/{address}",
+    tag = "watchlist",
+    params(("id" = i64, Path, description = "watchlist id"), ("address" = String, Path, description = "address of the entry")),
+    request_body = EntryUpdate,
+    security(("session" = [])),
+    responses((status = 200, body = WatchlistEntry))
+)]
+async fn update_entry(
+    State(state): State<ApiWatchlistData>,
+    user: SessionUser,
+    Path((id, address)): Path<(i64, String)>,
+    Json(info): Json<EntryUpdate>,
+) -> Result<Json<WatchlistEntry>, ApiError> {
+    owned_watchlist(&state, &user, id).await?;
+    let address = normalize_address(&address)?;
+    let entry = state
+        .storage_core
+        .load_watchlist_entries(id)
+        .await?
+        .into_iter()
+        .find(|e| e.address == address)
+        .ok_or_else(|| ApiError::NotFound(format!("address {}", address)))?;
+    let entry = WatchlistEntry {
+        label: info.label,
+        note: info.note,
+        ..entry
+    };
+    state
+        .storage_core
+        .update_watchlist_entry(id, &entry)
+        .await?;
+    Ok(Json(entry))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/watchlist/{id}/entry/{address}",
+    tag = "watchlist",
+    params(("id" = i64, Path, description = "watchlist id"), ("address" = String, Path, description = "address of the entry")),
+    security(("session" = [])),
+    responses((status = 200, body = ()))
+)]
+async fn remove_entry(
+    State(state): State<ApiWatchlistData>,
+    user: SessionUser,
+    Path((id, address)): Path<(i64, String)>,
+) -> Result<Json<()>, ApiError> {
+    owned_watchlist(&state, &user, id).await?;
+    let address = normalize_address(&address)?;
+    state
+        .storage_core
+        .delete_watchlist_entry(id, &address)
+        .await?;
+    Ok(Json(()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/watchlist/{id}/overview",
+    tag = "watchlist",
+    params(("id" = i64, Path, description = "watchlist id"), QueryCurrency),
+    security(("session" = [])),
+    responses((status = 200, body = WatchlistOverview))
+)]
+async fn watchlist_overview(
+    State(state): State<ApiWatchlistData>,
+    user: SessionUser,
+    Path(id): Path<i64>,
+    Query(fiat): Query<QueryCurrency>,
+) -> Result<Fiat<WatchlistOverview>, ApiError> {
+    let rate = state.fx.rate(fiat.currency.as_deref()).await?;
+    let watchlist = owned_watchlist(&state, &user, id).await?;
+    let chains = state.support_chains.get();
+    let mut entries = Vec::new();
+    for entry in state.storage_core.load_watchlist_entries(id).await? {
+        let balance = state
+            .ass_api
+            .muti_chain_balance(&entry.address, &chains)
+            .await?;
+        entries.push(EntryOverview { entry, balance });
+    }
+    let total_usd_value = entries.iter().map(|e| e.balance.total_usd_value).sum();
+    Ok(Fiat::new(
+        WatchlistOverview {
+            id,
+            name: watchlist.name,
+            total_usd_value,
+            entries,
+        },
+        rate,
+    ))
+}
+
+/// Streams the overview of every entry as its balance comes in, then the total.
+#[utoipa::path(
+    get,
+    path = "/watchlist/{id}/overview/stream",
+    tag = "watchlist",
+    params(("id" = i64, Path, description = "watchlist id"), QueryCurrency),
+    security(("session" = [])),
+    responses((
+        status = 200,
+        description = "`result` events with an `EntryOverview` per address, then a `summary` \
+            event with the `OverviewSummary`",
+        content_type = "text/event-stream",
+        body = String
+    ))
+)]
+async fn watchlist_overview_stream(
+    State(state): State<ApiWatchlistData>,
+    user: SessionUser,
+    Path(id): Path<i64>,
+    Query(fiat): Query<QueryCurrency>,
+) -> Result<Response, ApiError> {
+    let rate = state.fx.rate(fiat.currency.as_deref()).await?;
+    let watchlist = owned_watchlist(&state, &user, id).await?;
+    let mut entries: HashMap<String, WatchlistEntry> = state
+        .storage_core
+        .load_watchlist_entries(id)
+        .await?
+        .into_iter()
+        .map(|e| (e.address.clone(), e))
+        .collect();
+    let addresses = entries.keys().cloned().collect();
+    let (ass_api, chains, fx_rate) = (state.ass_api, state.support_chains.get(), rate.rate);
+    let fx = rate.is_converted().then(|| rate.clone());
+    let stream = sse::fan_out(
+        addresses,
+        move |address: String| {
+            let (ass_api, chains) = (ass_api.clone(), chains.clone());
+            let entry = entries.remove(&address);
+            async move {
+                let mut balance = ass_api.muti_chain_balance(&address, &chains).await?;
+                balance.convert(fx_rate);
+                let entry = entry.expect("every address has an entry");
+                Ok(EntryOverview { entry, balance })
+            }
+        },
+        move |entries| OverviewSummary {
+            id,
+            name: watchlist.name,
+            total_usd_value: entries.iter().map(|(_, e)| e.balance.total_usd_value).sum(),
+            fx,
+        },
+    );
+    Ok((rate.headers(), stream).into_response())
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::Response;
+use axum::routing::get;
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::hub::{BalanceHub, BalanceUpdate};
+
+use super::watchlist::normalize_address;
+use super::Services;
+
+/// Addresses a connection can watch at once.
+const MAX_SUBSCRIPTIONS: usize = 20;
+/// The server pings the idle clients this often and drops them after two missed pongs.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+#[derive(Clone)]
+pub struct ApiWsData {
+    hub: BalanceHub,
+}
+
+impl ApiWsData {
+    pub fn new(services: &Services) -> Self {
+        Self {
+            hub: services.hub.clone(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Subscribe { address: String },
+    Unsubscribe { address: String },
+    Ping,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage<'a> {
+    Subscribed { address: String },
+    Unsubscribed { address: String },
+    Balance(&'a BalanceUpdate),
+    Error { message: String },
+    Pong,
+}
+
+impl ServerMessage<'_> {
+    fn to_message(&self) -> Message {
+        Message::Text(serde_json::to_string(self).expect("server messages are serializable"))
+    }
+}
+
+pub fn api_scope(services: &Services) -> Router<ApiWsData> {
+    Router::with_state(ApiWsData::new(services)).route("/", get(ws_handler))
+}
+
+async fn ws_handler(State(state): State<ApiWsData>, ws: WebSocketUpgrade) -> Response {
+    ws.max_message_size(MAX_MESSAGE_SIZE)
+        .on_upgrade(move |socket| Connection::new(state.hub).run(socket))
+}
+
+/// Subscriptions of one client, every subscription forwards the updates of its topic to the
+/// connection.
+struct Connection {
+    hub: BalanceHub,
+    subscriptions: HashMap<String, JoinHandle<()>>,
+    updates_tx: mpsc::Sender<Arc<BalanceUpdate>>,
+    updates_rx: mpsc::Receiver<Arc<BalanceUpdate>>,
+}
+
+impl Connection {
+    fn new(hub: BalanceHub) -> Self {
+        let (updates_tx, updates_rx) = mpsc::channel(MAX_SUBSCRIPTIONS);
+        Self {
+            hub,
+            subscriptions: HashMap::new(),
+            updates_tx,
+            updates_rx,
+        }
+    }
+
+    async fn run(mut self, mut socket: WebSocket) {
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+        let mut last_seen = Instant::now();
+        loop {
+            let reply = tokio::select! {
+                msg = socket.recv() => {
+                    last_seen = Instant::now();
+                    match msg {
+                        Some(Ok(Message::Text(text))) => Some(self.handle(&text)),
+                        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                        // the pongs only refresh `last_seen`
+                        Some(Ok(_)) => None,
+                    }
+                }
+                Some(update) = self.updates_rx.recv() => {
+                    Some(ServerMessage::Balance(&update).to_message())
+                }
+                _ = heartbeat.tick() => {
+                    if last_seen.elapsed() > 2 * HEARTBEAT_INTERVAL {
+                        break;
+                    }
+                    Some(Message::Ping(Vec::new()))
+                }
+            };
+            if let Some(reply) = reply {
+                if socket.send(reply).await.is_err() {
+                    break;
+                }
+            }
+        }
+        for (_, forward) in self.subscriptions.drain() {
+            forward.abort();
+        }
+    }
+
+    fn handle(&mut self, text: &str) -> Message {
+        let msg = match serde_json::from_str(text) {
+            Ok(msg) => msg,
+            Err(e) => {
+                return ServerMessage::Error {
+                    message: e.to_string(),
+                }
+                .to_message()
+            }
+        };
+        let res = match msg {
+            ClientMessage::Subscribe { address } => self.subscribe(&address),
+            ClientMessage::Unsubscribe { address } => self.unsubscribe(&address),
+            ClientMessage::Ping => Ok(ServerMessage::Pong),
+        };
+        res.unwrap_or_else(|message| ServerMessage::Error { message })
+            .to_message()
+    }
+
+    fn subscribe(&mut self, address: &str) -> Result<ServerMessage<'static>, String> {
+        let address = normalize_address(address).map_err(|e| e.to_string())?;
+        if self.subscriptions.contains_key(&address) {
+            return Ok(ServerMessage::Subscribed { address });
+        }
+        if self.subscriptions.len() >= MAX_SUBSCRIPTIONS {
+            return Err(format!(
+                "a connection watches at most {} addresses",
+                MAX_SUBSCRIPTIONS
+            ));
+        }
+        let (mut updates, last) = self.hub.subscribe(&address).map_err(|e| e.to_string())?;
+        let tx = self.updates_tx.clone();
+        let forward = tokio::spawn(async move {
+            if let Some(last) = last {
+                if tx.send(last).await.is_err() {
+                    return;
+                }
+            }
+            loop {
+                match updates.recv().await {
+                    Ok(update) => {
+                        if tx.send(update).await.is_err() {
+                            return;
+                        }
+                    }
+                    // a slow client skips to the latest balances
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        });
+        self.subscriptions.insert(address.clone(), forward);
+        Ok(ServerMessage::Subscribed { address })
+    }
+
+    fn unsubscribe(&mut self, address: &str) -> Result<ServerMessage<'static>, String> {
+        let address = normalize_address(address).map_err(|e| e.to_string())?;
+        if let Some(forward) = self.subscriptions.remove(&address) {
+            forward.abort();
+        }
+        Ok(ServerMessage::Unsubscribed { address })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_message() {
+        let msg: ClientMessage =
+            serde_json::from_str(r#"{"type":"subscribe","address":"0xabc"}"#).unwrap();
+        assert_eq!(
+            msg,
+            ClientMessage::Subscribe {
+                address: "0xabc".into()
+            }
+        );
+        let msg: ClientMessage = serde_json::from_str(r#"{"type":"ping"}"#).unwrap();
+        assert_eq!(msg, ClientMessage::Ping);
+        assert!(serde_json::from_str::<ClientMessage>(r#"{"type":"other"}"#).is_err());
+
+        let reply = serde_json::to_string(&ServerMessage::Pong).unwrap();
+        assert_eq!(reply, r#"{"type":"pong"}"#);
+    }
+}
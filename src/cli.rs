@@ -0,0 +1,214 @@
+use std::env;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::debank::convert;
+use crate::debank::openapi::DebankOpenAPI;
+use crate::etherscan::EtherscanAPi;
+use crate::job::token_sync;
+use crate::storage::StorageProcessor;
+use crate::{account, api, distribution};
+
+#[derive(Debug, Parser)]
+#[command(name = "zportfolio", about = "Portfolio and governance api server")]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Start the api server, the default when no command is given
+    Serve,
+    /// Apply the pending database migrations
+    Migrate,
+    /// Sync the chain and token catalog with debank
+    SyncTokens,
+    /// Inspect an address
+    #[command(subcommand)]
+    User(UserCommand),
+    /// Print the exact amount of a vote token held by an address over its chains
+    VoteAmount { token: String, address: String },
+    /// Manage the data cached from the providers
+    #[command(subcommand)]
+    Cache(CacheCommand),
+    /// Manage the provider keys
+    #[command(subcommand)]
+    Keys(KeysCommand),
+    /// Manage the merkle distributions
+    #[command(subcommand)]
+    Distribution(DistributionCommand),
+}
+
+#[derive(Debug, Subcommand)]
+enum UserCommand {
+    /// Print the activation time and the total balance over the supported chains
+    Lookup { address: String },
+}
+
+#[derive(Debug, Subcommand)]
+enum CacheCommand {
+    /// Forget the stored activation times so they are fetched again
+    Flush {
+        /// only forget this address
+        #[arg(long)]
+        address: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum KeysCommand {
+    /// Call etherscan and debank with the keys of the environment
+    Check,
+}
+
+#[derive(Debug, Subcommand)]
+enum DistributionCommand {
+    /// Build and store a distribution from a vote snapshot
+    Create {
+        #[arg(long)]
+        snapshot: i64,
+        /// decimals of the distributed token
+        #[arg(long, default_value_t = 18)]
+        decimals: u32,
+    },
+    /// Export the full tree json of a distribution
+    Export {
+        id: i64,
+        /// write into the file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
+pub async fn run(cli: Cli) -> anyhow::Result<()> {
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => {
+            tracing::info!("start the api server");
+            api::start_server().await;
+        }
+        Command::Migrate => {
+            let storage = StorageProcessor::new_from_pool().await;
+            storage.migrate().await?;
+            println!("database is up to date");
+        }
+        Command::SyncTokens => {
+            let storage = StorageProcessor::new_from_pool().await;
+            let report = token_sync::sync_catalog(&storage, &DebankOpenAPI::from_env()).await?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Command::User(UserCommand::Lookup { address }) => {
+            let storage = StorageProcessor::new_from_pool().await;
+            let activation_time = account::activation_time(
+                &storage,
+                &EtherscanAPi::from_env(),
+                &address,
+                account::recheck_secs_from_env(),
+            )
+            .await?;
+            let chains = storage.load_support_chain_ids().await?;
+            let balance = DebankOpenAPI::from_env()
+                .muti_chain_balance(&address, &chains)
+                .await?;
+            let lookup = serde_json::json!({
+                "activation_time": activation_time,
+                "total_balance": convert::balance(&address, balance),
+            });
+            println!("{}", serde_json::to_string_pretty(&lookup)?);
+        }
+        Command::VoteAmount { token, address } => {
+            let storage = StorageProcessor::new_from_pool().await;
+            let token_ids = storage
+                .load_token_ids_by_name(token.clone())
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("unknown vote token: {}", token))?;
+            let amount = DebankOpenAPI::from_env()
+                .token_total_amount(&address, &token_ids)
+                .await?;
+            println!("{}", amount);
+        }
+        Command::Cache(CacheCommand::Flush { address }) => {
+            let storage = StorageProcessor::new_from_pool().await;
+            let flushed = storage.delete_user_info(address.as_deref()).await?;
+            println!("forgot the activation time of {} addresses", flushed);
+        }
+        Command::Keys(KeysCommand::Check) => check_keys().await?,
+        Command::Distribution(DistributionCommand::Create { snapshot, decimals }) => {
+            let storage = StorageProcessor::new_from_pool().await;
+            let id = distribution::create_from_snapshot(&storage, snapshot, decimals).await?;
+            println!("created distribution {}", id);
+        }
+        Command::Distribution(DistributionCommand::Export { id, output }) => {
+            let storage = StorageProcessor::new_from_pool().await;
+            let tree = distribution::load(&storage, id).await?;
+            let json = serde_json::to_string_pretty(&tree)?;
+            match output {
+                Some(path) => std::fs::write(path, json)?,
+                None => println!("{}", json),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reports every key before failing so one run shows all the invalid ones.
+async fn check_keys() -> anyhow::Result<()> {
+    let mut valid = true;
+    if env::var("ETHERSCAN_KEY").is_err() {
+        println!("etherscan: ETHERSCAN_KEY is not set");
+        valid = false;
+    } else {
+        match EtherscanAPi::from_env().check_key().await {
+            Ok(()) => println!("etherscan: ok"),
+            Err(e) => {
+                println!("etherscan: {}", e);
+                valid = false;
+            }
+        }
+    }
+    if env::var("DEBANK_KEY").is_err() {
+        println!("debank: DEBANK_KEY is not set");
+        valid = false;
+    } else {
+        match DebankOpenAPI::from_env().units().await {
+            Ok(units) => println!("debank: ok, {} units left", units.balance),
+            Err(e) => {
+                println!("debank: {}", e);
+                valid = false;
+            }
+        }
+    }
+    if !valid {
+        anyhow::bail!("some provider keys are invalid");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        Cli::command().debug_assert();
+        let cli = Cli::try_parse_from(["zportfolio", "vote-amount", "ZKS", "0xabc"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::VoteAmount { ref token, ref address }) if token == "ZKS" && address == "0xabc"
+        ));
+        let cli =
+            Cli::try_parse_from(["zportfolio", "cache", "flush", "--address", "0xabc"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Cache(CacheCommand::Flush { address: Some(_) }))
+        ));
+        assert!(Cli::try_parse_from(["zportfolio", "user", "lookup"]).is_err());
+        assert!(Cli::try_parse_from(["zportfolio"])
+            .unwrap()
+            .command
+            .is_none());
+    }
+}
@@ -0,0 +1,117 @@
+//! Mapping of the debank models into the zportfolio response types.
+
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+
+use crate::portfolio::{decimal_string, Balance, ChainValue, Protocol, Token, TokenBalance};
+
+use super::openapi::{DebankProtocol, DebankTokenBalance, DebankTotalBalance};
+
+/// The total is summed again as decimals so it matches the chains to the cent.
+pub fn balance(address: &str, balance: DebankTotalBalance) -> Balance {
+    let chains: Vec<(ChainValue, Decimal)> = balance
+        .chain_list
+        .into_iter()
+        .map(|chain| {
+            let usd_value = Decimal::from_f64(chain.usd_value).unwrap_or_default();
+            let value = ChainValue {
+                chain: chain.id,
+                name: chain.name,
+                logo_url: chain.logo_url,
+                usd_value: usd_value.normalize().to_string(),
+            };
+            (value, usd_value)
+        })
+        .collect();
+    let total: Decimal = chains.iter().map(|(_, usd_value)| usd_value).sum();
+    Balance {
+        address: address.to_lowercase(),
+        total_usd_value: total.normalize().to_string(),
+        chains: chains.into_iter().map(|(value, _)| value).collect(),
+    }
+}
+
+pub fn token_balance(balance: DebankTokenBalance) -> TokenBalance {
+    let amount = balance.exact_amount();
+    TokenBalance {
+        amount: amount.to_string(),
+        price: decimal_string(balance.price),
+        usd_value: amount.value_at(balance.price).to_string(),
+        token: Token {
+            chain: balance.chain,
+            id: balance.id,
+            symbol: balance.symbol,
+            name: balance.name,
+            decimals: balance.decimals.max(0) as u32,
+            logo_url: balance.logo_url,
+            protocol_id: balance.protocol_id,
+            is_core: balance.is_core,
+        },
+    }
+}
+
+pub fn protocol(protocol: DebankProtocol) -> Protocol {
+    Protocol {
+        id: protocol.id,
+        chain: protocol.chain,
+        name: protocol.name,
+        site_url: protocol.site_url,
+        logo_url: protocol.logo_url.unwrap_or_default(),
+        net_usd_value: decimal_string(protocol.net_usd_value),
+        asset_usd_value: decimal_string(protocol.asset_usd_value),
+        debt_usd_value: decimal_string(protocol.debt_usd_value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_balance() {
+        let debank: DebankTokenBalance = serde_json::from_value(serde_json::json!({
+            "id": "0xdac17f958d2ee523a2206206994597c13d831ec7",
+            "chain": "eth",
+            "name": "Tether USD",
+            "symbol": "USDT",
+            "decimals": 6,
+            "logo_url": "",
+            "protocol_id": "",
+            "is_core": true,
+            "price": 1.0,
+            "amount": 12345678.123456,
+            "raw_amount": 12345678123456.0,
+            "raw_amount_hex_str": "0xb3a73c251c0",
+            // fields unknown to zportfolio are not exposed
+            "display_symbol": null,
+        }))
+        .unwrap();
+        let balance = token_balance(debank);
+        assert_eq!(balance.amount, "12345678.123456");
+        assert_eq!(balance.usd_value, "12345678.123456");
+        assert_eq!(balance.token.decimals, 6);
+        let json = serde_json::to_value(&balance).unwrap();
+        assert!(json.get("raw_amount_hex_str").is_none());
+        assert!(json.get("display_symbol").is_none());
+        assert_eq!(json["symbol"], "USDT");
+    }
+
+    #[test]
+    fn test_balance_total() {
+        let debank: DebankTotalBalance = serde_json::from_value(serde_json::json!({
+            "total_usd_value": 0.0,
+            "chain_list": [
+                {"id": "eth", "community_id": 1, "name": "Ethereum", "logo_url": "",
+                    "native_token_id": "eth", "usd_value": 0.1},
+                {"id": "bsc", "community_id": 56, "name": "BNB Chain", "logo_url": "",
+                    "native_token_id": "bsc", "usd_value": 0.2},
+            ],
+        }))
+        .unwrap();
+        let balance = balance("0xABC", debank);
+        // 0.1 + 0.2 is 0.30000000000000004 as floats
+        assert_eq!(balance.total_usd_value, "0.3");
+        assert_eq!(balance.chains[1].usd_value, "0.2");
+        assert_eq!(balance.address, "0xabc");
+    }
+}
@@ -1,56 +1,159 @@
-use reqwest::{self, IntoUrl, Response, Url};
+use std::collections::HashMap;
+use std::env;
+
+use primitive_types::U256;
+use reqwest::{self, IntoUrl, RequestBuilder, Response, Url};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::amount::Amount;
+use crate::fx::FiatValue;
+use crate::http_fixture::{FixtureError, FixtureMode, HttpFixtures};
 
 #[derive(Error, Debug)]
 pub enum DebankApiError {
     #[error(transparent)]
     Reqwest(#[from] reqwest::Error),
+    #[error(transparent)]
+    Fixture(#[from] FixtureError),
     #[error("Unauthorized access key")]
     Unauthorized,
     #[error("Exceeded debank api ratelimit")]
     RateLimitExceeded,
     #[error("Hit debank api account capacity limit")]
     CapacityLimitExceeded,
+    #[error("Not found on debank")]
+    NotFound,
     #[error("Unknown error")]
     Unknown,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct ChainBalance {
-    id: String,
-    community_id: i64,
-    name: String,
-    logo_url: String,
-    native_token_id: String,
-    usd_value: f64,
+    pub id: String,
+    pub community_id: i64,
+    pub name: String,
+    pub logo_url: String,
+    pub native_token_id: String,
+    pub usd_value: f64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct DebankChainBalance {
-    usd_value: f64,
+    pub usd_value: f64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct DebankTotalBalance {
-    total_usd_value: f64,
-    chain_list: Vec<ChainBalance>,
+    pub total_usd_value: f64,
+    pub chain_list: Vec<ChainBalance>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct DebankTokenBalance {
-    id: String,
-    chain: String,
-    name: String,
-    symbol: String,
-    decimals: i64,
-    logo_url: String,
-    protocol_id: String,
-    is_core: bool,
-    price: f64,
+    pub id: String,
+    pub chain: String,
+    pub name: String,
+    pub symbol: String,
+    pub decimals: i64,
+    pub logo_url: String,
+    pub protocol_id: String,
+    pub is_core: bool,
+    pub price: f64,
     pub amount: f64,
     pub raw_amount: f64,
-    raw_amount_hex_str: String,
+    pub raw_amount_hex_str: String,
+}
+
+/// Position of an address in a protocol, the usd values are net of its debts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DebankProtocol {
+    pub id: String,
+    pub chain: String,
+    pub name: String,
+    pub site_url: String,
+    pub logo_url: Option<String>,
+    pub net_usd_value: f64,
+    pub asset_usd_value: f64,
+    pub debt_usd_value: f64,
+}
+
+impl DebankTokenBalance {
+    /// Exact amount from the raw hex amount, the rounded raw float when debank sent no valid hex.
+    pub fn exact_amount(&self) -> Amount {
+        let hex = self.raw_amount_hex_str.trim_start_matches("0x");
+        let raw = match U256::from_str_radix(hex, 16) {
+            Ok(raw) if !hex.is_empty() => raw,
+            _ if self.raw_amount.is_finite() && self.raw_amount > 0.0 => {
+                U256::from_dec_str(&format!("{:.0}", self.raw_amount)).unwrap_or_default()
+            }
+            _ => U256::zero(),
+        };
+        Amount::new(raw, self.decimals.max(0) as u32)
+    }
+}
+
+impl FiatValue for DebankTotalBalance {
+    fn convert(&mut self, rate: f64) {
+        self.total_usd_value *= rate;
+        for chain in self.chain_list.iter_mut() {
+            chain.usd_value *= rate;
+        }
+    }
+}
+
+impl FiatValue for DebankProtocol {
+    fn convert(&mut self, rate: f64) {
+        self.net_usd_value *= rate;
+        self.asset_usd_value *= rate;
+        self.debt_usd_value *= rate;
+    }
+}
+
+impl FiatValue for DebankChainBalance {
+    fn convert(&mut self, rate: f64) {
+        self.usd_value *= rate;
+    }
+}
+
+impl FiatValue for DebankTokenBalance {
+    fn convert(&mut self, rate: f64) {
+        self.price *= rate;
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DebankToken {
+    pub id: String,
+    pub chain: String,
+    pub name: String,
+    pub symbol: String,
+    pub decimals: i64,
+    pub logo_url: Option<String>,
+    pub protocol_id: String,
+    pub is_core: bool,
+    pub price: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DebankChain {
+    pub id: String,
+    pub community_id: i64,
+    pub name: String,
+    pub logo_url: Option<String>,
+    pub native_token_id: String,
+}
+
+/// Units left on the access key, every call spends some.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DebankUnits {
+    pub balance: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DebankHistoryPrice {
+    pub price: f64,
 }
 
 #[derive(Debug, Clone)]
@@ -58,6 +161,7 @@ pub struct DebankOpenAPI {
     api_url: Url,
     client: reqwest::Client,
     access_key: String,
+    fixtures: HttpFixtures,
 }
 
 impl DebankOpenAPI {
@@ -66,17 +170,43 @@ impl DebankOpenAPI {
             api_url: api_url.into_url().unwrap(),
             client: reqwest::Client::new(),
             access_key: access_key.to_string(),
+            fixtures: HttpFixtures::default(),
         }
     }
 
+    /// Builds the client with the `DEBANK_KEY` access key, `DEBANK_API_URL` points it to
+    /// another server than debank. The key may be left out when the calls are replayed.
+    pub fn from_env() -> Self {
+        let fixtures = HttpFixtures::from_env("debank");
+        let debank_key = match env::var("DEBANK_KEY") {
+            Ok(key) => key,
+            Err(_) if matches!(fixtures.mode(), FixtureMode::Replay(_)) => String::new(),
+            Err(_) => panic!("no valid debank key"),
+        };
+        let api_url = env::var("DEBANK_API_URL")
+            .unwrap_or_else(|_| "https://pro-openapi.debank.com/v1".to_string());
+        Self::new(api_url, &debank_key).with_fixtures(fixtures)
+    }
+
+    /// Records or replays the calls, the access key is redacted from the fixtures.
+    pub fn with_fixtures(mut self, fixtures: HttpFixtures) -> Self {
+        self.fixtures = fixtures.with_secret(&self.access_key);
+        self
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response, DebankApiError> {
+        self.fixtures.send(&self.client, request).await
+    }
+
     fn handle_debank_response(&self, resp: Response) -> Result<Response, DebankApiError> {
         if resp.status().as_u16() != 200 {
-            return match resp.status().as_u16() {
+            match resp.status().as_u16() {
                 401 => Err(DebankApiError::Unauthorized),
                 403 => Err(DebankApiError::CapacityLimitExceeded),
+                404 => Err(DebankApiError::NotFound),
                 429 => Err(DebankApiError::RateLimitExceeded),
                 _ => Err(DebankApiError::Unknown),
-            };
+            }
         } else {
             Ok(resp)
         }
@@ -93,52 +223,197 @@ impl DebankOpenAPI {
             .api_url
             .join("/v1/user/token")
             .expect("failed to join url path");
-        let mut resp = self
+        let request = self
             .client
             .get(url)
             .header("AccessKey", &self.access_key)
-            .query(&[("id", id), ("chain_id", chain_id), ("token_id", token_id)])
-            .send()
-            .await?;
+            .query(&[("id", id), ("chain_id", chain_id), ("token_id", token_id)]);
+        let mut resp = self.send(request).await?;
         resp = self.handle_debank_response(resp)?;
         let res = resp.json::<DebankTokenBalance>().await?;
         Ok(res)
     }
 
+    /// get the exact amount of a token summed over the chains, `token_ids` maps a chain to the
+    /// token.
+    pub async fn token_total_amount(
+        &self,
+        id: &str,
+        token_ids: &HashMap<String, String>,
+    ) -> Result<Amount, DebankApiError> {
+        let mut token_amount = Amount::default();
+        for (k, v) in token_ids {
+            let balance = self.token_balance(id, k, v).await?;
+            token_amount = token_amount + balance.exact_amount();
+        }
+        Ok(token_amount)
+    }
+
+    /// get the balances of the core tokens held on the provided chains.
+    pub async fn all_token_list(
+        &self,
+        id: &str,
+        chain_ids: &[String],
+    ) -> Result<Vec<DebankTokenBalance>, DebankApiError> {
+        let url = self
+            .api_url
+            .join("/v1/user/all_token_list")
+            .expect("failed to join url path");
+        let request = self
+            .client
+            .get(url)
+            .header("AccessKey", &self.access_key)
+            .query(&[("id", id), ("is_all", "false")]);
+        let mut resp = self.send(request).await?;
+        resp = self.handle_debank_response(resp)?;
+        let mut res = resp.json::<Vec<DebankTokenBalance>>().await?;
+        res.retain(|item| chain_ids.contains(&item.chain));
+        Ok(res)
+    }
+
+    /// get the protocols the address has a position in on the provided chains.
+    pub async fn all_simple_protocol_list(
+        &self,
+        id: &str,
+        chain_ids: &[String],
+    ) -> Result<Vec<DebankProtocol>, DebankApiError> {
+        let url = self
+            .api_url
+            .join("/v1/user/all_simple_protocol_list")
+            .expect("failed to join url path");
+        let request = self
+            .client
+            .get(url)
+            .header("AccessKey", &self.access_key)
+            .query(&[("id", id)]);
+        let mut resp = self.send(request).await?;
+        resp = self.handle_debank_response(resp)?;
+        let mut res = resp.json::<Vec<DebankProtocol>>().await?;
+        res.retain(|item| chain_ids.contains(&item.chain));
+        Ok(res)
+    }
+
     /// get the total balance on provide chains.
     pub async fn muti_chain_balance(
         &self,
         id: &str,
-        chain_ids: &Vec<String>,
+        chain_ids: &[String],
     ) -> Result<DebankTotalBalance, DebankApiError> {
         let url = self
             .api_url
             .join("/v1/user/total_balance")
             .expect("failed to join url path");
 
-        let mut resp = self
+        let request = self
             .client
             .get(url)
             .header("AccessKey", &self.access_key)
-            .query(&[("id", id)])
-            .send()
-            .await?;
+            .query(&[("id", id)]);
+        let mut resp = self.send(request).await?;
         resp = self.handle_debank_response(resp)?;
         let mut res = resp.json::<DebankTotalBalance>().await?;
-        res.chain_list.retain(|item| {
-            if chain_ids.contains(&item.id) {
-                true
-            } else {
-                false
-            }
-        });
+        res.chain_list.retain(|item| chain_ids.contains(&item.id));
         res.total_usd_value = 0.0;
         for chain in res.chain_list.iter() {
             res.total_usd_value += chain.usd_value;
         }
         Ok(res)
     }
-    #[allow(dead_code)]
+    /// get the token info with its current price.
+    pub async fn token_info(
+        &self,
+        chain_id: &str,
+        token_id: &str,
+    ) -> Result<DebankToken, DebankApiError> {
+        let url = self
+            .api_url
+            .join("/v1/token")
+            .expect("failed to join url path");
+        let request = self
+            .client
+            .get(url)
+            .header("AccessKey", &self.access_key)
+            .query(&[("chain_id", chain_id), ("id", token_id)]);
+        let mut resp = self.send(request).await?;
+        resp = self.handle_debank_response(resp)?;
+        let res = resp.json::<DebankToken>().await?;
+        Ok(res)
+    }
+
+    /// get the infos of the tokens on the chain, at most 100 ids are accepted at once.
+    pub async fn token_list_by_ids(
+        &self,
+        chain_id: &str,
+        token_ids: &[String],
+    ) -> Result<Vec<DebankToken>, DebankApiError> {
+        let url = self
+            .api_url
+            .join("/v1/token/list_by_ids")
+            .expect("failed to join url path");
+        let ids = token_ids.join(",");
+        let request = self
+            .client
+            .get(url)
+            .header("AccessKey", &self.access_key)
+            .query(&[("chain_id", chain_id), ("ids", ids.as_str())]);
+        let mut resp = self.send(request).await?;
+        resp = self.handle_debank_response(resp)?;
+        let res = resp.json::<Vec<DebankToken>>().await?;
+        Ok(res)
+    }
+
+    /// get the units left on the access key, the call itself is free.
+    pub async fn units(&self) -> Result<DebankUnits, DebankApiError> {
+        let url = self
+            .api_url
+            .join("/v1/account/units")
+            .expect("failed to join url path");
+        let request = self.client.get(url).header("AccessKey", &self.access_key);
+        let mut resp = self.send(request).await?;
+        resp = self.handle_debank_response(resp)?;
+        let res = resp.json::<DebankUnits>().await?;
+        Ok(res)
+    }
+
+    /// get all the chains supported by debank.
+    pub async fn chain_list(&self) -> Result<Vec<DebankChain>, DebankApiError> {
+        let url = self
+            .api_url
+            .join("/v1/chain/list")
+            .expect("failed to join url path");
+        let request = self.client.get(url).header("AccessKey", &self.access_key);
+        let mut resp = self.send(request).await?;
+        resp = self.handle_debank_response(resp)?;
+        let res = resp.json::<Vec<DebankChain>>().await?;
+        Ok(res)
+    }
+
+    /// get the token price on the given utc date, `date_at` is formatted as `YYYY-MM-DD`.
+    pub async fn token_history_price(
+        &self,
+        chain_id: &str,
+        token_id: &str,
+        date_at: &str,
+    ) -> Result<DebankHistoryPrice, DebankApiError> {
+        let url = self
+            .api_url
+            .join("/v1/token/history_price")
+            .expect("failed to join url path");
+        let request = self
+            .client
+            .get(url)
+            .header("AccessKey", &self.access_key)
+            .query(&[
+                ("chain_id", chain_id),
+                ("id", token_id),
+                ("date_at", date_at),
+            ]);
+        let mut resp = self.send(request).await?;
+        resp = self.handle_debank_response(resp)?;
+        let res = resp.json::<DebankHistoryPrice>().await?;
+        Ok(res)
+    }
+
     pub async fn chain_balance(
         &self,
         id: &str,
@@ -148,13 +423,12 @@ impl DebankOpenAPI {
             .api_url
             .join("/v1/user/chain_balance")
             .expect("failed to join url path");
-        let mut resp = self
+        let request = self
             .client
             .get(url)
             .header("AccessKey", &self.access_key)
-            .query(&[("id", id), ("chain_id", chain_id)])
-            .send()
-            .await?;
+            .query(&[("id", id), ("chain_id", chain_id)]);
+        let mut resp = self.send(request).await?;
         resp = self.handle_debank_response(resp)?;
         let res = resp.json::<DebankChainBalance>().await?;
         Ok(res)
@@ -164,27 +438,121 @@ impl DebankOpenAPI {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use dotenvy::dotenv;
-    use std::env;
+    use crate::mock::{MockServer, EXHAUSTED_KEY, INVALID_KEY, RATE_LIMITED_KEY, VALID_KEY};
+
+    fn token_balance(raw_amount: f64, raw_amount_hex_str: &str) -> DebankTokenBalance {
+        serde_json::from_value(serde_json::json!({
+            "id": "0x4d224452801aced8b2f0aebe155379bb5d594381",
+            "chain": "eth",
+            "name": "ApeCoin",
+            "symbol": "APE",
+            "decimals": 18,
+            "logo_url": "",
+            "protocol_id": "",
+            "is_core": true,
+            "price": 4.2,
+            "amount": raw_amount / 1e18,
+            "raw_amount": raw_amount,
+            "raw_amount_hex_str": raw_amount_hex_str,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_exact_amount() {
+        // 123456789.123456789123456789 with 18 decimals
+        let balance = token_balance(1.2345678912345679e26, "0x661efdf2e3b19f7c045f15");
+        assert_eq!(
+            balance.exact_amount().to_string(),
+            "123456789.123456789123456789"
+        );
+        // the float is rounded to whole raw units when there is no hex amount
+        let balance = token_balance(1.5e18, "");
+        assert_eq!(balance.exact_amount().to_string(), "1.5");
+        assert_eq!(token_balance(-1.0, "0x").exact_amount().to_f64(), 0.0);
+    }
+
+    const ADDRESS: &str = "0xa749cdefd2d9590549df709bbffec04a9bd35b42";
+
+    fn chains(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
 
     #[tokio::test]
     async fn test_total_balance() {
-        dotenv().ok();
-        let access_key = env::var("DEBANK_KEY").expect("Debank access key not exist");
-        let dapi = DebankOpenAPI::new("https://pro-openapi.debank.com/v1", access_key.as_str());
-
-        let ids = vec![
-            "eth".to_string(),
-            "bsc".to_string(),
-            "heco".to_string(),
-            "ftm".to_string(),
-            "matic".to_string(),
-            "avax".to_string(),
-        ];
-        let res = dapi
-            .muti_chain_balance("0xa749cdefd2d9590549df709bbffec04a9bd35b42", &ids)
+        let mock = MockServer::start();
+        let res = mock
+            .debank(VALID_KEY)
+            .muti_chain_balance(ADDRESS, &chains(&["eth", "bsc", "heco"]))
+            .await
+            .unwrap();
+        // the unsupported chains are left out of the total
+        let ids: Vec<&str> = res.chain_list.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, ["eth", "bsc"]);
+        assert_eq!(res.total_usd_value, 1500.5);
+    }
+
+    #[tokio::test]
+    async fn test_token_total_amount() {
+        let mock = MockServer::start();
+        let token_ids = HashMap::from([
+            (
+                "eth".to_string(),
+                "0xa3fb9e7e9f2d4fa0b7c5b2de1c47a0f7c0e6f8e1".to_string(),
+            ),
+            (
+                "bsc".to_string(),
+                "0x3b3a1de07439eeb04492fa64a889ee25a130cdc3".to_string(),
+            ),
+        ]);
+        let amount = mock
+            .debank(VALID_KEY)
+            .token_total_amount(ADDRESS, &token_ids)
             .await
             .unwrap();
-        println!("res is: {:?}", res)
+        assert_eq!(amount.to_string(), "123456789.12345678912345679");
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_user_lists() {
+        let mock = MockServer::start();
+        let api = mock.debank(VALID_KEY);
+        let support = chains(&["eth", "bsc"]);
+        let tokens = api.all_token_list(ADDRESS, &support).await.unwrap();
+        let symbols: Vec<&str> = tokens.iter().map(|t| t.symbol.as_str()).collect();
+        assert_eq!(symbols, ["ETH", "BNB"]);
+        let protocols = api
+            .all_simple_protocol_list(ADDRESS, &support)
+            .await
+            .unwrap();
+        assert_eq!(protocols.len(), 2);
+        assert_eq!(protocols[1].debt_usd_value, 40.0);
+        let balance = api.chain_balance(ADDRESS, "bsc").await.unwrap();
+        assert_eq!(balance.usd_value, 500.25);
+        assert_eq!(api.units().await.unwrap().balance, 99000);
+    }
+
+    #[tokio::test]
+    async fn test_error_status() {
+        let mock = MockServer::start();
+        let support = chains(&["eth"]);
+        let res = mock
+            .debank(INVALID_KEY)
+            .muti_chain_balance(ADDRESS, &support)
+            .await;
+        assert!(matches!(res, Err(DebankApiError::Unauthorized)));
+        let res = mock
+            .debank(EXHAUSTED_KEY)
+            .muti_chain_balance(ADDRESS, &support)
+            .await;
+        assert!(matches!(res, Err(DebankApiError::CapacityLimitExceeded)));
+        let res = mock
+            .debank(RATE_LIMITED_KEY)
+            .chain_balance(ADDRESS, "eth")
+            .await;
+        assert!(matches!(res, Err(DebankApiError::RateLimitExceeded)));
+        let res = mock.debank(VALID_KEY).chain_balance(ADDRESS, "heco").await;
+        assert!(matches!(res, Err(DebankApiError::NotFound)));
     }
 }
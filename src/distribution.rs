@@ -0,0 +1,85 @@
+use std::collections::BTreeMap;
+
+use chrono::Utc;
+use thiserror::Error;
+
+use crate::merkle::{to_checksum_address, Claim, MerkleDistribution, MerkleError};
+use crate::storage::distribution::DistributionClaim;
+use crate::storage::{StorageError, StorageProcessor};
+
+#[derive(Debug, Error)]
+pub enum DistributionError {
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Merkle(#[from] MerkleError),
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+    #[error("Vote snapshot not found: {0}")]
+    SnapshotNotFound(i64),
+    #[error("Distribution not found: {0}")]
+    NotFound(i64),
+}
+
+/// Builds a merkle distribution of the amounts of a vote snapshot and stores it, the exact
+/// amounts are converted into raw amounts with `decimals` decimals, the digits beyond them are
+/// truncated.
+pub async fn create_from_snapshot(
+    storage: &StorageProcessor,
+    snapshot_id: i64,
+    decimals: u32,
+) -> Result<i64, DistributionError> {
+    if storage.load_vote_snapshot(snapshot_id).await?.is_none() {
+        return Err(DistributionError::SnapshotNotFound(snapshot_id));
+    }
+    let total = storage.count_vote_snapshot_entries(snapshot_id).await?;
+    let mut balances = Vec::new();
+    for entry in storage
+        .load_vote_snapshot_entries(snapshot_id, total, 0)
+        .await?
+    {
+        let amount = entry.amount.to_raw(decimals);
+        // nothing to claim
+        if !amount.is_zero() {
+            balances.push((entry.address, amount));
+        }
+    }
+    let distribution = MerkleDistribution::build(&balances)?;
+    let id = storage
+        .save_distribution(
+            snapshot_id,
+            decimals as i32,
+            Utc::now().timestamp(),
+            &distribution,
+        )
+        .await?;
+    Ok(id)
+}
+
+pub fn to_claim(claim: &DistributionClaim) -> Result<Claim, DistributionError> {
+    Ok(Claim {
+        index: claim.idx as u64,
+        amount: claim.amount.clone(),
+        proof: serde_json::from_str(&claim.proof)?,
+    })
+}
+
+/// Loads the full tree of a stored distribution.
+pub async fn load(
+    storage: &StorageProcessor,
+    id: i64,
+) -> Result<MerkleDistribution, DistributionError> {
+    let info = storage
+        .load_distribution(id)
+        .await?
+        .ok_or(DistributionError::NotFound(id))?;
+    let mut claims = BTreeMap::new();
+    for claim in storage.load_distribution_claims(id).await? {
+        claims.insert(to_checksum_address(&claim.address)?, to_claim(&claim)?);
+    }
+    Ok(MerkleDistribution {
+        merkle_root: info.merkle_root,
+        token_total: info.token_total,
+        claims,
+    })
+}
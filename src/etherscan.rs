@@ -1,23 +1,62 @@
-use reqwest::{self, header, IntoUrl, Url};
+use std::env;
+
+use reqwest::{self, header, IntoUrl, RequestBuilder, Url};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::http_fixture::{FixtureError, FixtureMode, HttpFixtures};
+
 #[derive(Debug, Error)]
 pub enum EtherscanApiError {
     #[error("Bad status code: {0}")]
     BadStatusCode(String),
     #[error(transparent)]
     Reqwest(#[from] reqwest::Error),
+    #[error(transparent)]
+    Fixture(#[from] FixtureError),
     #[error("Rate limit exceeded")]
     RateLimitExceeded,
     #[error("Unknown error: {0}")]
     Unknown(String),
 }
 
+/// Results of a list call at most, the next call starts from the block of the last one.
+pub const PAGE_SIZE: usize = 10000;
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct NormalTransaction {
     pub time_stamp: String,
+    #[serde(default)]
+    pub block_number: String,
+    #[serde(default)]
+    pub hash: String,
+    #[serde(default)]
+    pub from: String,
+    #[serde(default)]
+    pub to: String,
+    /// wei of ether sent
+    #[serde(default)]
+    pub value: String,
+    /// `1` when the transaction reverted
+    #[serde(default)]
+    pub is_error: String,
+}
+
+/// An erc20 transfer to or from the address.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenTransferEvent {
+    pub block_number: String,
+    pub time_stamp: String,
+    pub hash: String,
+    pub from: String,
+    pub to: String,
+    pub contract_address: String,
+    /// raw units of the token
+    pub value: String,
+    pub token_decimal: String,
 }
 
 #[derive(Debug, Clone)]
@@ -27,21 +66,46 @@ pub struct EtherscanAPi {
     /// Etherscan API key
     api_key: String,
     api_url: Url,
+    fixtures: HttpFixtures,
 }
 
-
-
 impl EtherscanAPi {
     pub fn new(api_key: &str, api_url: impl IntoUrl) -> Self {
         Self {
             client: reqwest::Client::new(),
             api_key: api_key.to_string(),
             api_url: api_url.into_url().unwrap(),
+            fixtures: HttpFixtures::default(),
         }
     }
-    /// account_age will return the timestamp when the account send its first tx
-    pub async fn account_age(&self, id: &str) -> Result<i64, EtherscanApiError> {
-        let res = self
+
+    /// Builds the client with the `ETHERSCAN_KEY` api key, `ETHERSCAN_API_URL` points it to
+    /// another server than etherscan. The key may be left out when the calls are replayed.
+    pub fn from_env() -> Self {
+        let fixtures = HttpFixtures::from_env("etherscan");
+        let etherscan_key = match env::var("ETHERSCAN_KEY") {
+            Ok(key) => key,
+            Err(_) if matches!(fixtures.mode(), FixtureMode::Replay(_)) => String::new(),
+            Err(_) => panic!("no valid etherscan key"),
+        };
+        let api_url = env::var("ETHERSCAN_API_URL")
+            .unwrap_or_else(|_| "https://api.etherscan.io/api".to_string());
+        Self::new(&etherscan_key, api_url).with_fixtures(fixtures)
+    }
+
+    /// Records or replays the calls, the api key is redacted from the fixtures.
+    pub fn with_fixtures(mut self, fixtures: HttpFixtures) -> Self {
+        self.fixtures = fixtures.with_secret(&self.api_key);
+        self
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<reqwest::Response, EtherscanApiError> {
+        self.fixtures.send(&self.client, request).await
+    }
+    /// account_age will return the timestamp when the account send its first tx, `None` when
+    /// it never sent one.
+    pub async fn account_age(&self, id: &str) -> Result<Option<i64>, EtherscanApiError> {
+        let request = self
             .client
             .get(self.api_url.clone())
             .header(header::ACCEPT, "application/json")
@@ -54,14 +118,15 @@ impl EtherscanAPi {
                 ("page", "1"),
                 ("offset", "1"),
                 ("sort", "asc"),
-                ("api_key", self.api_key.as_str()),
-            ])
-            .send()
+                ("apikey", self.api_key.as_str()),
+            ]);
+        let res = self
+            .send(request)
             .await?
             .json::<ResponseData<Vec<NormalTransaction>>>()
             .await?;
         match res {
-            ResponseData::Error { result, .. } => {
+            ResponseData::Error { result } => {
                 if result.starts_with("Max rate limit reached") {
                     Err(EtherscanApiError::RateLimitExceeded)
                 } else {
@@ -69,18 +134,111 @@ impl EtherscanAPi {
                 }
             }
             ResponseData::Success(res) => match res.status.as_str() {
-                "0" => Ok(i64::MAX),
-                "1" => Ok(res.result[0].time_stamp.parse::<i64>().unwrap()),
+                "0" => Ok(None),
+                "1" => Ok(Some(res.result[0].time_stamp.parse::<i64>().unwrap())),
                 err => Err(EtherscanApiError::BadStatusCode(err.to_string())),
             },
         }
     }
+
+    /// get the transactions of the address from `start_block`, the oldest first.
+    pub async fn normal_transactions(
+        &self,
+        address: &str,
+        start_block: i64,
+    ) -> Result<Vec<NormalTransaction>, EtherscanApiError> {
+        self.account_list("txlist", address, start_block).await
+    }
+
+    /// get the ether sent to or from the address by contract calls from `start_block`, the
+    /// oldest first. `hash` is the one of the transaction the call is part of.
+    pub async fn internal_transactions(
+        &self,
+        address: &str,
+        start_block: i64,
+    ) -> Result<Vec<NormalTransaction>, EtherscanApiError> {
+        self.account_list("txlistinternal", address, start_block)
+            .await
+    }
+
+    /// get the erc20 transfers of the address from `start_block`, the oldest first.
+    pub async fn token_transfers(
+        &self,
+        address: &str,
+        start_block: i64,
+    ) -> Result<Vec<TokenTransferEvent>, EtherscanApiError> {
+        self.account_list("tokentx", address, start_block).await
+    }
+
+    async fn account_list<T: DeserializeOwned>(
+        &self,
+        action: &str,
+        address: &str,
+        start_block: i64,
+    ) -> Result<Vec<T>, EtherscanApiError> {
+        let start_block = start_block.to_string();
+        let offset = PAGE_SIZE.to_string();
+        let request = self
+            .client
+            .get(self.api_url.clone())
+            .header(header::ACCEPT, "application/json")
+            .query(&[
+                ("module", "account"),
+                ("action", action),
+                ("address", address),
+                ("startblock", start_block.as_str()),
+                ("endblock", "99999999"),
+                ("page", "1"),
+                ("offset", offset.as_str()),
+                ("sort", "asc"),
+                ("apikey", self.api_key.as_str()),
+            ]);
+        let res = self
+            .send(request)
+            .await?
+            .json::<ResponseData<Vec<T>>>()
+            .await?;
+        match res {
+            ResponseData::Error { result } => {
+                if result.starts_with("Max rate limit reached") {
+                    Err(EtherscanApiError::RateLimitExceeded)
+                } else {
+                    Err(EtherscanApiError::Unknown(result))
+                }
+            }
+            // `0` with an empty list when nothing was found
+            ResponseData::Success(res) => match res.status.as_str() {
+                "0" | "1" => Ok(res.result),
+                err => Err(EtherscanApiError::BadStatusCode(err.to_string())),
+            },
+        }
+    }
+
+    /// Checks the api key with the cheapest call, the total supply of ether.
+    pub async fn check_key(&self) -> Result<(), EtherscanApiError> {
+        let request = self
+            .client
+            .get(self.api_url.clone())
+            .header(header::ACCEPT, "application/json")
+            .query(&[
+                ("module", "stats"),
+                ("action", "ethsupply"),
+                ("apikey", self.api_key.as_str()),
+            ]);
+        let res = self.send(request).await?.json::<Response<String>>().await?;
+        match res.status.as_str() {
+            "1" => Ok(()),
+            _ if res.result.starts_with("Max rate limit reached") => {
+                Err(EtherscanApiError::RateLimitExceeded)
+            }
+            _ => Err(EtherscanApiError::Unknown(res.result)),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Response<T> {
     pub status: String,
-    pub message: String,
     pub result: T,
 }
 
@@ -88,27 +246,57 @@ pub struct Response<T> {
 #[serde(untagged)]
 pub enum ResponseData<T> {
     Success(Response<T>),
-    Error {
-        status: String,
-        message: String,
-        result: String,
-    },
+    Error { result: String },
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mock::{MockServer, INVALID_KEY, RATE_LIMITED_KEY, VALID_KEY};
+
+    const ACTIVE_ADDRESS: &str = "0xddbd2b932c763ba5b1b7ae3b362eac3e8d40121a";
 
     #[tokio::test]
     async fn test_account_age() {
-        let apic = EtherscanAPi::new(
-            "9B2BTDM982MDSPHABFNVMIGMD1RJI4U6IJ",
-            "https://api.etherscan.io/api",
-        );
-        let k = apic
-            .account_age("0xddbd2b932c763ba5b1b7ae3b362eac3e8d40121a")
+        let mock = MockServer::start();
+        let api = mock.etherscan(VALID_KEY);
+        let age = api.account_age(ACTIVE_ADDRESS).await.unwrap();
+        assert_eq!(age, Some(1506669224));
+        // an address without transaction has never been active
+        let age = api
+            .account_age("0x0000000000000000000000000000000000000001")
             .await
             .unwrap();
-        println!("k is: {}", k)
+        assert_eq!(age, None);
+        assert!(mock.requests()[0].contains("apikey=valid-key"));
+    }
+
+    #[tokio::test]
+    async fn test_account_age_errors() {
+        let mock = MockServer::start();
+        let res = mock
+            .etherscan(RATE_LIMITED_KEY)
+            .account_age(ACTIVE_ADDRESS)
+            .await;
+        assert!(matches!(res, Err(EtherscanApiError::RateLimitExceeded)));
+        let res = mock
+            .etherscan(INVALID_KEY)
+            .account_age(ACTIVE_ADDRESS)
+            .await;
+        assert!(matches!(res, Err(EtherscanApiError::Unknown(msg)) if msg == "Invalid API Key"));
+    }
+
+    #[tokio::test]
+    async fn test_check_key() {
+        let mock = MockServer::start();
+        assert!(mock.etherscan(VALID_KEY).check_key().await.is_ok());
+        assert!(matches!(
+            mock.etherscan(INVALID_KEY).check_key().await,
+            Err(EtherscanApiError::Unknown(_))
+        ));
+        assert!(matches!(
+            mock.etherscan(RATE_LIMITED_KEY).check_key().await,
+            Err(EtherscanApiError::RateLimitExceeded)
+        ));
     }
 }
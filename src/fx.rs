@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use axum::http::{HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::Utc;
+use reqwest::{IntoUrl, Url};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::RwLock;
+use utoipa::ToSchema;
+
+pub const BASE_CURRENCY: &str = "USD";
+
+#[derive(Debug, Error)]
+pub enum FxError {
+    #[error(transparent)]
+    Reqwest(#[from] reqwest::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+    #[error("Unsupported currency: {0}")]
+    UnsupportedCurrency(String),
+}
+
+/// Exchange rates against usd, `rates` maps a currency code to its amount for one usd.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FxRates {
+    pub timestamp: i64,
+    pub rates: HashMap<String, f64>,
+}
+
+/// The rate applied to a response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FxRate {
+    pub currency: String,
+    pub rate: f64,
+    pub timestamp: i64,
+}
+
+#[async_trait]
+pub trait FxProvider: Send + Sync {
+    async fn usd_rates(&self) -> Result<FxRates, FxError>;
+}
+
+/// Serves the rates of a local json file, used for tests and offline runs.
+pub struct StaticFxProvider {
+    rates: FxRates,
+}
+
+impl StaticFxProvider {
+    pub fn from_json(json: &str) -> Result<Self, FxError> {
+        Ok(Self {
+            rates: serde_json::from_str(json)?,
+        })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, FxError> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+}
+
+#[async_trait]
+impl FxProvider for StaticFxProvider {
+    async fn usd_rates(&self) -> Result<FxRates, FxError> {
+        Ok(self.rates.clone())
+    }
+}
+
+/// Fetches the rates from an http endpoint returning the same shape as [`FxRates`].
+pub struct HttpFxProvider {
+    client: reqwest::Client,
+    url: Url,
+}
+
+impl HttpFxProvider {
+    pub fn new(url: impl IntoUrl) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.into_url().unwrap(),
+        }
+    }
+}
+
+#[async_trait]
+impl FxProvider for HttpFxProvider {
+    async fn usd_rates(&self) -> Result<FxRates, FxError> {
+        let res = self
+            .client
+            .get(self.url.clone())
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<FxRates>()
+            .await?;
+        Ok(res)
+    }
+}
+
+/// Keeps the rates of the inner provider for `ttl` before fetching them again.
+pub struct CachedFxProvider {
+    inner: Box<dyn FxProvider>,
+    ttl: Duration,
+    cache: RwLock<Option<(Instant, FxRates)>>,
+}
+
+impl CachedFxProvider {
+    pub fn new(inner: Box<dyn FxProvider>, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            cache: RwLock::new(None),
+        }
+    }
+
+    /// Builds the provider from `FX_PROVIDER` (`http` or `static`), reading the rates from
+    /// `FX_RATES_URL` or `FX_RATES_FILE` and caching them for `FX_CACHE_SECS`, 600 by default.
+    /// There is no default provider, the fixture rates must never be served by accident.
+    pub fn from_env() -> Self {
+        let provider = env::var("FX_PROVIDER").expect("FX_PROVIDER must be set to http or static");
+        let inner: Box<dyn FxProvider> = match provider.as_str() {
+            "http" => Box::new(HttpFxProvider::new(
+                env::var("FX_RATES_URL").expect("FX_RATES_URL must be set"),
+            )),
+            "static" => Box::new(
+                StaticFxProvider::from_file(
+                    env::var("FX_RATES_FILE").expect("FX_RATES_FILE must be set"),
+                )
+                .expect("fail to load the fx rates file"),
+            ),
+            other => panic!("unknown fx provider: {}", other),
+        };
+        let ttl = match env::var("FX_CACHE_SECS") {
+            Ok(secs) => secs.parse().expect("FX_CACHE_SECS must be a number"),
+            Err(_) => 600,
+        };
+        Self::new(inner, Duration::from_secs(ttl))
+    }
+
+    async fn usd_rates(&self) -> Result<FxRates, FxError> {
+        if let Some((fetched_at, rates)) = self.cache.read().await.as_ref() {
+            if fetched_at.elapsed() < self.ttl {
+                return Ok(rates.clone());
+            }
+        }
+        let rates = self.inner.usd_rates().await?;
+        *self.cache.write().await = Some((Instant::now(), rates.clone()));
+        Ok(rates)
+    }
+
+    /// get the rate to convert usd values into `currency`, usd itself is always supported.
+    pub async fn rate(&self, currency: Option<&str>) -> Result<FxRate, FxError> {
+        let currency = currency.unwrap_or(BASE_CURRENCY).to_uppercase();
+        if currency == BASE_CURRENCY {
+            return Ok(FxRate {
+                currency,
+                rate: 1.0,
+                timestamp: Utc::now().timestamp(),
+            });
+        }
+        let rates = self.usd_rates().await?;
+        match rates.rates.get(&currency) {
+            Some(rate) => Ok(FxRate {
+                currency,
+                rate: *rate,
+                timestamp: rates.timestamp,
+            }),
+            None => Err(FxError::UnsupportedCurrency(currency)),
+        }
+    }
+}
+
+/// Values which are denominated in usd and can be converted into another currency.
+pub trait FiatValue {
+    fn convert(&mut self, rate: f64);
+}
+
+impl<T: FiatValue> FiatValue for Vec<T> {
+    fn convert(&mut self, rate: f64) {
+        self.iter_mut().for_each(|v| v.convert(rate));
+    }
+}
+
+/// Json response converted with the rate. The applied rate is echoed in the `X-Fx-*` headers,
+/// and a value converted out of usd is wrapped in a [`FiatBody`] so the rate is kept with it.
+pub struct Fiat<T>(pub T, pub FxRate);
+
+/// Body of a response converted out of usd.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FiatBody<T> {
+    pub data: T,
+    pub fx: FxRate,
+}
+
+impl<T: FiatValue> Fiat<T> {
+    pub fn new(mut value: T, rate: FxRate) -> Self {
+        value.convert(rate.rate);
+        Self(value, rate)
+    }
+}
+
+impl FxRate {
+    /// Whether the values were converted out of usd.
+    pub fn is_converted(&self) -> bool {
+        self.currency != BASE_CURRENCY
+    }
+
+    /// The `X-Fx-*` headers echoing the rate.
+    pub fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Ok(currency) = HeaderValue::from_str(&self.currency) {
+            headers.insert("x-fx-currency", currency);
+        }
+        if let Ok(value) = HeaderValue::from_str(&self.rate.to_string()) {
+            headers.insert("x-fx-rate", value);
+        }
+        headers.insert("x-fx-timestamp", HeaderValue::from(self.timestamp));
+        headers
+    }
+}
+
+impl<T: Serialize> IntoResponse for Fiat<T> {
+    fn into_response(self) -> Response {
+        let Fiat(data, rate) = self;
+        let headers = rate.headers();
+        if rate.is_converted() {
+            (headers, Json(FiatBody { data, fx: rate })).into_response()
+        } else {
+            (headers, Json(data)).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Value(f64);
+
+    impl FiatValue for Value {
+        fn convert(&mut self, rate: f64) {
+            self.0 *= rate;
+        }
+    }
+
+    fn provider() -> CachedFxProvider {
+        let inner = StaticFxProvider::from_json(include_str!("../fixtures/fx_rates.json")).unwrap();
+        CachedFxProvider::new(Box::new(inner), Duration::from_secs(60))
+    }
+
+    #[tokio::test]
+    async fn test_rate() {
+        let fx = provider();
+        let eur = fx.rate(Some("eur")).await.unwrap();
+        assert_eq!(eur.currency, "EUR");
+        assert_eq!(eur.rate, 0.98);
+        assert_eq!(eur.timestamp, 1666051200);
+
+        assert_eq!(fx.rate(None).await.unwrap().rate, 1.0);
+        assert!(matches!(
+            fx.rate(Some("XYZ")).await,
+            Err(FxError::UnsupportedCurrency(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_convert() {
+        let fx = provider();
+        let cny = fx.rate(Some("CNY")).await.unwrap();
+        let Fiat(values, _) = Fiat::new(vec![Value(1.0), Value(2.0)], cny);
+        assert_eq!(values[0].0, 7.2);
+        assert_eq!(values[1].0, 14.4);
+    }
+
+    #[tokio::test]
+    async fn test_fiat_body() {
+        let fx = provider();
+        let body = |resp: Response| async {
+            let bytes = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&bytes).unwrap()
+        };
+        let eur = fx.rate(Some("EUR")).await.unwrap();
+        let resp = Fiat(vec![1.0], eur).into_response();
+        assert_eq!(resp.headers()["x-fx-currency"], "EUR");
+        let json = body(resp).await;
+        assert_eq!(json["data"][0], 1.0);
+        assert_eq!(json["fx"]["currency"], "EUR");
+        assert_eq!(json["fx"]["rate"], 0.98);
+        // usd values keep their shape
+        let usd = fx.rate(None).await.unwrap();
+        assert_eq!(body(Fiat(vec![1.0], usd).into_response()).await[0], 1.0);
+    }
+}
@@ -0,0 +1,354 @@
+//! Record and replay of the calls the debank and etherscan clients make.
+//!
+//! In record mode every call goes out and its response is written next to the request to a json
+//! file of the fixture directory, in replay mode the calls are answered from those files without
+//! touching the network. The secrets of a client are replaced by [`REDACTED`] in the files and
+//! in the request a response is matched on, so recordings made with one key replay with any
+//! other.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use hyper::http;
+use reqwest::{Client, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+pub const REDACTED: &str = "<redacted>";
+/// Query parameters carrying a key, redacted by name so the requests match whatever key, even
+/// none, the replaying client has.
+const SECRET_PARAMS: &[&str] = &["apikey"];
+
+#[derive(Debug, Error)]
+pub enum FixtureError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+    #[error("No recorded response for {0}")]
+    Missing(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum FixtureMode {
+    /// the calls go out, nothing is written
+    #[default]
+    Live,
+    /// the calls go out and their responses are written to the directory
+    Record(PathBuf),
+    /// the calls are answered from the directory
+    Replay(PathBuf),
+}
+
+/// A recorded call, the body is kept as json when it parses so the large payloads stay
+/// editable by hand.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Fixture {
+    pub method: String,
+    /// path and query of the request
+    pub request: String,
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+impl Fixture {
+    fn body(&self) -> Result<Vec<u8>, FixtureError> {
+        Ok(match (&self.json, &self.text) {
+            (Some(json), _) => serde_json::to_vec(json)?,
+            (None, Some(text)) => text.clone().into_bytes(),
+            (None, None) => Vec::new(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HttpFixtures {
+    mode: FixtureMode,
+    secrets: Vec<String>,
+}
+
+impl HttpFixtures {
+    pub fn new(mode: FixtureMode) -> Self {
+        Self {
+            mode,
+            secrets: Vec::new(),
+        }
+    }
+
+    /// Mode of `HTTP_FIXTURES`, `live` by default, `record` or `replay` on the `client`
+    /// subdirectory of `HTTP_FIXTURES_DIR`.
+    pub fn from_env(client: &str) -> Self {
+        let dir = PathBuf::from(
+            env::var("HTTP_FIXTURES_DIR").unwrap_or_else(|_| "fixtures/recorded".into()),
+        )
+        .join(client);
+        let mode = match env::var("HTTP_FIXTURES").as_deref() {
+            Err(_) | Ok("live") => FixtureMode::Live,
+            Ok("record") => FixtureMode::Record(dir),
+            Ok("replay") => FixtureMode::Replay(dir),
+            Ok(other) => panic!("unknown http fixtures mode: {}", other),
+        };
+        Self::new(mode)
+    }
+
+    /// Redacts `secret` from the recorded requests and responses.
+    pub fn with_secret(mut self, secret: &str) -> Self {
+        if !secret.is_empty() {
+            self.secrets.push(secret.to_string());
+        }
+        self
+    }
+
+    pub fn mode(&self) -> &FixtureMode {
+        &self.mode
+    }
+
+    fn redact(&self, text: &str) -> String {
+        self.secrets.iter().fold(text.to_string(), |text, secret| {
+            text.replace(secret, REDACTED)
+        })
+    }
+
+    /// Sends `request` built on `client` in live and record mode, answers it from its fixture
+    /// in replay mode.
+    pub async fn send<E>(&self, client: &Client, request: RequestBuilder) -> Result<Response, E>
+    where
+        E: From<reqwest::Error> + From<FixtureError>,
+    {
+        let request = request.build()?;
+        let dir = match &self.mode {
+            FixtureMode::Live => return Ok(client.execute(request).await?),
+            FixtureMode::Record(dir) | FixtureMode::Replay(dir) => dir,
+        };
+        let method = request.method().to_string();
+        let url = request.url();
+        let target = self.redact(&match url.query() {
+            Some(query) => format!("{}?{}", url.path(), redact_query(query)),
+            None => url.path().to_string(),
+        });
+        let path = fixture_path(dir, &method, &target);
+        if let FixtureMode::Replay(_) = self.mode {
+            let text = match std::fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    return Err(FixtureError::Missing(format!("{} {}", method, target)).into())
+                }
+                Err(e) => return Err(FixtureError::from(e).into()),
+            };
+            let fixture: Fixture = serde_json::from_str(&text).map_err(FixtureError::from)?;
+            return Ok(response(fixture.status, fixture.body()?));
+        }
+
+        let resp = client.execute(request).await?;
+        let status = resp.status().as_u16();
+        let text = self.redact(&resp.text().await?);
+        let (json, text) = match serde_json::from_str(&text) {
+            Ok(json) => (Some(json), None),
+            Err(_) => (None, Some(text)),
+        };
+        let fixture = Fixture {
+            method,
+            request: target,
+            status,
+            json,
+            text,
+        };
+        let body = fixture.body()?;
+        write_fixture(&path, &fixture)?;
+        tracing::debug!(
+            "recorded {} {} to {}",
+            fixture.method,
+            fixture.request,
+            path.display()
+        );
+        Ok(response(status, body))
+    }
+}
+
+/// The query with the value of the secret parameters replaced by [`REDACTED`].
+fn redact_query(query: &str) -> String {
+    query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _)) if SECRET_PARAMS.contains(&name) => format!("{}={}", name, REDACTED),
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// `<path>-<hash>.json`, the hash tells apart the calls of an endpoint.
+fn fixture_path(dir: &Path, method: &str, target: &str) -> PathBuf {
+    let name = target
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .trim_matches('/')
+        .replace('/', "_");
+    let hash = Sha256::digest(format!("{} {}", method, target).as_bytes());
+    dir.join(format!("{}-{}.json", name, &hex::encode(hash)[..16]))
+}
+
+fn write_fixture(path: &Path, fixture: &Fixture) -> Result<(), FixtureError> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(fixture)?)?;
+    Ok(())
+}
+
+fn response(status: u16, body: Vec<u8>) -> Response {
+    http::Response::builder()
+        .status(status)
+        .body(body)
+        .expect("a recorded status is valid")
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::debank::openapi::{DebankApiError, DebankOpenAPI};
+    use crate::etherscan::EtherscanAPi;
+    use crate::mock::{MockServer, INVALID_KEY, VALID_KEY};
+
+    const ADDRESS: &str = "0xa749cdefd2d9590549df709bbffec04a9bd35b42";
+    const ACTIVE_ADDRESS: &str = "0xddbd2b932c763ba5b1b7ae3b362eac3e8d40121a";
+    /// nothing listens there, a replayed call that went out would fail
+    const OFFLINE_URL: &str = "http://127.0.0.1:9/";
+
+    /// A fixture directory of the test, removed with it.
+    struct FixtureDir(PathBuf);
+
+    impl FixtureDir {
+        fn new() -> Self {
+            static DIRS: AtomicUsize = AtomicUsize::new(0);
+            Self(std::env::temp_dir().join(format!(
+                "zportfolio-fixtures-{}-{}",
+                std::process::id(),
+                DIRS.fetch_add(1, Ordering::SeqCst)
+            )))
+        }
+
+        fn files(&self) -> Vec<String> {
+            std::fs::read_dir(&self.0)
+                .unwrap()
+                .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+                .collect()
+        }
+    }
+
+    impl Drop for FixtureDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_debank_record_and_replay() {
+        let mock = MockServer::start();
+        let dir = FixtureDir::new();
+        let chains = vec!["eth".to_string(), "bsc".to_string()];
+        let recorder = mock
+            .debank(VALID_KEY)
+            .with_fixtures(HttpFixtures::new(FixtureMode::Record(dir.0.clone())));
+        let recorded = recorder.muti_chain_balance(ADDRESS, &chains).await.unwrap();
+        let units = recorder.units().await.unwrap();
+        assert_eq!(mock.requests().len(), 2);
+        let files = dir.files();
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().all(|file| !file.contains(VALID_KEY)));
+
+        let replayer = DebankOpenAPI::new(OFFLINE_URL, "another-key")
+            .with_fixtures(HttpFixtures::new(FixtureMode::Replay(dir.0.clone())));
+        let replayed = replayer.muti_chain_balance(ADDRESS, &chains).await.unwrap();
+        assert_eq!(replayed, recorded);
+        assert_eq!(replayer.units().await.unwrap().balance, units.balance);
+        assert_eq!(mock.requests().len(), 2);
+        // a call never recorded isn't sent either
+        assert!(matches!(
+            replayer.chain_balance(ADDRESS, "eth").await,
+            Err(DebankApiError::Fixture(FixtureError::Missing(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_record_error_responses() {
+        let mock = MockServer::start();
+        let dir = FixtureDir::new();
+        let res = mock
+            .debank(INVALID_KEY)
+            .with_fixtures(HttpFixtures::new(FixtureMode::Record(dir.0.clone())))
+            .units()
+            .await;
+        assert!(matches!(res, Err(DebankApiError::Unauthorized)));
+        let res = DebankOpenAPI::new(OFFLINE_URL, VALID_KEY)
+            .with_fixtures(HttpFixtures::new(FixtureMode::Replay(dir.0.clone())))
+            .units()
+            .await;
+        assert!(matches!(res, Err(DebankApiError::Unauthorized)));
+    }
+
+    #[tokio::test]
+    async fn test_etherscan_record_and_replay() {
+        let mock = MockServer::start();
+        let dir = FixtureDir::new();
+        let age = mock
+            .etherscan(VALID_KEY)
+            .with_fixtures(HttpFixtures::new(FixtureMode::Record(dir.0.clone())))
+            .account_age(ACTIVE_ADDRESS)
+            .await
+            .unwrap();
+        let files = dir.files();
+        // the key is sent in the query, the recorded request has it redacted
+        assert!(files[0].contains(&format!("apikey={}", REDACTED)));
+        assert!(!files[0].contains(VALID_KEY));
+
+        let replayed = EtherscanAPi::new("another-key", format!("{}api", OFFLINE_URL))
+            .with_fixtures(HttpFixtures::new(FixtureMode::Replay(dir.0.clone())))
+            .account_age(ACTIVE_ADDRESS)
+            .await
+            .unwrap();
+        assert_eq!(replayed, age);
+        // replayed without a key, as `from_env` allows
+        let replayed = EtherscanAPi::new("", format!("{}api", OFFLINE_URL))
+            .with_fixtures(HttpFixtures::new(FixtureMode::Replay(dir.0.clone())))
+            .account_age(ACTIVE_ADDRESS)
+            .await
+            .unwrap();
+        assert_eq!(replayed, age);
+    }
+
+    #[test]
+    fn test_redact_query() {
+        assert_eq!(
+            redact_query("module=account&apikey=abc&page=1"),
+            format!("module=account&apikey={}&page=1", REDACTED)
+        );
+        assert_eq!(
+            redact_query("apikey=&sort=asc"),
+            format!("apikey={}&sort=asc", REDACTED)
+        );
+        assert_eq!(redact_query("id=0x1"), "id=0x1");
+    }
+
+    #[test]
+    fn test_fixture_path() {
+        let dir = Path::new("fixtures");
+        let path = fixture_path(dir, "GET", "/v1/user/total_balance?id=0x1");
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("v1_user_total_balance-"));
+        assert!(name.ends_with(".json"));
+        assert_ne!(
+            path,
+            fixture_path(dir, "GET", "/v1/user/total_balance?id=0x2")
+        );
+    }
+}
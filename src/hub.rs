@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use thiserror::Error;
+use tokio::sync::{broadcast, Notify};
+
+use crate::debank::openapi::DebankTotalBalance;
+
+/// Updates buffered for a slow subscriber before it starts missing some.
+const TOPIC_CAPACITY: usize = 16;
+
+#[derive(Debug, Error)]
+pub enum HubError {
+    #[error("Too many watched addresses, at most {0}")]
+    TooManyTopics(usize),
+}
+
+/// Balance of an address pushed to its subscribers.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BalanceUpdate {
+    pub address: String,
+    pub updated_at: i64,
+    pub balance: DebankTotalBalance,
+}
+
+/// Updates of an address with its last known balance.
+pub type Subscription = (
+    broadcast::Receiver<Arc<BalanceUpdate>>,
+    Option<Arc<BalanceUpdate>>,
+);
+
+struct Topic {
+    tx: broadcast::Sender<Arc<BalanceUpdate>>,
+    last: Option<Arc<BalanceUpdate>>,
+}
+
+/// Fan-out of the balance updates, the subscribers of an address share one topic so the
+/// address is polled once however many clients watch it.
+#[derive(Clone)]
+pub struct BalanceHub {
+    topics: Arc<Mutex<HashMap<String, Topic>>>,
+    /// wakes the refresher up when an address without snapshot is watched
+    pending: Arc<Notify>,
+    max_topics: usize,
+}
+
+impl BalanceHub {
+    pub fn new(max_topics: usize) -> Self {
+        Self {
+            topics: Default::default(),
+            pending: Default::default(),
+            max_topics,
+        }
+    }
+
+    /// Subscribes to the updates of the lowercase address, with its last known balance.
+    pub fn subscribe(&self, address: &str) -> Result<Subscription, HubError> {
+        let mut topics = self.topics.lock().unwrap();
+        if let Some(topic) = topics.get(address) {
+            return Ok((topic.tx.subscribe(), topic.last.clone()));
+        }
+        // the topics left by every subscriber are only dropped by the next refresh
+        topics.retain(|_, t| t.tx.receiver_count() > 0);
+        if topics.len() >= self.max_topics {
+            return Err(HubError::TooManyTopics(self.max_topics));
+        }
+        let (tx, rx) = broadcast::channel(TOPIC_CAPACITY);
+        topics.insert(address.to_string(), Topic { tx, last: None });
+        self.pending.notify_one();
+        Ok((rx, None))
+    }
+
+    /// Drops the topics without subscriber and returns the watched addresses, only the ones
+    /// without snapshot yet when `pending_only`.
+    pub fn watched(&self, pending_only: bool) -> Vec<String> {
+        let mut topics = self.topics.lock().unwrap();
+        topics.retain(|_, t| t.tx.receiver_count() > 0);
+        topics
+            .iter()
+            .filter(|(_, t)| !pending_only || t.last.is_none())
+            .map(|(address, _)| address.clone())
+            .collect()
+    }
+
+    /// Waits until an address without snapshot is watched.
+    pub async fn wait_pending(&self) {
+        self.pending.notified().await
+    }
+
+    /// Pushes the balance to the subscribers of the address when it changed, returns whether
+    /// it was pushed.
+    pub fn publish(&self, address: &str, balance: DebankTotalBalance, updated_at: i64) -> bool {
+        let mut topics = self.topics.lock().unwrap();
+        let topic = match topics.get_mut(address) {
+            Some(topic) => topic,
+            None => return false,
+        };
+        if topic.last.as_ref().is_some_and(|l| l.balance == balance) {
+            return false;
+        }
+        let update = Arc::new(BalanceUpdate {
+            address: address.to_string(),
+            updated_at,
+            balance,
+        });
+        topic.last = Some(update.clone());
+        // no receiver left is not an error, the topic is dropped by the next refresh
+        let _ = topic.tx.send(update);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn balance(usd: f64) -> DebankTotalBalance {
+        serde_json::from_value(serde_json::json!({
+            "total_usd_value": usd,
+            "chain_list": [],
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_fan_out() {
+        let hub = BalanceHub::new(10);
+        let (mut first, last) = hub.subscribe("0xabc").unwrap();
+        assert!(last.is_none());
+        let (mut second, _) = hub.subscribe("0xabc").unwrap();
+        assert_eq!(hub.watched(true), vec!["0xabc"]);
+
+        assert!(hub.publish("0xabc", balance(1.0), 1));
+        // an unchanged balance is not pushed again
+        assert!(!hub.publish("0xabc", balance(1.0), 2));
+        assert!(hub.publish("0xabc", balance(2.0), 3));
+        assert_eq!(first.recv().await.unwrap().updated_at, 1);
+        assert_eq!(first.recv().await.unwrap().updated_at, 3);
+        assert_eq!(second.recv().await.unwrap().updated_at, 1);
+        assert!(hub.watched(true).is_empty());
+
+        // a late subscriber gets the last snapshot
+        let (_third, last) = hub.subscribe("0xabc").unwrap();
+        assert_eq!(last.unwrap().updated_at, 3);
+        assert!(!hub.publish("0xdef", balance(1.0), 4));
+    }
+
+    #[test]
+    fn test_topic_limit() {
+        let hub = BalanceHub::new(1);
+        let (rx, _) = hub.subscribe("0xabc").unwrap();
+        assert!(hub.subscribe("0xdef").is_err());
+        drop(rx);
+        // the topic left by its subscribers makes room
+        let _rx = hub.subscribe("0xdef").unwrap();
+        assert_eq!(hub.watched(false), vec!["0xdef"]);
+    }
+}
@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use chrono::Utc;
+
+use crate::account::{self, AccountError};
+use crate::etherscan::EtherscanAPi;
+use crate::storage::StorageProcessor;
+
+/// Addresses checked again by a run at most, etherscan rate limits the key.
+const BATCH_SIZE: i64 = 100;
+
+/// Checks again the addresses found without transaction more than `recheck_secs` ago, the
+/// longest unchecked first. Returns the number of addresses found active since.
+pub async fn refresh_inactive(
+    storage: &StorageProcessor,
+    acc_api: &EtherscanAPi,
+    recheck_secs: i64,
+) -> Result<usize, AccountError> {
+    let before = Utc::now().timestamp() - recheck_secs;
+    let mut activated = 0;
+    for address in storage
+        .load_stale_inactive_users(before, BATCH_SIZE)
+        .await?
+    {
+        // the first error stops the run, the addresses left wait for the next one
+        if account::check_activation_time(storage, acc_api, &address)
+            .await?
+            .is_some()
+        {
+            activated += 1;
+        }
+    }
+    Ok(activated)
+}
+
+/// Runs the recheck periodically in the background.
+pub fn spawn(
+    storage: StorageProcessor,
+    acc_api: EtherscanAPi,
+    recheck_secs: i64,
+    period: Duration,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            match refresh_inactive(&storage, &acc_api, recheck_secs).await {
+                Ok(activated) => tracing::info!("{} inactive accounts became active", activated),
+                Err(e) => tracing::error!("inactive account refresh failed: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use chrono::Utc;
+
+use crate::alert::{self, AlertError, AlertEvent, AlertKind, ThresholdMode, WebhookSender};
+use crate::debank::openapi::DebankOpenAPI;
+use crate::storage::alert::AlertRule;
+use crate::storage::StorageProcessor;
+
+/// Current value watched by the rule.
+pub async fn observe(
+    ass_api: &DebankOpenAPI,
+    chains: &[String],
+    rule: &AlertRule,
+) -> Result<f64, AlertError> {
+    let value = match rule.kind.parse()? {
+        AlertKind::TotalUsd => {
+            ass_api
+                .muti_chain_balance(&rule.address, chains)
+                .await?
+                .total_usd_value
+        }
+        AlertKind::TokenAmount => {
+            let (chain, token_id) = rule
+                .chain
+                .as_deref()
+                .zip(rule.token_id.as_deref())
+                .ok_or(AlertError::MissingToken)?;
+            ass_api
+                .token_balance(&rule.address, chain, token_id)
+                .await?
+                .amount
+        }
+    };
+    Ok(value)
+}
+
+/// Observes every enabled rule and compares with its previous observation, the triggered
+/// webhooks are delivered in the background so their retries do not hold the evaluation.
+pub async fn evaluate(
+    storage: &StorageProcessor,
+    ass_api: &DebankOpenAPI,
+    sender: &WebhookSender,
+) -> Result<usize, AlertError> {
+    let chains = storage.load_support_chain_ids().await?;
+    let mut triggered = 0;
+    for rule in storage.load_enabled_alert_rules().await? {
+        let current = match observe(ass_api, &chains, &rule).await {
+            Ok(value) => value,
+            Err(e) => {
+                tracing::warn!("failed to observe alert {}: {}", rule.id, e);
+                continue;
+            }
+        };
+        let observed_at = Utc::now().timestamp();
+        storage
+            .save_alert_observation(rule.id, current, observed_at)
+            .await?;
+        let previous = match rule.last_value {
+            Some(previous) => previous,
+            None => continue,
+        };
+        let mode: ThresholdMode = rule.threshold_mode.parse()?;
+        if !alert::is_triggered(previous, current, rule.threshold, mode) {
+            continue;
+        }
+        triggered += 1;
+        let event = AlertEvent {
+            rule_id: rule.id,
+            address: rule.address.clone(),
+            kind: rule.kind.parse()?,
+            chain: rule.chain.clone(),
+            token_id: rule.token_id.clone(),
+            previous,
+            current,
+            observed_at,
+        };
+        let (storage, sender) = (storage.clone(), sender.clone());
+        tokio::spawn(async move {
+            match alert::deliver(&storage, &sender, &rule, &event).await {
+                Ok(true) => {}
+                Ok(false) => tracing::warn!("alert {} was not delivered", rule.id),
+                Err(e) => tracing::error!("failed to deliver alert {}: {}", rule.id, e),
+            }
+        });
+    }
+    Ok(triggered)
+}
+
+/// Evaluates the alert rules periodically in the background.
+pub fn spawn(
+    storage: StorageProcessor,
+    ass_api: DebankOpenAPI,
+    sender: WebhookSender,
+    period: Duration,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            match evaluate(&storage, &ass_api, &sender).await {
+                Ok(triggered) => tracing::info!("alerts evaluated, {} triggered", triggered),
+                Err(e) => tracing::error!("alert evaluation failed: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use chrono::Utc;
+
+use crate::api::SupportChains;
+use crate::debank::openapi::DebankOpenAPI;
+use crate::hub::BalanceHub;
+
+/// Polls the balance of the addresses once for all their subscribers.
+pub async fn refresh(
+    hub: &BalanceHub,
+    ass_api: &DebankOpenAPI,
+    chains: &[String],
+    addresses: Vec<String>,
+) -> usize {
+    let mut pushed = 0;
+    for address in addresses {
+        match ass_api.muti_chain_balance(&address, chains).await {
+            Ok(balance) => {
+                if hub.publish(&address, balance, Utc::now().timestamp()) {
+                    pushed += 1;
+                }
+            }
+            Err(e) => tracing::warn!("failed to refresh the balance of {}: {}", address, e),
+        }
+    }
+    pushed
+}
+
+/// Refreshes the watched addresses periodically, the newly watched ones right away.
+pub fn spawn(
+    hub: BalanceHub,
+    ass_api: DebankOpenAPI,
+    support_chains: SupportChains,
+    period: Duration,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            let pending_only = tokio::select! {
+                _ = interval.tick() => false,
+                _ = hub.wait_pending() => true,
+            };
+            let addresses = hub.watched(pending_only);
+            if addresses.is_empty() {
+                continue;
+            }
+            let pushed = refresh(&hub, &ass_api, &support_chains.get(), addresses).await;
+            tracing::debug!("balances refreshed, {} updates pushed", pushed);
+        }
+    });
+}
@@ -0,0 +1,6 @@
+pub mod account_refresher;
+pub mod alert_evaluator;
+pub mod balance_refresher;
+pub mod price_backfill;
+pub mod token_sync;
+pub mod vote_leaderboard;
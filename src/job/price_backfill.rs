@@ -0,0 +1,87 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use thiserror::Error;
+
+use crate::price::{PriceError, PriceProvider, SECONDS_PER_DAY};
+use crate::storage::{price::TokenPrice, StorageError, StorageProcessor};
+
+#[derive(Debug, Error)]
+pub enum BackfillError {
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Price(#[from] PriceError),
+}
+
+/// Fills the daily prices of every stored token for the last `days` complete days, days which
+/// already have a stored price are skipped. The days the provider has no price for, before the
+/// listing of the token, are recorded and not asked again. The prices of a token stop at the
+/// first day that fails otherwise so the next run starts again from it.
+pub async fn backfill_prices(
+    storage: &StorageProcessor,
+    provider: &dyn PriceProvider,
+    days: i64,
+) -> Result<usize, BackfillError> {
+    let today = Utc::now().timestamp() / SECONDS_PER_DAY * SECONDS_PER_DAY;
+    let earliest = today - days * SECONDS_PER_DAY;
+    let mut saved = 0;
+    for token in storage.load_tokens().await? {
+        let latest = storage
+            .load_latest_price_timestamp(&token.chain, &token.id)
+            .await?
+            .max(
+                storage
+                    .load_latest_missing_price_timestamp(&token.chain, &token.id)
+                    .await?,
+            );
+        let start = match latest {
+            Some(latest) => earliest.max(latest + SECONDS_PER_DAY),
+            None => earliest,
+        };
+        let mut prices = Vec::new();
+        let mut missing = Vec::new();
+        let mut day = start;
+        // the price of today is only stored once the day is over, the next run starts after
+        // the newest stored day
+        while day < today {
+            match provider.daily_price(&token.chain, &token.id, day).await {
+                Ok(price) => prices.push(TokenPrice {
+                    chain: token.chain.clone(),
+                    token_id: token.id.clone(),
+                    timestamp: day,
+                    price,
+                }),
+                Err(e) if e.is_missing() => missing.push(day),
+                // a gap would never be filled since the next run starts after the newest
+                // stored day, the other tokens are still backfilled
+                Err(e) => {
+                    tracing::warn!("no price for {} on {}: {}", token.id, token.chain, e);
+                    break;
+                }
+            }
+            day += SECONDS_PER_DAY;
+        }
+        storage.save_token_prices(&prices).await?;
+        storage
+            .save_missing_prices(&token.chain, &token.id, &missing)
+            .await?;
+        saved += prices.len();
+    }
+    Ok(saved)
+}
+
+/// Runs the backfill once a day in the background.
+pub fn spawn(storage: StorageProcessor, provider: Arc<dyn PriceProvider>, days: i64) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(SECONDS_PER_DAY as u64));
+        loop {
+            interval.tick().await;
+            match backfill_prices(&storage, provider.as_ref(), days).await {
+                Ok(saved) => tracing::info!("price backfill stored {} prices", saved),
+                Err(e) => tracing::error!("price backfill failed: {}", e),
+            }
+        }
+    });
+}
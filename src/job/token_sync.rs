@@ -0,0 +1,248 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::debank::openapi::{DebankApiError, DebankChain, DebankOpenAPI, DebankToken};
+use crate::storage::{chain::ChainInfo, token::TokenInfo, StorageError, StorageProcessor};
+
+pub const SYNC_NAME: &str = "token_catalog";
+
+/// Max ids accepted by the debank `list_by_ids` endpoint.
+const TOKEN_BATCH_SIZE: usize = 100;
+
+#[derive(Debug, Error)]
+pub enum SyncError {
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Debank(#[from] DebankApiError),
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SyncReport {
+    pub chains_inserted: usize,
+    pub chains_updated: usize,
+    pub tokens_inserted: usize,
+    pub tokens_updated: usize,
+    pub tokens_unchanged: usize,
+    pub tokens_deleted: usize,
+}
+
+/// Changes needed to make the stored tokens match the fetched ones.
+#[derive(Debug, Default, PartialEq)]
+pub struct TokenDiff {
+    pub insert: Vec<TokenInfo>,
+    pub update: Vec<TokenInfo>,
+    pub delete: Vec<TokenInfo>,
+    pub unchanged: usize,
+}
+
+impl From<DebankChain> for ChainInfo {
+    fn from(chain: DebankChain) -> Self {
+        Self {
+            id: chain.id,
+            community_id: chain.community_id as i32,
+            name: chain.name,
+            native_token_id: chain.native_token_id,
+            logo_url: chain.logo_url.unwrap_or_default(),
+        }
+    }
+}
+
+impl From<DebankToken> for TokenInfo {
+    fn from(token: DebankToken) -> Self {
+        Self {
+            id: token.id,
+            chain: token.chain,
+            name: token.name,
+            symbol: token.symbol,
+            decimals: token.decimals as i32,
+            logo_url: token.logo_url.unwrap_or_default(),
+            protocol_id: token.protocol_id,
+            is_core: token.is_core,
+        }
+    }
+}
+
+/// Compares the stored tokens with the fetched ones, a stored token missing upstream is deleted
+/// unless it is `protected`.
+pub fn diff_tokens(
+    stored: &[TokenInfo],
+    fetched: Vec<TokenInfo>,
+    protected: &HashSet<(String, String)>,
+) -> TokenDiff {
+    let stored_by_key: BTreeMap<(&str, &str), &TokenInfo> = stored
+        .iter()
+        .map(|t| ((t.chain.as_str(), t.id.as_str()), t))
+        .collect();
+    let mut diff = TokenDiff::default();
+    let mut seen = BTreeSet::new();
+    for token in fetched {
+        seen.insert((token.chain.clone(), token.id.clone()));
+        match stored_by_key.get(&(token.chain.as_str(), token.id.as_str())) {
+            Some(old) if **old == token => diff.unchanged += 1,
+            Some(_) => diff.update.push(token),
+            None => diff.insert.push(token),
+        }
+    }
+    for token in stored {
+        let key = (token.chain.clone(), token.id.clone());
+        if !seen.contains(&key) && !protected.contains(&key) {
+            diff.delete.push(token.clone());
+        }
+    }
+    diff
+}
+
+/// Syncs the `chain` and `token` tables with the debank metadata.
+///
+/// The synced tokens are the stored ones, the vote tokens and the native token of each chain.
+/// The tokens referenced by a vote token, disabled or not, and the ones created through the
+/// admin api are never deleted. The chains new to the catalog are stored disabled, an admin
+/// enables the ones to support.
+pub async fn sync_catalog(
+    storage: &StorageProcessor,
+    ass_api: &DebankOpenAPI,
+) -> Result<SyncReport, SyncError> {
+    let mut report = SyncReport::default();
+
+    let stored_chains = storage.load_chains().await?;
+    let chains: Vec<ChainInfo> = ass_api
+        .chain_list()
+        .await?
+        .into_iter()
+        .map(ChainInfo::from)
+        .collect();
+    for chain in &chains {
+        match stored_chains.iter().find(|c| c.id == chain.id) {
+            Some(old) if old == chain => {}
+            Some(_) => {
+                storage.update_chain(chain).await?;
+                report.chains_updated += 1;
+            }
+            None => {
+                storage.insert_chain(chain, true).await?;
+                report.chains_inserted += 1;
+            }
+        }
+    }
+
+    let mut protected: HashSet<(String, String)> = storage
+        .load_vote_token_references()
+        .await?
+        .into_iter()
+        .collect();
+    protected.extend(storage.load_admin_created_tokens().await?);
+    let stored_tokens = storage.load_tokens().await?;
+
+    let mut wanted = BTreeMap::<String, BTreeSet<String>>::new();
+    for chain in &chains {
+        wanted
+            .entry(chain.id.clone())
+            .or_default()
+            .insert(chain.native_token_id.clone());
+    }
+    for (chain, token_id) in stored_tokens
+        .iter()
+        .map(|t| (&t.chain, &t.id))
+        .chain(protected.iter().map(|(c, t)| (c, t)))
+    {
+        wanted
+            .entry(chain.clone())
+            .or_default()
+            .insert(token_id.clone());
+    }
+
+    let mut fetched = Vec::new();
+    for (chain, token_ids) in wanted {
+        let token_ids: Vec<String> = token_ids.into_iter().collect();
+        for batch in token_ids.chunks(TOKEN_BATCH_SIZE) {
+            let tokens = ass_api.token_list_by_ids(&chain, batch).await?;
+            fetched.extend(tokens.into_iter().map(TokenInfo::from));
+        }
+    }
+
+    let diff = diff_tokens(&stored_tokens, fetched, &protected);
+    for token in &diff.insert {
+        storage.insert_token(token, false).await?;
+    }
+    for token in &diff.update {
+        storage.update_token(token).await?;
+    }
+    for token in &diff.delete {
+        storage.delete_token(&token.chain, &token.id).await?;
+    }
+    report.tokens_inserted = diff.insert.len();
+    report.tokens_updated = diff.update.len();
+    report.tokens_deleted = diff.delete.len();
+    report.tokens_unchanged = diff.unchanged;
+
+    storage
+        .save_last_sync_time(SYNC_NAME, Utc::now().timestamp())
+        .await?;
+    Ok(report)
+}
+
+/// Runs the sync periodically in the background, the first run waits for a full period after
+/// the last recorded sync so restarts do not resync right away.
+pub fn spawn(storage: StorageProcessor, ass_api: DebankOpenAPI, period: Duration) {
+    tokio::spawn(async move {
+        if let Ok(Some(last)) = storage.load_last_sync_time(SYNC_NAME).await {
+            let elapsed = (Utc::now().timestamp() - last).max(0) as u64;
+            if elapsed < period.as_secs() {
+                tokio::time::sleep(Duration::from_secs(period.as_secs() - elapsed)).await;
+            }
+        }
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            match sync_catalog(&storage, &ass_api).await {
+                Ok(report) => tracing::info!("token catalog synced: {:?}", report),
+                Err(e) => tracing::error!("token catalog sync failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(chain: &str, id: &str, symbol: &str) -> TokenInfo {
+        TokenInfo {
+            id: id.to_string(),
+            chain: chain.to_string(),
+            name: symbol.to_string(),
+            symbol: symbol.to_string(),
+            decimals: 18,
+            logo_url: String::new(),
+            protocol_id: String::new(),
+            is_core: true,
+        }
+    }
+
+    #[test]
+    fn test_diff_tokens() {
+        let stored = vec![
+            token("eth", "0x1", "AAA"),
+            token("eth", "0x2", "BBB"),
+            token("eth", "0x3", "CCC"),
+            token("bsc", "0x4", "DDD"),
+        ];
+        let fetched = vec![
+            token("eth", "0x1", "AAA"),
+            token("eth", "0x2", "BBB2"),
+            token("eth", "0x5", "EEE"),
+        ];
+        let protected = HashSet::from([("bsc".to_string(), "0x4".to_string())]);
+        let diff = diff_tokens(&stored, fetched, &protected);
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.update, vec![token("eth", "0x2", "BBB2")]);
+        assert_eq!(diff.insert, vec![token("eth", "0x5", "EEE")]);
+        // 0x4 is missing upstream but referenced by a vote token
+        assert_eq!(diff.delete, vec![token("eth", "0x3", "CCC")]);
+    }
+}
@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use thiserror::Error;
+
+use crate::amount::Amount;
+use crate::debank::openapi::{DebankApiError, DebankOpenAPI};
+use crate::storage::{vote::VoteSnapshotEntry, StorageError, StorageProcessor};
+
+#[derive(Debug, Error)]
+pub enum LeaderboardError {
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Debank(#[from] DebankApiError),
+    #[error("Unknown vote token: {0}")]
+    UnknownToken(String),
+}
+
+/// Ranks the amounts from the largest, equal amounts share the same rank and are ordered by
+/// address so the result is deterministic. The exact amounts are compared, two holders a wei
+/// apart do not share a rank even though their floats are equal.
+pub fn rank(mut amounts: Vec<(String, Amount)>) -> Vec<VoteSnapshotEntry> {
+    amounts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let mut entries: Vec<VoteSnapshotEntry> = Vec::with_capacity(amounts.len());
+    let mut prev: Option<Amount> = None;
+    for (i, (address, amount)) in amounts.into_iter().enumerate() {
+        let rank = match (prev, entries.last()) {
+            (Some(prev), Some(last)) if prev == amount => last.rank,
+            _ => i as i64 + 1,
+        };
+        prev = Some(amount);
+        entries.push(VoteSnapshotEntry {
+            rank,
+            address,
+            amount,
+        });
+    }
+    entries
+}
+
+/// Computes the amount of every tracked address of the vote token and stores the ranked
+/// result as a new snapshot. An address debank fails on is logged and left out, the snapshot
+/// is only given up when no address could be fetched.
+pub async fn compute_leaderboard(
+    storage: &StorageProcessor,
+    ass_api: &DebankOpenAPI,
+    vote_token: &str,
+) -> Result<i64, LeaderboardError> {
+    let token_ids = storage
+        .load_token_ids_by_name(vote_token.to_string())
+        .await?
+        .ok_or_else(|| LeaderboardError::UnknownToken(vote_token.to_string()))?;
+    let mut amounts = Vec::new();
+    let mut last_error = None;
+    for address in storage.load_vote_addresses(vote_token).await? {
+        match ass_api.token_total_amount(&address, &token_ids).await {
+            Ok(amount) => amounts.push((address.to_lowercase(), amount)),
+            Err(e) => {
+                tracing::warn!(
+                    "skip {} in the leaderboard of {}: {}",
+                    address,
+                    vote_token,
+                    e
+                );
+                last_error = Some(e);
+            }
+        }
+    }
+    if let (true, Some(e)) = (amounts.is_empty(), last_error) {
+        return Err(e.into());
+    }
+    let id = storage
+        .save_vote_snapshot(vote_token, Utc::now().timestamp(), &rank(amounts))
+        .await?;
+    Ok(id)
+}
+
+/// Recomputes the leaderboard of every vote token periodically in the background.
+pub fn spawn(storage: StorageProcessor, ass_api: DebankOpenAPI, period: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            let vote_tokens = match storage.load_vote_token_names().await {
+                Ok(names) => names,
+                Err(e) => {
+                    tracing::error!("fail to load the vote tokens: {}", e);
+                    continue;
+                }
+            };
+            for vote_token in vote_tokens {
+                match compute_leaderboard(&storage, &ass_api, &vote_token).await {
+                    Ok(id) => tracing::info!("stored leaderboard {} of {}", id, vote_token),
+                    Err(e) => tracing::error!("leaderboard of {} failed: {}", vote_token, e),
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use primitive_types::U256;
+
+    use super::*;
+
+    fn amount(raw: u64) -> Amount {
+        Amount::new(U256::from(raw) * U256::exp10(17), 18)
+    }
+
+    #[test]
+    fn test_rank() {
+        let amounts = vec![
+            ("0xc".to_string(), amount(50)),
+            ("0xa".to_string(), amount(100)),
+            ("0xd".to_string(), amount(10)),
+            ("0xb".to_string(), Amount::new(U256::from(5), 0)),
+        ];
+        let res: Vec<(i64, String)> = rank(amounts)
+            .into_iter()
+            .map(|e| (e.rank, e.address))
+            .collect();
+        assert_eq!(
+            res,
+            vec![
+                (1, "0xa".to_string()),
+                (2, "0xb".to_string()),
+                (2, "0xc".to_string()),
+                (4, "0xd".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rank_exact() {
+        let whale = Amount::new(U256::exp10(27), 18);
+        let amounts = vec![
+            ("0xa".to_string(), whale),
+            ("0xb".to_string(), whale + Amount::new(U256::one(), 18)),
+        ];
+        let entries = rank(amounts);
+        // the floats are equal, the ranks and the stored amounts are not
+        assert_eq!(entries[0].amount.to_f64(), entries[1].amount.to_f64());
+        assert_eq!(
+            entries[0].amount.to_string(),
+            "1000000000.000000000000000001"
+        );
+        assert_eq!(entries[0].address, "0xb");
+        assert_eq!((entries[0].rank, entries[1].rank), (1, 2));
+    }
+}
@@ -1,13 +1,33 @@
+mod account;
+mod alert;
+mod amount;
 mod api;
+mod cli;
 mod etherscan;
 
 mod debank;
+mod distribution;
+mod fx;
+mod http_fixture;
+mod hub;
+mod job;
+mod merkle;
+#[cfg(test)]
+mod mock;
+mod pnl;
+mod portfolio;
+mod price;
+mod rpc;
+mod siwe;
 mod storage;
+mod transfer;
+mod vote;
 
+use clap::Parser;
 use tracing_subscriber::{self, layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
-async fn main() {
+async fn main() -> anyhow::Result<()> {
     tracing_subscriber::registry()
         .with(tracing_subscriber::EnvFilter::new(
             std::env::var("RUST_LOG")
@@ -15,6 +35,5 @@ async fn main() {
         ))
         .with(tracing_subscriber::fmt::layer())
         .init();
-    tracing::info!("start the api server");
-    api::start_server().await;
+    cli::run(cli::Cli::parse()).await
 }
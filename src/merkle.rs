@@ -0,0 +1,299 @@
+use std::collections::BTreeMap;
+
+use primitive_types::U256;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+pub type Hash = [u8; 32];
+
+#[derive(Debug, Error)]
+pub enum MerkleError {
+    #[error("Invalid address: {0}")]
+    InvalidAddress(String),
+    #[error("Invalid amount: {0}")]
+    InvalidAmount(String),
+    #[error("Duplicated address: {0}")]
+    DuplicatedAddress(String),
+}
+
+pub fn keccak256(data: &[u8]) -> Hash {
+    Keccak256::digest(data).into()
+}
+
+pub fn to_hex(data: &[u8]) -> String {
+    format!("0x{}", hex::encode(data))
+}
+
+fn parse_address(address: &str) -> Result<[u8; 20], MerkleError> {
+    let invalid = || MerkleError::InvalidAddress(address.to_string());
+    let bytes = hex::decode(address.trim_start_matches("0x")).map_err(|_| invalid())?;
+    bytes.try_into().map_err(|_| invalid())
+}
+
+/// Formats the address with the EIP-55 mixed case checksum.
+pub fn to_checksum_address(address: &str) -> Result<String, MerkleError> {
+    let lower = hex::encode(parse_address(address)?);
+    let hash = hex::encode(keccak256(lower.as_bytes()));
+    let checksummed: String = lower
+        .chars()
+        .zip(hash.chars())
+        .map(|(c, h)| {
+            if h.to_digit(16).unwrap_or(0) >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect();
+    Ok(format!("0x{}", checksummed))
+}
+
+/// Converts a decimal token amount like `12.5` into its raw integer with `decimals` decimals,
+/// digits beyond `decimals` are truncated.
+pub fn parse_units(amount: &str, decimals: u32) -> Result<U256, MerkleError> {
+    let invalid = || MerkleError::InvalidAmount(amount.to_string());
+    let (int, frac) = amount.split_once('.').unwrap_or((amount, ""));
+    if !int.chars().chain(frac.chars()).all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let frac = &frac[..frac.len().min(decimals as usize)];
+    let digits = format!("{}{:0<width$}", int, frac, width = decimals as usize);
+    let digits = digits.trim_start_matches('0');
+    if digits.is_empty() {
+        return Ok(U256::zero());
+    }
+    U256::from_dec_str(digits).map_err(|_| invalid())
+}
+
+/// Formats a raw integer amount with `decimals` decimals, the inverse of `parse_units`
+/// without trailing zeros.
+pub fn format_units(raw: U256, decimals: u32) -> String {
+    let digits = format!(
+        "{:0>width$}",
+        raw.to_string(),
+        width = decimals as usize + 1
+    );
+    let (int, frac) = digits.split_at(digits.len() - decimals as usize);
+    let frac = frac.trim_end_matches('0');
+    if frac.is_empty() {
+        int.to_string()
+    } else {
+        format!("{}.{}", int, frac)
+    }
+}
+
+/// Leaf of the uniswap `MerkleDistributor`: `keccak256(abi.encodePacked(index, account, amount))`.
+pub fn distributor_leaf(index: u64, address: &str, amount: U256) -> Result<Hash, MerkleError> {
+    let mut packed = Vec::with_capacity(84);
+    let mut word = [0u8; 32];
+    U256::from(index).to_big_endian(&mut word);
+    packed.extend_from_slice(&word);
+    packed.extend_from_slice(&parse_address(address)?);
+    amount.to_big_endian(&mut word);
+    packed.extend_from_slice(&word);
+    Ok(keccak256(&packed))
+}
+
+fn hash_pair(a: &Hash, b: &Hash) -> Hash {
+    let (first, second) = if a <= b { (a, b) } else { (b, a) };
+    let mut data = [0u8; 64];
+    data[..32].copy_from_slice(first);
+    data[32..].copy_from_slice(second);
+    keccak256(&data)
+}
+
+/// Merkle tree hashing sorted pairs, verifiable by openzeppelin's `MerkleProof`.
+///
+/// The leaves are sorted and deduplicated, a node without sibling is promoted to the next layer
+/// unchanged, the same as the uniswap distributor scripts.
+pub struct MerkleTree {
+    layers: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    pub fn new(mut leaves: Vec<Hash>) -> Self {
+        leaves.sort();
+        leaves.dedup();
+        let mut layers = vec![leaves];
+        while layers.last().is_some_and(|l| l.len() > 1) {
+            let next = layers
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [a, b] => hash_pair(a, b),
+                    [a] => *a,
+                    _ => unreachable!(),
+                })
+                .collect();
+            layers.push(next);
+        }
+        Self { layers }
+    }
+
+    pub fn root(&self) -> Hash {
+        self.layers
+            .last()
+            .and_then(|l| l.first())
+            .copied()
+            .unwrap_or([0u8; 32])
+    }
+
+    pub fn proof(&self, leaf: &Hash) -> Option<Vec<Hash>> {
+        let mut index = self.layers[0].binary_search(leaf).ok()?;
+        let mut proof = Vec::new();
+        for layer in &self.layers[..self.layers.len() - 1] {
+            let sibling = index ^ 1;
+            if let Some(hash) = layer.get(sibling) {
+                proof.push(*hash);
+            }
+            index /= 2;
+        }
+        Some(proof)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Claim {
+    pub index: u64,
+    /// hex encoded raw amount
+    pub amount: String,
+    pub proof: Vec<String>,
+}
+
+/// Same json layout as the output of the uniswap `generate-merkle-root` script.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MerkleDistribution {
+    pub merkle_root: String,
+    pub token_total: String,
+    /// keyed by the checksummed address
+    pub claims: BTreeMap<String, Claim>,
+}
+
+impl MerkleDistribution {
+    /// Builds the distribution, the indexes follow the order of the checksummed addresses.
+    pub fn build(balances: &[(String, U256)]) -> Result<Self, MerkleError> {
+        let mut sorted = BTreeMap::new();
+        for (address, amount) in balances {
+            let address = to_checksum_address(address)?;
+            if sorted.insert(address.clone(), *amount).is_some() {
+                return Err(MerkleError::DuplicatedAddress(address));
+            }
+        }
+        let mut leaves = Vec::with_capacity(sorted.len());
+        for (index, (address, amount)) in sorted.iter().enumerate() {
+            leaves.push(distributor_leaf(index as u64, address, *amount)?);
+        }
+        let tree = MerkleTree::new(leaves.clone());
+
+        let mut claims = BTreeMap::new();
+        let mut token_total = U256::zero();
+        for (index, ((address, amount), leaf)) in sorted.into_iter().zip(leaves).enumerate() {
+            token_total += amount;
+            let proof = tree.proof(&leaf).unwrap_or_default();
+            claims.insert(
+                address,
+                Claim {
+                    index: index as u64,
+                    amount: format!("{:#x}", amount),
+                    proof: proof.iter().map(|p| to_hex(p)).collect(),
+                },
+            );
+        }
+        Ok(Self {
+            merkle_root: to_hex(&tree.root()),
+            token_total: format!("{:#x}", token_total),
+            claims,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Same check as openzeppelin's `MerkleProof.verify`.
+    fn verify(proof: &[Hash], root: &Hash, leaf: &Hash) -> bool {
+        proof.iter().fold(*leaf, |acc, p| hash_pair(&acc, p)) == *root
+    }
+
+    #[test]
+    fn test_keccak256() {
+        assert_eq!(
+            to_hex(&keccak256(b"")),
+            "0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+    }
+
+    #[test]
+    fn test_checksum_address() {
+        assert_eq!(
+            to_checksum_address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").unwrap(),
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"
+        );
+        assert!(to_checksum_address("0x1234").is_err());
+    }
+
+    #[test]
+    fn test_parse_units() {
+        assert_eq!(parse_units("1", 18).unwrap(), U256::exp10(18));
+        assert_eq!(parse_units("12.5", 2).unwrap(), U256::from(1250));
+        assert_eq!(parse_units("0.000", 6).unwrap(), U256::zero());
+        assert_eq!(parse_units("0.019", 2).unwrap(), U256::one());
+        assert!(parse_units("1e5", 2).is_err());
+    }
+
+    #[test]
+    fn test_format_units() {
+        assert_eq!(format_units(U256::exp10(18), 18), "1");
+        assert_eq!(format_units(U256::from(1250), 2), "12.5");
+        assert_eq!(format_units(U256::from(5), 3), "0.005");
+        assert_eq!(format_units(U256::zero(), 18), "0");
+        assert_eq!(format_units(U256::from(42), 0), "42");
+        let raw = parse_units("123456789.123456789123456789", 18).unwrap();
+        assert_eq!(format_units(raw, 18), "123456789.123456789123456789");
+    }
+
+    #[test]
+    fn test_distribution_proofs() {
+        let balances: Vec<(String, U256)> = (1..=5u64)
+            .map(|i| (format!("0x{:040x}", i), U256::from(i * 100)))
+            .collect();
+        let distribution = MerkleDistribution::build(&balances).unwrap();
+        assert_eq!(distribution.token_total, "0x5dc");
+        assert_eq!(distribution.claims.len(), 5);
+
+        let root: Hash = hex::decode(&distribution.merkle_root[2..])
+            .unwrap()
+            .try_into()
+            .unwrap();
+        for (address, claim) in &distribution.claims {
+            let amount = U256::from_str_radix(&claim.amount[2..], 16).unwrap();
+            let leaf = distributor_leaf(claim.index, address, amount).unwrap();
+            let proof: Vec<Hash> = claim
+                .proof
+                .iter()
+                .map(|p| hex::decode(&p[2..]).unwrap().try_into().unwrap())
+                .collect();
+            assert!(verify(&proof, &root, &leaf));
+        }
+    }
+
+    #[test]
+    fn test_duplicated_address() {
+        let balances = vec![
+            (
+                "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed".to_string(),
+                U256::one(),
+            ),
+            (
+                "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed".to_string(),
+                U256::one(),
+            ),
+        ];
+        assert!(MerkleDistribution::build(&balances).is_err());
+    }
+}
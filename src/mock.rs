@@ -0,0 +1,265 @@
+//! In-process stand-in of the debank and etherscan apis for the tests, answering from the json
+//! fixtures of `fixtures/debank` and `fixtures/etherscan`.
+//!
+//! The debank fixture of an address holds the answer of every `/v1/user/*` endpoint under the
+//! endpoint name, nested by `chain_id` then `token_id` for the endpoints taking them, the tokens
+//! of `/v1/token*` are nested by chain then id in `debank/token.json` and the chains of
+//! `/v1/chain/list` are listed in `debank/chain.json`. The errors are chosen with the key:
+//! `INVALID_KEY`, `EXHAUSTED_KEY` and `RATE_LIMITED_KEY` get the status or the body the real
+//! apis answer with.
+
+use std::collections::HashMap;
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::{json, Value};
+
+use crate::debank::openapi::DebankOpenAPI;
+use crate::etherscan::EtherscanAPi;
+
+pub const VALID_KEY: &str = "valid-key";
+/// debank answers 401, etherscan `Invalid API Key`
+pub const INVALID_KEY: &str = "invalid-key";
+/// debank answers 403 once the units of the key are spent
+pub const EXHAUSTED_KEY: &str = "exhausted-key";
+/// debank answers 429, etherscan `Max rate limit reached`
+pub const RATE_LIMITED_KEY: &str = "rate-limited-key";
+
+#[derive(Clone)]
+struct MockState {
+    fixtures: PathBuf,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockState {
+    fn fixture(&self, path: impl AsRef<FsPath>) -> Option<Value> {
+        let text = std::fs::read_to_string(self.fixtures.join(path)).ok()?;
+        Some(serde_json::from_str(&text).expect("fixtures are valid json"))
+    }
+
+    fn record(&self, uri: &Uri) {
+        self.requests.lock().unwrap().push(uri.to_string());
+    }
+}
+
+pub struct MockServer {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockServer {
+    /// Serves the fixtures on a free local port until the runtime of the test stops.
+    pub fn start() -> Self {
+        let state = MockState {
+            fixtures: PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures")),
+            requests: Default::default(),
+        };
+        let requests = state.requests.clone();
+        let app = Router::with_state(state)
+            .route("/v1/user/:endpoint", get(debank_user))
+            .route("/v1/account/units", get(debank_units))
+            .route("/v1/chain/list", get(debank_chain_list))
+            .route("/v1/token", get(debank_token))
+            .route("/v1/token/list_by_ids", get(debank_token_list))
+            .route("/api", get(etherscan))
+            .route("/webhook", post(webhook));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+        Self { addr, requests }
+    }
+
+    pub fn debank_url(&self) -> String {
+        format!("http://{}/v1", self.addr)
+    }
+
+    pub fn etherscan_url(&self) -> String {
+        format!("http://{}/api", self.addr)
+    }
+
+    /// Accepts every delivery, they are recorded like the requests.
+    pub fn webhook_url(&self) -> String {
+        format!("http://{}/webhook", self.addr)
+    }
+
+    pub fn debank(&self, key: &str) -> DebankOpenAPI {
+        DebankOpenAPI::new(self.debank_url(), key)
+    }
+
+    pub fn etherscan(&self, key: &str) -> EtherscanAPi {
+        EtherscanAPi::new(key, self.etherscan_url())
+    }
+
+    /// Path and query of every request received so far.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn not_found(what: &str) -> Response {
+    let body = json!({ "error_code": 404, "message": format!("{} not found", what) });
+    (StatusCode::NOT_FOUND, Json(body)).into_response()
+}
+
+fn debank_key_status(headers: &HeaderMap) -> Option<StatusCode> {
+    match headers.get("AccessKey").and_then(|v| v.to_str().ok()) {
+        Some(VALID_KEY) => None,
+        Some(EXHAUSTED_KEY) => Some(StatusCode::FORBIDDEN),
+        Some(RATE_LIMITED_KEY) => Some(StatusCode::TOO_MANY_REQUESTS),
+        _ => Some(StatusCode::UNAUTHORIZED),
+    }
+}
+
+async fn debank_user(
+    State(state): State<MockState>,
+    Path(endpoint): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> Response {
+    state.record(&uri);
+    if let Some(status) = debank_key_status(&headers) {
+        return status.into_response();
+    }
+    let id = params
+        .get("id")
+        .map(|id| id.to_lowercase())
+        .unwrap_or_default();
+    let Some(mut fixture) = state.fixture(format!("debank/{}.json", id)) else {
+        return not_found(&id);
+    };
+    let mut node = fixture[&endpoint].take();
+    for param in ["chain_id", "token_id"] {
+        if let Some(key) = params.get(param) {
+            node = node[key].take();
+        }
+    }
+    if node.is_null() {
+        return not_found(&uri.to_string());
+    }
+    Json(node).into_response()
+}
+
+async fn debank_units(State(state): State<MockState>, headers: HeaderMap, uri: Uri) -> Response {
+    state.record(&uri);
+    if let Some(status) = debank_key_status(&headers) {
+        return status.into_response();
+    }
+    let account = state.fixture("debank/account.json").unwrap_or_default();
+    Json(account["units"].clone()).into_response()
+}
+
+async fn debank_chain_list(
+    State(state): State<MockState>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> Response {
+    state.record(&uri);
+    if let Some(status) = debank_key_status(&headers) {
+        return status.into_response();
+    }
+    Json(state.fixture("debank/chain.json").unwrap_or_default()).into_response()
+}
+
+fn debank_tokens(state: &MockState, chain: &str, ids: &str) -> Vec<Value> {
+    let tokens = state.fixture("debank/token.json").unwrap_or_default();
+    ids.split(',')
+        .filter_map(|id| tokens[chain].get(id).cloned())
+        .collect()
+}
+
+async fn debank_token(
+    State(state): State<MockState>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> Response {
+    state.record(&uri);
+    if let Some(status) = debank_key_status(&headers) {
+        return status.into_response();
+    }
+    let param = |name: &str| params.get(name).map(String::as_str).unwrap_or_default();
+    match debank_tokens(&state, param("chain_id"), param("id")).pop() {
+        Some(token) => Json(token).into_response(),
+        None => not_found(&uri.to_string()),
+    }
+}
+
+/// The unknown ids are left out like debank does.
+async fn debank_token_list(
+    State(state): State<MockState>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> Response {
+    state.record(&uri);
+    if let Some(status) = debank_key_status(&headers) {
+        return status.into_response();
+    }
+    let param = |name: &str| params.get(name).map(String::as_str).unwrap_or_default();
+    Json(debank_tokens(&state, param("chain_id"), param("ids"))).into_response()
+}
+
+async fn webhook(State(state): State<MockState>, uri: Uri) -> StatusCode {
+    state.record(&uri);
+    StatusCode::OK
+}
+
+/// Etherscan answers every error with a 200 and a string result.
+async fn etherscan(
+    State(state): State<MockState>,
+    Query(params): Query<HashMap<String, String>>,
+    uri: Uri,
+) -> Json<Value> {
+    state.record(&uri);
+    let notok = |result: &str| Json(json!({ "status": "0", "message": "NOTOK", "result": result }));
+    match params.get("apikey").map(String::as_str) {
+        Some(VALID_KEY) => {}
+        Some(RATE_LIMITED_KEY) => {
+            return notok("Max rate limit reached, please use API Key for higher rate limit")
+        }
+        _ => return notok("Invalid API Key"),
+    }
+    let param = |name: &str| params.get(name).map(String::as_str).unwrap_or_default();
+    match (param("module"), param("action")) {
+        ("account", action @ ("txlist" | "txlistinternal" | "tokentx")) => {
+            let address = param("address").to_lowercase();
+            let txs = state
+                .fixture(format!("etherscan/{}.json", action))
+                .and_then(|mut list| list.get_mut(&address).map(Value::take))
+                .unwrap_or_else(|| json!([]));
+            let start_block: u64 = param("startblock").parse().unwrap_or_default();
+            let offset: usize = param("offset").parse().unwrap_or(usize::MAX);
+            let txs: Vec<Value> = serde_json::from_value::<Vec<Value>>(txs)
+                .unwrap()
+                .into_iter()
+                .filter(|tx| {
+                    let block = tx["blockNumber"].as_str().unwrap_or_default();
+                    block.parse::<u64>().unwrap_or_default() >= start_block
+                })
+                .take(offset.max(1))
+                .collect();
+            if txs.is_empty() {
+                Json(json!({ "status": "0", "message": "No transactions found", "result": [] }))
+            } else {
+                Json(json!({ "status": "1", "message": "OK", "result": txs }))
+            }
+        }
+        ("stats", "ethsupply") => Json(json!({
+            "status": "1",
+            "message": "OK",
+            "result": "122373866217800000000000000",
+        })),
+        _ => notok("Error! Missing Or invalid Module name"),
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::fx::FiatValue;
+
+/// Method used to match outgoing transfers against the lots acquired before them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CostBasisMethod {
+    #[default]
+    Fifo,
+    Lifo,
+    Average,
+}
+
+/// A single movement of a token, positive amount for incoming and negative for outgoing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trade {
+    pub timestamp: i64,
+    pub amount: f64,
+    /// usd price of one token at `timestamp`
+    pub price: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize, ToSchema)]
+pub struct TokenPnl {
+    /// amount still held after replaying all the trades
+    pub amount: f64,
+    /// usd cost of the amount still held
+    pub cost_basis: f64,
+    pub realized_pnl: f64,
+    pub unrealized_pnl: f64,
+}
+
+impl TokenPnl {
+    /// Values the amount still held at `current_price`.
+    pub fn revalue(&mut self, current_price: f64) {
+        self.unrealized_pnl = self.amount * current_price - self.cost_basis;
+    }
+}
+
+impl FiatValue for TokenPnl {
+    fn convert(&mut self, rate: f64) {
+        self.cost_basis *= rate;
+        self.realized_pnl *= rate;
+        self.unrealized_pnl *= rate;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Lot {
+    amount: f64,
+    price: f64,
+}
+
+/// Replays the trades in time order and computes the pnl of a single token.
+///
+/// Outgoing amount which is not covered by any earlier lot (e.g. tokens received before the
+/// recorded history starts) is treated as acquired at zero cost.
+pub fn calculate(trades: &[Trade], current_price: f64, method: CostBasisMethod) -> TokenPnl {
+    let mut ordered = trades.to_vec();
+    // stable sort, trades within the same second keep their recorded order
+    ordered.sort_by_key(|t| t.timestamp);
+
+    let mut lots = VecDeque::<Lot>::new();
+    let mut realized_pnl = 0.0;
+
+    for trade in ordered {
+        if trade.amount >= 0.0 {
+            let lot = Lot {
+                amount: trade.amount,
+                price: trade.price,
+            };
+            match method {
+                CostBasisMethod::Average => merge_into_average(&mut lots, lot),
+                _ => lots.push_back(lot),
+            }
+            continue;
+        }
+
+        let mut remaining = -trade.amount;
+        let mut cost = 0.0;
+        while remaining > 0.0 {
+            let lot = match method {
+                CostBasisMethod::Lifo => lots.back_mut(),
+                _ => lots.front_mut(),
+            };
+            let lot = match lot {
+                Some(lot) => lot,
+                None => break,
+            };
+            let used = remaining.min(lot.amount);
+            cost += used * lot.price;
+            lot.amount -= used;
+            remaining -= used;
+            if lot.amount <= 0.0 {
+                match method {
+                    CostBasisMethod::Lifo => lots.pop_back(),
+                    _ => lots.pop_front(),
+                };
+            }
+        }
+        realized_pnl += -trade.amount * trade.price - cost;
+    }
+
+    let amount: f64 = lots.iter().map(|l| l.amount).sum();
+    let cost_basis: f64 = lots.iter().map(|l| l.amount * l.price).sum();
+    let mut pnl = TokenPnl {
+        amount,
+        cost_basis,
+        realized_pnl,
+        unrealized_pnl: 0.0,
+    };
+    pnl.revalue(current_price);
+    pnl
+}
+
+fn merge_into_average(lots: &mut VecDeque<Lot>, lot: Lot) {
+    match lots.front_mut() {
+        Some(avg) => {
+            let total = avg.amount + lot.amount;
+            if total > 0.0 {
+                avg.price = (avg.amount * avg.price + lot.amount * lot.price) / total;
+            }
+            avg.amount = total;
+        }
+        None => lots.push_back(lot),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(timestamp: i64, amount: f64, price: f64) -> Trade {
+        Trade {
+            timestamp,
+            amount,
+            price,
+        }
+    }
+
+    fn history() -> Vec<Trade> {
+        vec![
+            trade(1, 10.0, 1.0),
+            trade(2, 10.0, 3.0),
+            trade(3, -15.0, 4.0),
+        ]
+    }
+
+    #[test]
+    fn test_fifo() {
+        let res = calculate(&history(), 5.0, CostBasisMethod::Fifo);
+        // sold 10 @1 and 5 @3 for 60
+        assert_eq!(res.realized_pnl, 60.0 - 25.0);
+        assert_eq!(res.amount, 5.0);
+        assert_eq!(res.cost_basis, 15.0);
+        assert_eq!(res.unrealized_pnl, 25.0 - 15.0);
+    }
+
+    #[test]
+    fn test_lifo() {
+        let res = calculate(&history(), 5.0, CostBasisMethod::Lifo);
+        // sold 10 @3 and 5 @1 for 60
+        assert_eq!(res.realized_pnl, 60.0 - 35.0);
+        assert_eq!(res.cost_basis, 5.0);
+        assert_eq!(res.unrealized_pnl, 25.0 - 5.0);
+    }
+
+    #[test]
+    fn test_average() {
+        let res = calculate(&history(), 5.0, CostBasisMethod::Average);
+        // average price is 2
+        assert_eq!(res.realized_pnl, 60.0 - 30.0);
+        assert_eq!(res.cost_basis, 10.0);
+        assert_eq!(res.unrealized_pnl, 25.0 - 10.0);
+    }
+
+    #[test]
+    fn test_unordered_and_uncovered() {
+        let trades = vec![trade(5, -4.0, 2.0), trade(1, 2.0, 1.0)];
+        let res = calculate(&trades, 3.0, CostBasisMethod::Fifo);
+        // 2 covered at price 1, 2 more treated as free
+        assert_eq!(res.realized_pnl, 8.0 - 2.0);
+        assert_eq!(res.amount, 0.0);
+        assert_eq!(res.unrealized_pnl, 0.0);
+    }
+}
@@ -0,0 +1,140 @@
+//! Response types owned by zportfolio. The providers and the storage are mapped into them so
+//! a vendor schema change does not reach the clients, the amounts and the usd values are
+//! decimal strings so they survive json parsers without loss. The same types back the rest and
+//! the graphql schemas.
+
+use async_graphql::SimpleObject;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::storage::chain::ChainInfo;
+use crate::storage::token::TokenInfo;
+
+/// Formats a float as a plain decimal string, never in scientific notation.
+pub fn decimal_string(value: f64) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "0".to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema, SimpleObject)]
+#[graphql(complex)]
+pub struct Chain {
+    pub id: String,
+    pub community_id: i64,
+    pub name: String,
+    pub native_token_id: String,
+    pub logo_url: String,
+}
+
+impl From<ChainInfo> for Chain {
+    fn from(chain: ChainInfo) -> Self {
+        Self {
+            id: chain.id,
+            community_id: chain.community_id.into(),
+            name: chain.name,
+            native_token_id: chain.native_token_id,
+            logo_url: chain.logo_url,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema, SimpleObject)]
+#[graphql(complex)]
+pub struct Token {
+    pub chain: String,
+    pub id: String,
+    pub symbol: String,
+    pub name: String,
+    pub decimals: u32,
+    pub logo_url: String,
+    pub protocol_id: String,
+    pub is_core: bool,
+}
+
+impl From<TokenInfo> for Token {
+    fn from(token: TokenInfo) -> Self {
+        Self {
+            chain: token.chain,
+            id: token.id,
+            symbol: token.symbol,
+            name: token.name,
+            decimals: token.decimals.max(0) as u32,
+            logo_url: token.logo_url,
+            protocol_id: token.protocol_id,
+            is_core: token.is_core,
+        }
+    }
+}
+
+/// Token with its current price.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct TokenQuote {
+    #[serde(flatten)]
+    pub token: Token,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price: Option<String>,
+}
+
+/// Value held on a chain.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema, SimpleObject)]
+pub struct ChainValue {
+    pub chain: String,
+    pub name: String,
+    pub logo_url: String,
+    pub usd_value: String,
+}
+
+/// Value held by an address over the supported chains.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema, SimpleObject)]
+pub struct Balance {
+    pub address: String,
+    pub total_usd_value: String,
+    pub chains: Vec<ChainValue>,
+}
+
+/// Amount of a token held by an address.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema, SimpleObject)]
+pub struct TokenBalance {
+    #[serde(flatten)]
+    pub token: Token,
+    /// amount with the decimals of the token
+    pub amount: String,
+    pub price: String,
+    pub usd_value: String,
+}
+
+/// Position of an address in a protocol.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema, SimpleObject)]
+pub struct Protocol {
+    pub id: String,
+    pub chain: String,
+    pub name: String,
+    pub site_url: String,
+    pub logo_url: String,
+    /// assets minus debts
+    pub net_usd_value: String,
+    pub asset_usd_value: String,
+    pub debt_usd_value: String,
+}
+
+/// Amount of a token summed over the chains.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct TokenAmount {
+    pub amount: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decimal_string() {
+        assert_eq!(decimal_string(1e21), "1000000000000000000000");
+        assert_eq!(decimal_string(0.000001), "0.000001");
+        assert_eq!(decimal_string(12.5), "12.5");
+        assert_eq!(decimal_string(f64::NAN), "0");
+    }
+}
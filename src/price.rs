@@ -0,0 +1,189 @@
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::debank::openapi::{DebankApiError, DebankOpenAPI};
+use crate::fx::FiatValue;
+
+pub const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+#[derive(Debug, Error)]
+pub enum PriceError {
+    #[error(transparent)]
+    Debank(#[from] DebankApiError),
+    #[error("Invalid timestamp: {0}")]
+    InvalidTimestamp(i64),
+}
+
+impl PriceError {
+    /// Whether the provider has no price for the request, asking again won't give one.
+    pub fn is_missing(&self) -> bool {
+        matches!(
+            self,
+            PriceError::Debank(DebankApiError::NotFound) | PriceError::InvalidTimestamp(_)
+        )
+    }
+}
+
+/// Source of token prices in usd.
+#[async_trait]
+pub trait PriceProvider: Send + Sync {
+    async fn current_price(&self, chain_id: &str, token_id: &str) -> Result<f64, PriceError>;
+
+    /// Price of the token on the utc day containing `timestamp`.
+    async fn daily_price(
+        &self,
+        chain_id: &str,
+        token_id: &str,
+        timestamp: i64,
+    ) -> Result<f64, PriceError>;
+}
+
+#[async_trait]
+impl PriceProvider for DebankOpenAPI {
+    async fn current_price(&self, chain_id: &str, token_id: &str) -> Result<f64, PriceError> {
+        Ok(self.token_info(chain_id, token_id).await?.price)
+    }
+
+    async fn daily_price(
+        &self,
+        chain_id: &str,
+        token_id: &str,
+        timestamp: i64,
+    ) -> Result<f64, PriceError> {
+        let date_at = Utc
+            .timestamp_opt(timestamp, 0)
+            .single()
+            .ok_or(PriceError::InvalidTimestamp(timestamp))?
+            .format("%Y-%m-%d")
+            .to_string();
+        let res = self
+            .token_history_price(chain_id, token_id, &date_at)
+            .await?;
+        Ok(res.price)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceInterval {
+    Day,
+    Week,
+}
+
+impl PriceInterval {
+    pub fn seconds(&self) -> i64 {
+        match self {
+            PriceInterval::Day => SECONDS_PER_DAY,
+            PriceInterval::Week => 7 * SECONDS_PER_DAY,
+        }
+    }
+
+    /// Start of the interval containing `timestamp`, the weeks start on monday.
+    pub fn start(&self, timestamp: i64) -> i64 {
+        // the unix epoch is a thursday, the first monday is 4 days later
+        let offset = match self {
+            PriceInterval::Day => 0,
+            PriceInterval::Week => 4 * SECONDS_PER_DAY,
+        };
+        timestamp - (timestamp - offset).rem_euclid(self.seconds())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PriceCandle {
+    /// start of the interval
+    pub timestamp: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+}
+
+impl FiatValue for PriceCandle {
+    fn convert(&mut self, rate: f64) {
+        self.open *= rate;
+        self.high *= rate;
+        self.low *= rate;
+        self.close *= rate;
+    }
+}
+
+/// Groups the price points into candles of the given interval, the points must be sorted by time.
+pub fn aggregate(points: &[(i64, f64)], interval: PriceInterval) -> Vec<PriceCandle> {
+    let mut candles: Vec<PriceCandle> = Vec::new();
+    for &(timestamp, price) in points {
+        let start = interval.start(timestamp);
+        match candles.last_mut() {
+            Some(candle) if candle.timestamp == start => {
+                candle.high = candle.high.max(price);
+                candle.low = candle.low.min(price);
+                candle.close = price;
+            }
+            _ => candles.push(PriceCandle {
+                timestamp: start,
+                open: price,
+                high: price,
+                low: price,
+                close: price,
+            }),
+        }
+    }
+    candles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aggregate() {
+        let day = SECONDS_PER_DAY;
+        let points = vec![
+            (0, 1.0),
+            (3600, 3.0),
+            (7200, 2.0),
+            (day, 5.0),
+            (day + 10, 4.0),
+            (3 * day, 6.0),
+        ];
+        let res = aggregate(&points, PriceInterval::Day);
+        assert_eq!(res.len(), 3);
+        assert_eq!(
+            res[0],
+            PriceCandle {
+                timestamp: 0,
+                open: 1.0,
+                high: 3.0,
+                low: 1.0,
+                close: 2.0
+            }
+        );
+        assert_eq!(res[1].timestamp, day);
+        assert_eq!(res[1].close, 4.0);
+        assert_eq!(res[2].timestamp, 3 * day);
+
+        // thursday to sunday of the week started on monday 1969-12-29
+        let weekly = aggregate(&points, PriceInterval::Week);
+        assert_eq!(weekly.len(), 1);
+        assert_eq!(weekly[0].timestamp, -3 * day);
+        assert_eq!(weekly[0].close, 6.0);
+        let next_week = aggregate(&[(3 * day, 1.0), (4 * day, 2.0)], PriceInterval::Week);
+        assert_eq!(next_week.len(), 2);
+        assert_eq!(next_week[1].timestamp, 4 * day);
+        assert!(aggregate(&[], PriceInterval::Week).is_empty());
+    }
+
+    #[test]
+    fn test_week_start() {
+        let day = SECONDS_PER_DAY;
+        // monday 2022-10-17
+        let monday = 1665964800;
+        assert_eq!(PriceInterval::Week.start(monday), monday);
+        assert_eq!(PriceInterval::Week.start(monday + 6 * day + 3600), monday);
+        assert_eq!(PriceInterval::Week.start(monday - 1), monday - 7 * day);
+        assert_eq!(PriceInterval::Day.start(monday + 3600), monday);
+    }
+}
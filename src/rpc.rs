@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::env;
+
+use primitive_types::U256;
+use reqwest::{IntoUrl, Url};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RpcError {
+    #[error(transparent)]
+    Reqwest(#[from] reqwest::Error),
+    #[error("Rpc error {code}: {message}")]
+    Rpc { code: i64, message: String },
+    #[error("Invalid rpc response: {0}")]
+    InvalidResponse(String),
+    #[error("No rpc endpoint for chain: {0}")]
+    UnsupportedChain(String),
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcErrorObject {
+    code: i64,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcResponse {
+    result: Option<Value>,
+    error: Option<RpcErrorObject>,
+}
+
+/// Minimal ethereum json-rpc client, every state query is made at an explicit block so the
+/// results are reproducible.
+#[derive(Debug, Clone)]
+pub struct JsonRpcClient {
+    client: reqwest::Client,
+    url: Url,
+}
+
+impl JsonRpcClient {
+    pub fn new(url: impl IntoUrl) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.into_url().unwrap(),
+        }
+    }
+
+    /// Loads one client per chain from the `RPC_URL_<CHAIN>` variables, e.g. `RPC_URL_ETH`.
+    pub fn from_env() -> HashMap<String, JsonRpcClient> {
+        env::vars()
+            .filter_map(|(k, v)| {
+                k.strip_prefix("RPC_URL_")
+                    .map(|chain| (chain.to_lowercase(), JsonRpcClient::new(v.as_str())))
+            })
+            .collect()
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let res = self
+            .client
+            .post(self.url.clone())
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))
+            .send()
+            .await?
+            .json::<RpcResponse>()
+            .await?;
+        if let Some(err) = res.error {
+            return Err(RpcError::Rpc {
+                code: err.code,
+                message: err.message,
+            });
+        }
+        res.result
+            .ok_or_else(|| RpcError::InvalidResponse(format!("{} returned no result", method)))
+    }
+
+    async fn request_hex(&self, method: &str, params: Value) -> Result<String, RpcError> {
+        match self.request(method, params).await? {
+            Value::String(s) => Ok(s),
+            other => Err(RpcError::InvalidResponse(other.to_string())),
+        }
+    }
+
+    pub async fn block_number(&self) -> Result<u64, RpcError> {
+        let res = self.request_hex("eth_blockNumber", json!([])).await?;
+        hex_to_u64(&res)
+    }
+
+    pub async fn block_timestamp(&self, block: u64) -> Result<i64, RpcError> {
+        let res = self
+            .request("eth_getBlockByNumber", json!([to_hex(block), false]))
+            .await?;
+        let timestamp = res
+            .get("timestamp")
+            .and_then(Value::as_str)
+            .ok_or_else(|| RpcError::InvalidResponse(format!("block {} not found", block)))?;
+        Ok(hex_to_u64(timestamp)? as i64)
+    }
+
+    /// Finds the last block mined at or before `timestamp`.
+    pub async fn block_at_time(&self, timestamp: i64) -> Result<u64, RpcError> {
+        let (mut low, mut high) = (0, self.block_number().await?);
+        if self.block_timestamp(high).await? <= timestamp {
+            return Ok(high);
+        }
+        while low < high {
+            let mid = (low + high).div_ceil(2);
+            if self.block_timestamp(mid).await? <= timestamp {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+        Ok(low)
+    }
+
+    pub async fn eth_call(&self, to: &str, data: &str, block: u64) -> Result<String, RpcError> {
+        self.request_hex(
+            "eth_call",
+            json!([{ "to": to, "data": data }, to_hex(block)]),
+        )
+        .await
+    }
+
+    pub async fn get_balance(&self, address: &str, block: u64) -> Result<String, RpcError> {
+        self.request_hex("eth_getBalance", json!([address, to_hex(block)]))
+            .await
+    }
+}
+
+fn to_hex(n: u64) -> String {
+    format!("0x{:x}", n)
+}
+
+pub fn hex_to_u64(s: &str) -> Result<u64, RpcError> {
+    u64::from_str_radix(s.trim_start_matches("0x"), 16)
+        .map_err(|_| RpcError::InvalidResponse(s.to_string()))
+}
+
+/// Splits the abi encoded return data into its 32 bytes words.
+pub fn abi_words(data: &str) -> Vec<&str> {
+    let data = data.trim_start_matches("0x");
+    (0..data.len() / 64)
+        .map(|i| &data[i * 64..(i + 1) * 64])
+        .collect()
+}
+
+/// Encodes a call of a function taking at most one address argument.
+pub fn encode_call(selector: &str, address: Option<&str>) -> String {
+    match address {
+        Some(address) => format!(
+            "{}{:0>64}",
+            selector,
+            address.trim_start_matches("0x").to_lowercase()
+        ),
+        None => selector.to_string(),
+    }
+}
+
+/// Decodes a hex encoded uint of at most 256 bits, the empty `0x` a call to an address
+/// without code answers is zero.
+pub fn hex_to_u256(s: &str) -> Result<U256, RpcError> {
+    let digits = s.trim_start_matches("0x");
+    if digits.is_empty() {
+        return Ok(U256::zero());
+    }
+    U256::from_str_radix(digits, 16).map_err(|_| RpcError::InvalidResponse(s.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_call() {
+        assert_eq!(
+            encode_call(
+                "0x70a08231",
+                Some("0xDDbd2b932c763bA5b1b7AE3B362eac3e8d40121A")
+            ),
+            "0x70a08231000000000000000000000000ddbd2b932c763ba5b1b7ae3b362eac3e8d40121a"
+        );
+        assert_eq!(encode_call("0x18160ddd", None), "0x18160ddd");
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(hex_to_u64("0xf4240").unwrap(), 1_000_000);
+        assert_eq!(hex_to_u256("0x0de0b6b3a7640000").unwrap(), U256::exp10(18));
+        assert_eq!(hex_to_u256("0x").unwrap(), U256::zero());
+        assert!(hex_to_u256(&format!("0x1{:0>64}", "")).is_err());
+        let data = format!("0x{:0>64}{:0>64}", "1", "ff");
+        let words = abi_words(&data);
+        assert_eq!(words.len(), 2);
+        assert_eq!(hex_to_u256(words[1]).unwrap(), U256::from(255));
+    }
+}
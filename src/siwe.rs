@@ -0,0 +1,311 @@
+use std::str::FromStr;
+
+use chrono::DateTime;
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+use thiserror::Error;
+
+use crate::merkle::{keccak256, to_hex};
+
+/// Seconds the clock of the wallet may be ahead of the server when the message is issued.
+const MAX_CLOCK_SKEW_SECS: i64 = 60;
+
+#[derive(Debug, Error)]
+pub enum SiweError {
+    #[error("Invalid siwe message: {0}")]
+    InvalidMessage(String),
+    #[error("Invalid signature")]
+    InvalidSignature,
+    #[error("Signed by {recovered} instead of {expected}")]
+    AddressMismatch { recovered: String, expected: String },
+    #[error("Message for domain {0} not accepted")]
+    DomainMismatch(String),
+    #[error("Unsupported message version: {0}")]
+    UnsupportedVersion(String),
+    #[error("Message expired or not yet valid")]
+    Expired,
+    #[error("Message issued in the future")]
+    IssuedInFuture,
+}
+
+const PREAMBLE: &str = " wants you to sign in with your Ethereum account:";
+
+/// Sign-In with Ethereum message, see EIP-4361.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SiweMessage {
+    pub domain: String,
+    /// as written in the message
+    pub address: String,
+    pub statement: Option<String>,
+    pub uri: String,
+    pub version: String,
+    pub chain_id: u64,
+    pub nonce: String,
+    /// unix seconds
+    pub issued_at: i64,
+    pub expiration_time: Option<i64>,
+    pub not_before: Option<i64>,
+    pub request_id: Option<String>,
+    pub resources: Vec<String>,
+}
+
+fn parse_time(s: &str) -> Result<i64, SiweError> {
+    DateTime::parse_from_rfc3339(s)
+        .map(|t| t.timestamp())
+        .map_err(|_| SiweError::InvalidMessage(format!("invalid time {}", s)))
+}
+
+impl FromStr for SiweMessage {
+    type Err = SiweError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| SiweError::InvalidMessage(reason.to_string());
+        let mut lines = s.lines().peekable();
+        let domain = lines
+            .next()
+            .and_then(|l| l.strip_suffix(PREAMBLE))
+            .filter(|d| !d.is_empty())
+            .ok_or_else(|| invalid("missing domain"))?
+            .to_string();
+        let address = lines
+            .next()
+            .filter(|a| {
+                a.len() == 42
+                    && a.starts_with("0x")
+                    && a[2..].chars().all(|c| c.is_ascii_hexdigit())
+            })
+            .ok_or_else(|| invalid("missing address"))?
+            .to_string();
+
+        // the statement is optional and surrounded by empty lines
+        let mut statement = None;
+        while lines.peek() == Some(&"") {
+            lines.next();
+        }
+        if let Some(line) = lines.peek() {
+            if !line.starts_with("URI: ") {
+                statement = Some(line.to_string());
+                lines.next();
+                while lines.peek() == Some(&"") {
+                    lines.next();
+                }
+            }
+        }
+
+        let mut field = |name: &str, required: bool| -> Result<Option<String>, SiweError> {
+            let prefix = format!("{}: ", name);
+            match lines.peek().and_then(|l| l.strip_prefix(prefix.as_str())) {
+                Some(value) => {
+                    let value = value.to_string();
+                    lines.next();
+                    Ok(Some(value))
+                }
+                None if required => Err(SiweError::InvalidMessage(format!("missing {}", name))),
+                None => Ok(None),
+            }
+        };
+        let uri = field("URI", true)?.unwrap_or_default();
+        let version = field("Version", true)?.unwrap_or_default();
+        let chain_id = field("Chain ID", true)?
+            .unwrap_or_default()
+            .parse()
+            .map_err(|_| invalid("invalid chain id"))?;
+        let nonce = field("Nonce", true)?.unwrap_or_default();
+        let issued_at = parse_time(&field("Issued At", true)?.unwrap_or_default())?;
+        let expiration_time = field("Expiration Time", false)?
+            .map(|t| parse_time(&t))
+            .transpose()?;
+        let not_before = field("Not Before", false)?
+            .map(|t| parse_time(&t))
+            .transpose()?;
+        let request_id = field("Request ID", false)?;
+
+        let mut resources = Vec::new();
+        if lines.peek() == Some(&"Resources:") {
+            lines.next();
+            while let Some(resource) = lines.peek().and_then(|l| l.strip_prefix("- ")) {
+                resources.push(resource.to_string());
+                lines.next();
+            }
+        }
+        if lines.next().is_some() {
+            return Err(invalid("unexpected trailing lines"));
+        }
+        if nonce.len() < 8 || !nonce.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(invalid("invalid nonce"));
+        }
+
+        Ok(Self {
+            domain,
+            address,
+            statement,
+            uri,
+            version,
+            chain_id,
+            nonce,
+            issued_at,
+            expiration_time,
+            not_before,
+            request_id,
+            resources,
+        })
+    }
+}
+
+impl SiweMessage {
+    /// Checks the message is meant for `domain` and valid at `now`, the nonce is left to the
+    /// caller.
+    pub fn validate(&self, domain: &str, now: i64) -> Result<(), SiweError> {
+        if self.version != "1" {
+            return Err(SiweError::UnsupportedVersion(self.version.clone()));
+        }
+        if self.domain != domain {
+            return Err(SiweError::DomainMismatch(self.domain.clone()));
+        }
+        if self.expiration_time.is_some_and(|t| now >= t)
+            || self.not_before.is_some_and(|t| now < t)
+        {
+            return Err(SiweError::Expired);
+        }
+        if self.issued_at > now + MAX_CLOCK_SKEW_SECS {
+            return Err(SiweError::IssuedInFuture);
+        }
+        Ok(())
+    }
+}
+
+/// Hash signed by `personal_sign`, see EIP-191.
+pub fn personal_message_hash(message: &str) -> [u8; 32] {
+    let mut data = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+    data.extend_from_slice(message.as_bytes());
+    keccak256(&data)
+}
+
+/// Lowercase address of the public key.
+pub fn public_key_address(key: &VerifyingKey) -> String {
+    let point = key.to_encoded_point(false);
+    // skip the 0x04 tag of the uncompressed point
+    let hash = keccak256(&point.as_bytes()[1..]);
+    to_hex(&hash[12..])
+}
+
+/// Recovers the lowercase address which signed the message with `personal_sign`, the signature
+/// is the 65 bytes `r || s || v` hex string returned by the wallets.
+pub fn recover_address(message: &str, signature: &str) -> Result<String, SiweError> {
+    let bytes =
+        hex::decode(signature.trim_start_matches("0x")).map_err(|_| SiweError::InvalidSignature)?;
+    if bytes.len() != 65 {
+        return Err(SiweError::InvalidSignature);
+    }
+    let v = match bytes[64] {
+        27 | 28 => bytes[64] - 27,
+        v => v,
+    };
+    let mut recovery_id = RecoveryId::from_byte(v).ok_or(SiweError::InvalidSignature)?;
+    let mut sig = Signature::from_slice(&bytes[..64]).map_err(|_| SiweError::InvalidSignature)?;
+    // only low s signatures are verified, negating s flips the parity of the point
+    if let Some(normalized) = sig.normalize_s() {
+        sig = normalized;
+        recovery_id = RecoveryId::new(!recovery_id.is_y_odd(), recovery_id.is_x_reduced());
+    }
+    let key =
+        VerifyingKey::recover_from_prehash(&personal_message_hash(message), &sig, recovery_id)
+            .map_err(|_| SiweError::InvalidSignature)?;
+    Ok(public_key_address(&key))
+}
+
+/// Parses the message and checks it was signed by the address it contains.
+pub fn verify(message: &str, signature: &str) -> Result<SiweMessage, SiweError> {
+    let parsed: SiweMessage = message.parse()?;
+    let recovered = recover_address(message, signature)?;
+    if !recovered.eq_ignore_ascii_case(&parsed.address) {
+        return Err(SiweError::AddressMismatch {
+            recovered,
+            expected: parsed.address,
+        });
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k256::ecdsa::SigningKey;
+
+    const MESSAGE: &str = "example.com wants you to sign in with your Ethereum account:
+0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf
+
+Sign in to zportfolio
+
+URI: https://example.com/login
+Version: 1
+Chain ID: 1
+Nonce: 32891756abcd
+Issued At: 2022-11-01T16:25:24Z
+Expiration Time: 2022-11-02T16:25:24Z
+Resources:
+- https://example.com/terms";
+
+    fn sign(key: &SigningKey, message: &str) -> String {
+        let (sig, id) = key
+            .sign_prehash_recoverable(&personal_message_hash(message))
+            .unwrap();
+        let mut bytes = sig.to_bytes().to_vec();
+        bytes.push(id.to_byte() + 27);
+        to_hex(&bytes)
+    }
+
+    #[test]
+    fn test_parse_message() {
+        let msg: SiweMessage = MESSAGE.parse().unwrap();
+        assert_eq!(msg.domain, "example.com");
+        assert_eq!(msg.statement.as_deref(), Some("Sign in to zportfolio"));
+        assert_eq!(msg.chain_id, 1);
+        assert_eq!(msg.nonce, "32891756abcd");
+        assert_eq!(msg.issued_at, 1667319924);
+        assert_eq!(msg.expiration_time, Some(1667406324));
+        assert_eq!(msg.resources, vec!["https://example.com/terms"]);
+
+        let without_statement = MESSAGE.replace("Sign in to zportfolio\n\n", "");
+        let msg: SiweMessage = without_statement.parse().unwrap();
+        assert_eq!(msg.statement, None);
+        assert!(MESSAGE
+            .replace("Nonce: ", "Nonce: !")
+            .parse::<SiweMessage>()
+            .is_err());
+
+        let msg: SiweMessage = MESSAGE.parse().unwrap();
+        assert!(msg.validate("example.com", 1667320000).is_ok());
+        assert!(msg.validate("evil.com", 1667320000).is_err());
+        assert!(msg.validate("example.com", 1667406324).is_err());
+        // the clock of the wallet may be a little ahead of the server
+        assert!(msg
+            .validate("example.com", 1667319924 - MAX_CLOCK_SKEW_SECS)
+            .is_ok());
+        assert!(matches!(
+            msg.validate("example.com", 1667319923 - MAX_CLOCK_SKEW_SECS),
+            Err(SiweError::IssuedInFuture)
+        ));
+    }
+
+    #[test]
+    fn test_verify() {
+        // the address of the private key 1 is well known
+        let mut secret = [0u8; 32];
+        secret[31] = 1;
+        let key = SigningKey::from_slice(&secret).unwrap();
+        assert_eq!(
+            public_key_address(key.verifying_key()),
+            "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf"
+        );
+
+        let signature = sign(&key, MESSAGE);
+        assert!(verify(MESSAGE, &signature).is_ok());
+
+        let other = SigningKey::from_slice(&[7u8; 32]).unwrap();
+        assert!(matches!(
+            verify(MESSAGE, &sign(&other, MESSAGE)),
+            Err(SiweError::AddressMismatch { .. })
+        ));
+        assert!(recover_address(MESSAGE, "0x1234").is_err());
+    }
+}
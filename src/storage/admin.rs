@@ -0,0 +1,62 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use super::{StorageError, StorageProcessor};
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AuditEntry {
+    pub id: i64,
+    /// name of the admin token used
+    pub actor: String,
+    pub action: String,
+    pub target: String,
+    /// json body of the request
+    pub payload: String,
+    pub created_at: i64,
+}
+
+impl StorageProcessor {
+    pub async fn save_audit_entry(
+        &self,
+        actor: &str,
+        action: &str,
+        target: &str,
+        payload: &str,
+    ) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            INSERT INTO admin_audit (actor, action, target, payload, created_at)
+            VALUES ( ?, ?, ?, ?, ? )
+            "#,
+        )
+        .bind(actor)
+        .bind(action)
+        .bind(target)
+        .bind(payload)
+        .bind(Utc::now().timestamp())
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Loads a page of the audit log, most recent first.
+    pub async fn load_audit_entries(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AuditEntry>, StorageError> {
+        let entries = sqlx::query_as::<_, AuditEntry>(
+            r#"
+            SELECT id, actor, action, target, payload, created_at FROM admin_audit
+            ORDER BY id DESC
+            LIMIT ? OFFSET ?
+            "#,
+        )
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.conn)
+        .await?;
+        Ok(entries)
+    }
+}
@@ -0,0 +1,210 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+use super::{StorageError, StorageProcessor};
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct AlertRule {
+    pub id: i64,
+    /// lowercase address of the owner
+    pub owner: String,
+    /// lowercase watched address
+    pub address: String,
+    /// `total_usd` or `token_amount`
+    pub kind: String,
+    pub chain: Option<String>,
+    pub token_id: Option<String>,
+    pub threshold: f64,
+    /// `absolute` or `percent`
+    pub threshold_mode: String,
+    pub webhook_url: String,
+    /// only returned when the rule is created
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub enabled: bool,
+    pub created_at: i64,
+    pub last_value: Option<f64>,
+    pub last_observed_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct AlertDelivery {
+    pub id: i64,
+    pub rule_id: i64,
+    pub attempt: i64,
+    pub status_code: Option<i64>,
+    pub error: Option<String>,
+    pub success: bool,
+    pub payload: String,
+    pub attempted_at: i64,
+}
+
+const ALERT_RULE_COLUMNS: &str = "id, owner, address, kind, chain, token_id, threshold, \
+    threshold_mode, webhook_url, secret, enabled, created_at, last_value, last_observed_at";
+
+impl StorageProcessor {
+    /// Stores a new rule and returns its id.
+    pub async fn insert_alert_rule(&self, rule: &AlertRule) -> Result<i64, StorageError> {
+        let id = sqlx::query(
+            r#"
+            INSERT INTO alert_rule (owner, address, kind, chain, token_id, threshold,
+                threshold_mode, webhook_url, secret, enabled, created_at)
+            VALUES ( ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ? )
+            "#,
+        )
+        .bind(&rule.owner)
+        .bind(&rule.address)
+        .bind(&rule.kind)
+        .bind(&rule.chain)
+        .bind(&rule.token_id)
+        .bind(rule.threshold)
+        .bind(&rule.threshold_mode)
+        .bind(&rule.webhook_url)
+        .bind(&rule.secret)
+        .bind(rule.enabled)
+        .bind(rule.created_at)
+        .execute(&self.conn)
+        .await?
+        .last_insert_id()
+        .unwrap_or_default();
+        Ok(id)
+    }
+
+    pub async fn load_alert_rules(&self, owner: &str) -> Result<Vec<AlertRule>, StorageError> {
+        let sql = format!(
+            "SELECT {} FROM alert_rule WHERE owner = ? ORDER BY id",
+            ALERT_RULE_COLUMNS
+        );
+        let rules = sqlx::query_as::<_, AlertRule>(&sql)
+            .bind(owner)
+            .fetch_all(&self.conn)
+            .await?;
+        Ok(rules)
+    }
+
+    pub async fn load_enabled_alert_rules(&self) -> Result<Vec<AlertRule>, StorageError> {
+        let sql = format!(
+            "SELECT {} FROM alert_rule WHERE enabled = TRUE ORDER BY id",
+            ALERT_RULE_COLUMNS
+        );
+        let rules = sqlx::query_as::<_, AlertRule>(&sql)
+            .fetch_all(&self.conn)
+            .await?;
+        Ok(rules)
+    }
+
+    pub async fn load_alert_rule(&self, id: i64) -> Result<Option<AlertRule>, StorageError> {
+        let sql = format!("SELECT {} FROM alert_rule WHERE id = ?", ALERT_RULE_COLUMNS);
+        let rule = sqlx::query_as::<_, AlertRule>(&sql)
+            .bind(id)
+            .fetch_optional(&self.conn)
+            .await?;
+        Ok(rule)
+    }
+
+    /// Saves the settings of the rule, the watched address and token can not change.
+    pub async fn update_alert_rule(&self, rule: &AlertRule) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            UPDATE alert_rule SET threshold = ?, threshold_mode = ?, webhook_url = ?, enabled = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(rule.threshold)
+        .bind(&rule.threshold_mode)
+        .bind(&rule.webhook_url)
+        .bind(rule.enabled)
+        .bind(rule.id)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Deletes the rule with its delivery log.
+    pub async fn delete_alert_rule(&self, id: i64) -> Result<(), StorageError> {
+        let mut tx = self.conn.begin().await?;
+        sqlx::query(
+            r#"
+            DELETE FROM alert_delivery
+            WHERE rule_id = ?
+            "#,
+        )
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+        sqlx::query(
+            r#"
+            DELETE FROM alert_rule
+            WHERE id = ?
+            "#,
+        )
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn save_alert_observation(
+        &self,
+        id: i64,
+        value: f64,
+        observed_at: i64,
+    ) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            UPDATE alert_rule SET last_value = ?, last_observed_at = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(value)
+        .bind(observed_at)
+        .bind(id)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn save_alert_delivery(&self, delivery: &AlertDelivery) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            INSERT INTO alert_delivery (rule_id, attempt, status_code, error, success, payload,
+                attempted_at)
+            VALUES ( ?, ?, ?, ?, ?, ?, ? )
+            "#,
+        )
+        .bind(delivery.rule_id)
+        .bind(delivery.attempt)
+        .bind(delivery.status_code)
+        .bind(&delivery.error)
+        .bind(delivery.success)
+        .bind(&delivery.payload)
+        .bind(delivery.attempted_at)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Loads the last delivery attempts of the rule, most recent first.
+    pub async fn load_alert_deliveries(
+        &self,
+        rule_id: i64,
+        limit: i64,
+    ) -> Result<Vec<AlertDelivery>, StorageError> {
+        let deliveries = sqlx::query_as::<_, AlertDelivery>(
+            r#"
+            SELECT id, rule_id, attempt, status_code, error, success, payload, attempted_at
+            FROM alert_delivery
+            WHERE rule_id = ?
+            ORDER BY id DESC
+            LIMIT ?
+            "#,
+        )
+        .bind(rule_id)
+        .bind(limit)
+        .fetch_all(&self.conn)
+        .await?;
+        Ok(deliveries)
+    }
+}
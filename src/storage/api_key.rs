@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+use sqlx::any::AnyKind;
+use sqlx::{FromRow, Row};
+
+use super::{StorageError, StorageProcessor};
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ApiClient {
+    pub id: i64,
+    pub name: String,
+    pub key_prefix: String,
+    /// requests per utc day, unlimited when none
+    pub daily_quota: Option<i64>,
+    /// requests per minute, unlimited when none
+    pub rate_limit: Option<i64>,
+    pub created_at: i64,
+    pub revoked_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ApiUsage {
+    pub day: i64,
+    pub endpoint: String,
+    pub requests: i64,
+}
+
+impl StorageProcessor {
+    /// Stores a new client and returns its id.
+    pub async fn insert_api_client(
+        &self,
+        client: &ApiClient,
+        key_hash: &str,
+    ) -> Result<i64, StorageError> {
+        let id = sqlx::query(
+            r#"
+            INSERT INTO api_client (name, key_hash, key_prefix, daily_quota, rate_limit,
+                created_at)
+            VALUES ( ?, ?, ?, ?, ?, ? )
+            "#,
+        )
+        .bind(&client.name)
+        .bind(key_hash)
+        .bind(&client.key_prefix)
+        .bind(client.daily_quota)
+        .bind(client.rate_limit)
+        .bind(client.created_at)
+        .execute(&self.conn)
+        .await?
+        .last_insert_id()
+        .unwrap_or_default();
+        Ok(id)
+    }
+
+    /// get the client owning the key, revoked clients are ignored.
+    pub async fn load_api_client_by_hash(
+        &self,
+        key_hash: &str,
+    ) -> Result<Option<ApiClient>, StorageError> {
+        let client = sqlx::query_as::<_, ApiClient>(
+            r#"
+            SELECT id, name, key_prefix, daily_quota, rate_limit, created_at, revoked_at
+            FROM api_client
+            WHERE key_hash = ? AND revoked_at IS NULL
+            "#,
+        )
+        .bind(key_hash)
+        .fetch_optional(&self.conn)
+        .await?;
+        Ok(client)
+    }
+
+    pub async fn load_api_clients(&self) -> Result<Vec<ApiClient>, StorageError> {
+        let clients = sqlx::query_as::<_, ApiClient>(
+            r#"
+            SELECT id, name, key_prefix, daily_quota, rate_limit, created_at, revoked_at
+            FROM api_client
+            ORDER BY id
+            "#,
+        )
+        .fetch_all(&self.conn)
+        .await?;
+        Ok(clients)
+    }
+
+    pub async fn load_api_client(&self, id: i64) -> Result<Option<ApiClient>, StorageError> {
+        let client = sqlx::query_as::<_, ApiClient>(
+            r#"
+            SELECT id, name, key_prefix, daily_quota, rate_limit, created_at, revoked_at
+            FROM api_client
+            WHERE id = ?
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.conn)
+        .await?;
+        Ok(client)
+    }
+
+    pub async fn revoke_api_client(&self, id: i64, revoked_at: i64) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            UPDATE api_client SET revoked_at = ?
+            WHERE id = ? AND revoked_at IS NULL
+            "#,
+        )
+        .bind(revoked_at)
+        .bind(id)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Counts one more request of the client on the endpoint.
+    pub async fn record_api_usage(
+        &self,
+        client_id: i64,
+        day: i64,
+        endpoint: &str,
+    ) -> Result<(), StorageError> {
+        // a single upsert, the first requests of the day may be counted at once
+        let upsert = match self.conn.any_kind() {
+            AnyKind::Sqlite => "ON CONFLICT (client_id, day, endpoint) DO UPDATE SET",
+            _ => "ON DUPLICATE KEY UPDATE",
+        };
+        let sql = format!(
+            r#"
+            INSERT INTO api_usage (client_id, day, endpoint, requests)
+            VALUES ( ?, ?, ?, 1 )
+            {} requests = requests + 1
+            "#,
+            upsert
+        );
+        sqlx::query(&sql)
+            .bind(client_id)
+            .bind(day)
+            .bind(endpoint)
+            .execute(&self.conn)
+            .await?;
+        Ok(())
+    }
+
+    /// get the number of requests of the client on the day over every endpoint.
+    pub async fn load_daily_api_usage(
+        &self,
+        client_id: i64,
+        day: i64,
+    ) -> Result<i64, StorageError> {
+        // summed here as `SUM` of a BIGINT is a DECIMAL on mysql
+        let rows = sqlx::query(
+            r#"
+            SELECT requests FROM api_usage
+            WHERE client_id = ? AND day = ?
+            "#,
+        )
+        .bind(client_id)
+        .bind(day)
+        .fetch_all(&self.conn)
+        .await?;
+        Ok(rows.iter().map(|row| row.get::<i64, _>("requests")).sum())
+    }
+
+    /// Loads the usage of the client per day and endpoint since `since`, most recent day first.
+    pub async fn load_api_usage(
+        &self,
+        client_id: i64,
+        since: i64,
+    ) -> Result<Vec<ApiUsage>, StorageError> {
+        let usage = sqlx::query_as::<_, ApiUsage>(
+            r#"
+            SELECT day, endpoint, requests FROM api_usage
+            WHERE client_id = ? AND day >= ?
+            ORDER BY day DESC, endpoint
+            "#,
+        )
+        .bind(client_id)
+        .bind(since)
+        .fetch_all(&self.conn)
+        .await?;
+        Ok(usage)
+    }
+}
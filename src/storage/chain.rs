@@ -1,15 +1,30 @@
 use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, Row};
+use utoipa::ToSchema;
 
-use super::{StorageError, StorageProcessor};
+use super::token::like_prefix;
+use super::{SqlBuilder, StorageError, StorageProcessor};
 
-#[derive(Deserialize, Serialize, Debug, FromRow)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, FromRow, ToSchema)]
 pub struct ChainInfo {
-    id: String,
-    community_id: i32,
-    name: String,
-    native_token_id: String,
-    logo_url: String,
+    pub id: String,
+    pub community_id: i32,
+    pub name: String,
+    pub native_token_id: String,
+    pub logo_url: String,
+}
+
+fn push_chain_filter(builder: &mut SqlBuilder, search: Option<&str>) {
+    builder.push(" WHERE disabled = FALSE");
+    if let Some(search) = search {
+        let pattern = like_prefix(search);
+        builder
+            .push(" AND (id LIKE ")
+            .push_bind(pattern.clone())
+            .push(" ESCAPE '!' OR name LIKE ")
+            .push_bind(pattern)
+            .push(" ESCAPE '!')");
+    }
 }
 
 impl StorageProcessor {
@@ -17,6 +32,7 @@ impl StorageProcessor {
         let chain_ids_row = sqlx::query(
             r#"
             SELECT id FROM chain
+            WHERE disabled = FALSE
             "#,
         )
         .fetch_all(&self.conn)
@@ -28,6 +44,7 @@ impl StorageProcessor {
         Ok(res)
     }
 
+    /// Loads every stored chain, the disabled ones included.
     pub async fn load_chains(&self) -> Result<Vec<ChainInfo>, StorageError> {
         let chain_infos = sqlx::query_as::<_, ChainInfo>(
             r#"
@@ -38,4 +55,124 @@ impl StorageProcessor {
         .await?;
         Ok(chain_infos)
     }
+
+    pub async fn load_chain(&self, id: &str) -> Result<Option<ChainInfo>, StorageError> {
+        let chain = sqlx::query_as::<_, ChainInfo>(
+            r#"
+            SELECT id, community_id, name, native_token_id, logo_url FROM chain
+            WHERE id = ? AND disabled = FALSE
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.conn)
+        .await?;
+        Ok(chain)
+    }
+
+    /// Loads the chains whose id or name starts with `search` ordered by id, all of them without
+    /// `limit`.
+    pub async fn load_chains_filtered(
+        &self,
+        search: Option<&str>,
+        limit: Option<i64>,
+        offset: i64,
+    ) -> Result<Vec<ChainInfo>, StorageError> {
+        let mut builder =
+            SqlBuilder::new("SELECT id, community_id, name, native_token_id, logo_url FROM chain");
+        push_chain_filter(&mut builder, search);
+        builder.push(" ORDER BY id");
+        if let Some(limit) = limit {
+            builder
+                .push(" LIMIT ")
+                .push_bind(limit)
+                .push(" OFFSET ")
+                .push_bind(offset);
+        }
+        let (sql, arguments) = builder.into_parts();
+        let rows = sqlx::query_with(&sql, arguments)
+            .fetch_all(&self.conn)
+            .await?;
+        let chains = rows
+            .iter()
+            .map(ChainInfo::from_row)
+            .collect::<Result<_, _>>()?;
+        Ok(chains)
+    }
+
+    pub async fn count_chains(&self, search: Option<&str>) -> Result<i64, StorageError> {
+        let mut builder = SqlBuilder::new("SELECT COUNT(*) AS total FROM chain");
+        push_chain_filter(&mut builder, search);
+        let (sql, arguments) = builder.into_parts();
+        let row = sqlx::query_with(&sql, arguments)
+            .fetch_one(&self.conn)
+            .await?;
+        Ok(row.try_get("total")?)
+    }
+
+    /// Checks if the chain is stored, disabled or not.
+    pub async fn chain_exists(&self, id: &str) -> Result<bool, StorageError> {
+        let row = sqlx::query(
+            r#"
+            SELECT id FROM chain
+            WHERE id = ?
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.conn)
+        .await?;
+        Ok(row.is_some())
+    }
+
+    pub async fn insert_chain(
+        &self,
+        chain: &ChainInfo,
+        disabled: bool,
+    ) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            INSERT INTO chain (id, community_id, name, native_token_id, logo_url, disabled)
+            VALUES ( ?, ?, ?, ?, ?, ? )
+            "#,
+        )
+        .bind(&chain.id)
+        .bind(chain.community_id)
+        .bind(&chain.name)
+        .bind(&chain.native_token_id)
+        .bind(&chain.logo_url)
+        .bind(disabled)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn update_chain(&self, chain: &ChainInfo) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            UPDATE chain SET community_id = ?, name = ?, native_token_id = ?, logo_url = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(chain.community_id)
+        .bind(&chain.name)
+        .bind(&chain.native_token_id)
+        .bind(&chain.logo_url)
+        .bind(&chain.id)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn set_chain_disabled(&self, id: &str, disabled: bool) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            UPDATE chain SET disabled = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(disabled)
+        .bind(id)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
 }
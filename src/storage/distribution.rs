@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+use super::{StorageError, StorageProcessor};
+use crate::merkle::MerkleDistribution;
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct DistributionInfo {
+    pub id: i64,
+    pub snapshot_id: i64,
+    pub decimals: i32,
+    pub merkle_root: String,
+    pub token_total: String,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DistributionClaim {
+    /// lowercase address
+    pub address: String,
+    pub idx: i64,
+    pub amount: String,
+    /// json array of the proof hashes
+    pub proof: String,
+}
+
+impl StorageProcessor {
+    /// Stores the distribution with all its claims and returns its id.
+    pub async fn save_distribution(
+        &self,
+        snapshot_id: i64,
+        decimals: i32,
+        created_at: i64,
+        distribution: &MerkleDistribution,
+    ) -> Result<i64, StorageError> {
+        let mut tx = self.conn.begin().await?;
+        let id = sqlx::query(
+            r#"
+            INSERT INTO distribution (snapshot_id, decimals, merkle_root, token_total, created_at)
+            VALUES ( ?, ?, ?, ?, ? )
+            "#,
+        )
+        .bind(snapshot_id)
+        .bind(decimals)
+        .bind(&distribution.merkle_root)
+        .bind(&distribution.token_total)
+        .bind(created_at)
+        .execute(&mut tx)
+        .await?
+        .last_insert_id()
+        .unwrap_or_default();
+        for (address, claim) in &distribution.claims {
+            sqlx::query(
+                r#"
+                INSERT INTO distribution_claim (distribution_id, address, idx, amount, proof)
+                VALUES ( ?, ?, ?, ?, ? )
+                "#,
+            )
+            .bind(id)
+            .bind(address.to_lowercase())
+            .bind(claim.index as i64)
+            .bind(&claim.amount)
+            .bind(serde_json::to_string(&claim.proof).expect("proof is always serializable"))
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    pub async fn load_distribution(
+        &self,
+        id: i64,
+    ) -> Result<Option<DistributionInfo>, StorageError> {
+        let info = sqlx::query_as::<_, DistributionInfo>(
+            r#"
+            SELECT id, snapshot_id, decimals, merkle_root, token_total, created_at FROM distribution
+            WHERE id = ?
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.conn)
+        .await?;
+        Ok(info)
+    }
+
+    /// get the claim of the address.
+    pub async fn load_distribution_claim(
+        &self,
+        id: i64,
+        address: &str,
+    ) -> Result<Option<DistributionClaim>, StorageError> {
+        let claim = sqlx::query_as::<_, DistributionClaim>(
+            r#"
+            SELECT address, idx, amount, proof FROM distribution_claim
+            WHERE distribution_id = ? AND address = ?
+            "#,
+        )
+        .bind(id)
+        .bind(address.to_lowercase())
+        .fetch_optional(&self.conn)
+        .await?;
+        Ok(claim)
+    }
+
+    pub async fn load_distribution_claims(
+        &self,
+        id: i64,
+    ) -> Result<Vec<DistributionClaim>, StorageError> {
+        let claims = sqlx::query_as::<_, DistributionClaim>(
+            r#"
+            SELECT address, idx, amount, proof FROM distribution_claim
+            WHERE distribution_id = ?
+            ORDER BY idx
+            "#,
+        )
+        .bind(id)
+        .fetch_all(&self.conn)
+        .await?;
+        Ok(claims)
+    }
+}
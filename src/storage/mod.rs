@@ -1,39 +1,97 @@
 // Built-in deps
 use std::env;
+use std::fmt::Display;
 
-use sqlx::mysql::MySqlPoolOptions;
-use sqlx::MySqlPool;
+use sqlx::any::{AnyArguments, AnyPool, AnyPoolOptions};
+use sqlx::{Any, Arguments, Encode, Type};
 
+pub mod admin;
+pub mod alert;
+pub mod api_key;
 pub mod chain;
 
+pub mod distribution;
+pub mod price;
+pub mod session;
+pub mod sync;
 pub mod token;
+pub mod transfer;
 pub mod user;
+pub mod vote;
+pub mod watchlist;
 
-use sqlx;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum StorageError {
     #[error(transparent)]
     Sqlx(#[from] sqlx::Error),
+    #[error(transparent)]
+    Migrate(#[from] sqlx::migrate::MigrateError),
+    #[error("Invalid identifier: {0}")]
+    InvalidIdentifier(String),
 }
 
 pub fn get_database_url() -> String {
     env::var("DATABASE_URL").expect("DATABASE_URL must be set")
 }
 
+/// Sql assembled from fragments and bound values, like the `QueryBuilder` of sqlx whose
+/// queries can't be run on the `Any` driver.
+pub(crate) struct SqlBuilder<'q> {
+    sql: String,
+    arguments: AnyArguments<'q>,
+}
+
+impl<'q> SqlBuilder<'q> {
+    pub fn new(init: impl Into<String>) -> Self {
+        Self {
+            sql: init.into(),
+            arguments: Default::default(),
+        }
+    }
+
+    pub fn push(&mut self, sql: impl Display) -> &mut Self {
+        self.sql.push_str(&sql.to_string());
+        self
+    }
+
+    pub fn push_bind<T>(&mut self, value: T) -> &mut Self
+    where
+        T: 'q + Send + Encode<'q, Any> + Type<Any>,
+    {
+        self.arguments.add(value);
+        self.sql.push('?');
+        self
+    }
+
+    /// The sql and its arguments, to run with `sqlx::query_with`.
+    pub fn into_parts(self) -> (String, AnyArguments<'q>) {
+        (self.sql, self.arguments)
+    }
+}
+
 #[derive(Clone)]
 pub struct StorageProcessor {
-    conn: MySqlPool,
+    conn: AnyPool,
 }
 
 impl StorageProcessor {
     pub async fn new_from_pool() -> Self {
-        let conn_pool = MySqlPoolOptions::new()
-            .connect(&get_database_url())
-            .await
-            .unwrap();
-        Self { conn: conn_pool }
+        Self::connect(&get_database_url()).await.unwrap()
+    }
+
+    /// Pool on `url`, the driver follows its scheme: `mysql://` in production, `sqlite:` for
+    /// the tests.
+    pub async fn connect(url: &str) -> Result<Self, StorageError> {
+        let conn = AnyPoolOptions::new().connect(url).await?;
+        Ok(Self { conn })
+    }
+
+    /// Applies the migrations of `migrations/` not applied yet, they are embedded at build time.
+    pub async fn migrate(&self) -> Result<(), StorageError> {
+        sqlx::migrate!().run(&self.conn).await?;
+        Ok(())
     }
 }
 
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Row};
+
+use super::{StorageError, StorageProcessor};
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TokenPrice {
+    pub chain: String,
+    pub token_id: String,
+    pub timestamp: i64,
+    pub price: f64,
+}
+
+impl StorageProcessor {
+    /// Stores the prices, an existing price at the same timestamp is overwritten.
+    pub async fn save_token_prices(&self, prices: &[TokenPrice]) -> Result<(), StorageError> {
+        let mut tx = self.conn.begin().await?;
+        for price in prices {
+            sqlx::query(
+                r#"
+                REPLACE INTO token_price (chain, token_id, timestamp, price)
+                VALUES ( ?, ?, ?, ? )
+                "#,
+            )
+            .bind(&price.chain)
+            .bind(&price.token_id)
+            .bind(price.timestamp)
+            .bind(price.price)
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Loads the prices of the token within `[start, end]` ordered by time.
+    pub async fn load_token_prices(
+        &self,
+        chain: &str,
+        token_id: &str,
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<TokenPrice>, StorageError> {
+        let prices = sqlx::query_as::<_, TokenPrice>(
+            r#"
+            SELECT chain, token_id, timestamp, price FROM token_price
+            WHERE chain = ? AND token_id = ? AND timestamp >= ? AND timestamp <= ?
+            ORDER BY timestamp
+            "#,
+        )
+        .bind(chain)
+        .bind(token_id)
+        .bind(start)
+        .bind(end)
+        .fetch_all(&self.conn)
+        .await?;
+        Ok(prices)
+    }
+
+    /// Records the days the provider has no price of the token for.
+    pub async fn save_missing_prices(
+        &self,
+        chain: &str,
+        token_id: &str,
+        days: &[i64],
+    ) -> Result<(), StorageError> {
+        let mut tx = self.conn.begin().await?;
+        for day in days {
+            sqlx::query(
+                r#"
+                REPLACE INTO missing_price (chain, token_id, timestamp)
+                VALUES ( ?, ?, ? )
+                "#,
+            )
+            .bind(chain)
+            .bind(token_id)
+            .bind(day)
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// get the newest day recorded without price for the token.
+    pub async fn load_latest_missing_price_timestamp(
+        &self,
+        chain: &str,
+        token_id: &str,
+    ) -> Result<Option<i64>, StorageError> {
+        let row = sqlx::query(
+            r#"
+            SELECT MAX(timestamp) AS latest FROM missing_price
+            WHERE chain = ? AND token_id = ?
+            "#,
+        )
+        .bind(chain)
+        .bind(token_id)
+        .fetch_one(&self.conn)
+        .await?;
+        Ok(row.try_get("latest")?)
+    }
+
+    /// get the timestamp of the newest stored price of the token.
+    pub async fn load_latest_price_timestamp(
+        &self,
+        chain: &str,
+        token_id: &str,
+    ) -> Result<Option<i64>, StorageError> {
+        let row = sqlx::query(
+            r#"
+            SELECT MAX(timestamp) AS latest FROM token_price
+            WHERE chain = ? AND token_id = ?
+            "#,
+        )
+        .bind(chain)
+        .bind(token_id)
+        .fetch_one(&self.conn)
+        .await?;
+        Ok(row.try_get("latest")?)
+    }
+}
@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use super::{StorageError, StorageProcessor};
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Session {
+    /// lowercase address of the signed in user
+    pub address: String,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+impl StorageProcessor {
+    pub async fn save_siwe_nonce(&self, nonce: &str, expires_at: i64) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            INSERT INTO siwe_nonce (nonce, expires_at)
+            VALUES ( ?, ? )
+            "#,
+        )
+        .bind(nonce)
+        .bind(expires_at)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Deletes the nonce and returns whether it was issued and not expired at `now`, so every
+    /// nonce is accepted once.
+    pub async fn take_siwe_nonce(&self, nonce: &str, now: i64) -> Result<bool, StorageError> {
+        let deleted = sqlx::query(
+            r#"
+            DELETE FROM siwe_nonce
+            WHERE nonce = ? AND expires_at > ?
+            "#,
+        )
+        .bind(nonce)
+        .bind(now)
+        .execute(&self.conn)
+        .await?
+        .rows_affected();
+        Ok(deleted > 0)
+    }
+
+    pub async fn save_session(
+        &self,
+        token_hash: &str,
+        session: &Session,
+    ) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            INSERT INTO session (token_hash, address, created_at, expires_at)
+            VALUES ( ?, ?, ?, ? )
+            "#,
+        )
+        .bind(token_hash)
+        .bind(&session.address)
+        .bind(session.created_at)
+        .bind(session.expires_at)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
+
+    /// get the session of the token if it has not expired at `now`.
+    pub async fn load_session(
+        &self,
+        token_hash: &str,
+        now: i64,
+    ) -> Result<Option<Session>, StorageError> {
+        let session = sqlx::query_as::<_, Session>(
+            r#"
+            SELECT address, created_at, expires_at FROM session
+            WHERE token_hash = ? AND expires_at > ?
+            "#,
+        )
+        .bind(token_hash)
+        .bind(now)
+        .fetch_optional(&self.conn)
+        .await?;
+        Ok(session)
+    }
+
+    pub async fn delete_session(&self, token_hash: &str) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            DELETE FROM session
+            WHERE token_hash = ?
+            "#,
+        )
+        .bind(token_hash)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Removes the expired sessions and nonces.
+    pub async fn delete_expired_sessions(&self, now: i64) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            DELETE FROM session
+            WHERE expires_at <= ?
+            "#,
+        )
+        .bind(now)
+        .execute(&self.conn)
+        .await?;
+        sqlx::query(
+            r#"
+            DELETE FROM siwe_nonce
+            WHERE expires_at <= ?
+            "#,
+        )
+        .bind(now)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,33 @@
+use sqlx::Row;
+
+use super::{StorageError, StorageProcessor};
+
+impl StorageProcessor {
+    /// get the time of the last successful run of the sync job.
+    pub async fn load_last_sync_time(&self, name: &str) -> Result<Option<i64>, StorageError> {
+        let row = sqlx::query(
+            r#"
+            SELECT last_synced_at FROM sync_state
+            WHERE name = ?
+            "#,
+        )
+        .bind(name)
+        .fetch_optional(&self.conn)
+        .await?;
+        Ok(row.map(|r| r.get("last_synced_at")))
+    }
+
+    pub async fn save_last_sync_time(&self, name: &str, time: i64) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            REPLACE INTO sync_state (name, last_synced_at)
+            VALUES ( ?, ? )
+            "#,
+        )
+        .bind(name)
+        .bind(time)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
+}
@@ -2,23 +2,69 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, Row};
+use utoipa::ToSchema;
 
-use super::{StorageError, StorageProcessor};
+use super::vote::chain_column;
+use super::{SqlBuilder, StorageError, StorageProcessor};
 
-#[derive(Debug, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct TokenInfo {
-    id: String,
-    chain: String,
-    name: String,
-    symbol: String,
-    decimals: i32,
-    logo_url: String,
-    protocol_id: String,
-    is_core: bool,
+    pub id: String,
+    pub chain: String,
+    pub name: String,
+    pub symbol: String,
+    pub decimals: i32,
+    pub logo_url: String,
+    pub protocol_id: String,
+    pub is_core: bool,
+}
+
+/// Conditions to select tokens, unset fields match every token.
+#[derive(Debug, Default, Clone)]
+pub struct TokenFilter {
+    pub chain: Option<String>,
+    pub is_core: Option<bool>,
+    pub protocol_id: Option<String>,
+    /// prefix of the symbol or the name
+    pub search: Option<String>,
+}
+
+/// Escapes the `LIKE` wildcards of the user input, `!` is used as escape character as it has
+/// no special meaning in string literals of any sql dialect.
+pub(crate) fn like_prefix(prefix: &str) -> String {
+    let escaped = prefix
+        .replace('!', "!!")
+        .replace('%', "!%")
+        .replace('_', "!_");
+    format!("{}%", escaped)
+}
+
+fn push_token_filter(builder: &mut SqlBuilder, filter: &TokenFilter) {
+    builder.push(" WHERE disabled = FALSE");
+    if let Some(chain) = &filter.chain {
+        builder.push(" AND chain = ").push_bind(chain.clone());
+    }
+    if let Some(is_core) = filter.is_core {
+        builder.push(" AND is_core = ").push_bind(is_core);
+    }
+    if let Some(protocol_id) = &filter.protocol_id {
+        builder
+            .push(" AND protocol_id = ")
+            .push_bind(protocol_id.clone());
+    }
+    if let Some(search) = &filter.search {
+        let pattern = like_prefix(search);
+        builder
+            .push(" AND (symbol LIKE ")
+            .push_bind(pattern.clone())
+            .push(" ESCAPE '!' OR name LIKE ")
+            .push_bind(pattern)
+            .push(" ESCAPE '!')");
+    }
 }
 
 impl StorageProcessor {
-    /// Loads all the stored tokens from the database.
+    /// Loads all the stored tokens from the database, the disabled ones included.
     pub async fn load_tokens(&self) -> Result<Vec<TokenInfo>, StorageError> {
         let tokens = sqlx::query_as::<_, TokenInfo>(
             r#"
@@ -30,30 +76,213 @@ impl StorageProcessor {
         Ok(tokens)
     }
 
-    /// get token ids on different chain by its name, only vote token have this method.
-    pub async fn load_token_ids_by_name(
+    /// Loads the tokens matching the filter ordered by chain and id, all of them without `limit`.
+    pub async fn load_tokens_filtered(
         &self,
-        id: String,
-    ) -> Result<HashMap<String, String>, StorageError> {
-        let res = sqlx::query(
+        filter: &TokenFilter,
+        limit: Option<i64>,
+        offset: i64,
+    ) -> Result<Vec<TokenInfo>, StorageError> {
+        let mut builder = SqlBuilder::new(
+            "SELECT id, chain, name, symbol, decimals, logo_url, protocol_id, is_core FROM token",
+        );
+        push_token_filter(&mut builder, filter);
+        builder.push(" ORDER BY chain, id");
+        if let Some(limit) = limit {
+            builder
+                .push(" LIMIT ")
+                .push_bind(limit)
+                .push(" OFFSET ")
+                .push_bind(offset);
+        }
+        let (sql, arguments) = builder.into_parts();
+        let rows = sqlx::query_with(&sql, arguments)
+            .fetch_all(&self.conn)
+            .await?;
+        let tokens = rows
+            .iter()
+            .map(TokenInfo::from_row)
+            .collect::<Result<_, _>>()?;
+        Ok(tokens)
+    }
+
+    pub async fn load_token(
+        &self,
+        chain: &str,
+        id: &str,
+    ) -> Result<Option<TokenInfo>, StorageError> {
+        let token = sqlx::query_as::<_, TokenInfo>(
             r#"
-            SELECT * FROM vote_token
-            WHERE id = ?
+            SELECT id, chain, name, symbol, decimals, logo_url, protocol_id, is_core FROM token
+            WHERE chain = ? AND id = ? AND disabled = FALSE
             "#,
         )
+        .bind(chain)
         .bind(id)
-        .fetch_one(&self.conn)
+        .fetch_optional(&self.conn)
         .await?;
+        Ok(token)
+    }
 
-        let chain_ids = self.load_support_chain_ids().await?;
-        let mut token_ids = HashMap::<String, String>::new();
+    pub async fn count_tokens(&self, filter: &TokenFilter) -> Result<i64, StorageError> {
+        let mut builder = SqlBuilder::new("SELECT COUNT(*) AS total FROM token");
+        push_token_filter(&mut builder, filter);
+        let (sql, arguments) = builder.into_parts();
+        let row = sqlx::query_with(&sql, arguments)
+            .fetch_one(&self.conn)
+            .await?;
+        Ok(row.try_get("total")?)
+    }
 
-        for chain_id in chain_ids {
-            let ids: Option<String> = res.try_get(chain_id.as_str()).unwrap_or(None);
-            if let Some(i) = ids {
-                token_ids.insert(chain_id, i);
+    /// get token ids on different chain by its name, only vote token have this method. `None`
+    /// when the vote token is unknown or disabled.
+    pub async fn load_token_ids_by_name(
+        &self,
+        id: String,
+    ) -> Result<Option<HashMap<String, String>>, StorageError> {
+        // the chain columns are selected by name, a cached `SELECT *` keeps the columns it was
+        // prepared with and misses those added since on another connection
+        let columns = self.load_vote_token_chains().await?;
+        let chains: Vec<String> = self
+            .load_support_chain_ids()
+            .await?
+            .into_iter()
+            .filter(|chain| columns.contains(chain))
+            .collect();
+        let mut builder = SqlBuilder::new("SELECT id");
+        for chain in &chains {
+            builder.push(", ").push(chain_column(chain)?);
+        }
+        builder
+            .push(" FROM vote_token WHERE disabled = FALSE AND id = ")
+            .push_bind(id);
+        let (sql, arguments) = builder.into_parts();
+        let Some(row) = sqlx::query_with(&sql, arguments)
+            .fetch_optional(&self.conn)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let mut token_ids = HashMap::<String, String>::new();
+        for chain in chains {
+            if let Some(token_id) = row.try_get::<Option<String>, _>(chain.as_str())? {
+                token_ids.insert(chain, token_id);
             }
         }
-        Ok(token_ids)
+        Ok(Some(token_ids))
+    }
+
+    /// Checks if the token is stored, disabled or not.
+    pub async fn token_exists(&self, chain: &str, id: &str) -> Result<bool, StorageError> {
+        let row = sqlx::query(
+            r#"
+            SELECT id FROM token
+            WHERE chain = ? AND id = ?
+            "#,
+        )
+        .bind(chain)
+        .bind(id)
+        .fetch_optional(&self.conn)
+        .await?;
+        Ok(row.is_some())
+    }
+
+    /// Stores a new token, `admin_created` keeps it from being deleted by the catalog sync.
+    pub async fn insert_token(
+        &self,
+        token: &TokenInfo,
+        admin_created: bool,
+    ) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            INSERT INTO token
+                (id, chain, name, symbol, decimals, logo_url, protocol_id, is_core, admin_created)
+            VALUES ( ?, ?, ?, ?, ?, ?, ?, ?, ? )
+            "#,
+        )
+        .bind(&token.id)
+        .bind(&token.chain)
+        .bind(&token.name)
+        .bind(&token.symbol)
+        .bind(token.decimals)
+        .bind(&token.logo_url)
+        .bind(&token.protocol_id)
+        .bind(token.is_core)
+        .bind(admin_created)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
+
+    /// get the chain and id of the tokens created through the admin api.
+    pub async fn load_admin_created_tokens(&self) -> Result<Vec<(String, String)>, StorageError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT chain, id FROM token
+            WHERE admin_created = TRUE
+            "#,
+        )
+        .fetch_all(&self.conn)
+        .await?;
+        Ok(rows
+            .iter()
+            .map(|row| (row.get("chain"), row.get("id")))
+            .collect())
+    }
+
+    pub async fn update_token(&self, token: &TokenInfo) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            UPDATE token SET name = ?, symbol = ?, decimals = ?, logo_url = ?, protocol_id = ?,
+                is_core = ?
+            WHERE chain = ? AND id = ?
+            "#,
+        )
+        .bind(&token.name)
+        .bind(&token.symbol)
+        .bind(token.decimals)
+        .bind(&token.logo_url)
+        .bind(&token.protocol_id)
+        .bind(token.is_core)
+        .bind(&token.chain)
+        .bind(&token.id)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_token(&self, chain: &str, id: &str) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            DELETE FROM token
+            WHERE chain = ? AND id = ?
+            "#,
+        )
+        .bind(chain)
+        .bind(id)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn set_token_disabled(
+        &self,
+        chain: &str,
+        id: &str,
+        disabled: bool,
+    ) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            UPDATE token SET disabled = ?
+            WHERE chain = ? AND id = ?
+            "#,
+        )
+        .bind(disabled)
+        .bind(chain)
+        .bind(id)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
     }
 }
@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Row};
+
+use super::{StorageError, StorageProcessor};
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct TokenTransfer {
+    pub chain: String,
+    pub token_id: String,
+    pub timestamp: i64,
+    /// positive for incoming and negative for outgoing
+    pub amount: f64,
+    pub price: f64,
+}
+
+/// Where the transfer sync of a user resumes on a chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromRow)]
+pub struct TransferSync {
+    pub next_block: i64,
+    /// unix seconds of the last sync
+    pub synced_at: i64,
+}
+
+/// A transfer as ingested.
+#[derive(Debug, Clone)]
+pub struct NewTokenTransfer {
+    pub chain: String,
+    pub token_id: String,
+    pub tx_hash: String,
+    pub block: i64,
+    /// position among the transfers of the token in the transaction
+    pub seq: i32,
+    pub timestamp: i64,
+    pub amount: f64,
+    pub price: f64,
+}
+
+impl StorageProcessor {
+    /// Loads the transfer history of the user ordered by time.
+    pub async fn load_token_transfers(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<TokenTransfer>, StorageError> {
+        let id = user_id.to_lowercase();
+        let transfers = sqlx::query_as::<_, TokenTransfer>(
+            r#"
+            SELECT chain, token_id, timestamp, amount, price FROM token_transfer
+            WHERE user_id = ?
+            ORDER BY timestamp, id
+            "#,
+        )
+        .bind(id)
+        .fetch_all(&self.conn)
+        .await?;
+        Ok(transfers)
+    }
+
+    /// Stores the transfers of the user, a transfer already stored is overwritten.
+    pub async fn save_token_transfers(
+        &self,
+        user_id: &str,
+        transfers: &[NewTokenTransfer],
+    ) -> Result<(), StorageError> {
+        let id = user_id.to_lowercase();
+        let mut tx = self.conn.begin().await?;
+        for transfer in transfers {
+            sqlx::query(
+                r#"
+                REPLACE INTO token_transfer
+                    (user_id, chain, token_id, tx_hash, block, seq, timestamp, amount, price)
+                VALUES ( ?, ?, ?, ?, ?, ?, ?, ?, ? )
+                "#,
+            )
+            .bind(&id)
+            .bind(&transfer.chain)
+            .bind(&transfer.token_id)
+            .bind(&transfer.tx_hash)
+            .bind(transfer.block)
+            .bind(transfer.seq)
+            .bind(transfer.timestamp)
+            .bind(transfer.amount)
+            .bind(transfer.price)
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// get the block of the newest stored transfer of the user on the chain.
+    pub async fn load_last_transfer_block(
+        &self,
+        user_id: &str,
+        chain: &str,
+    ) -> Result<Option<i64>, StorageError> {
+        let row = sqlx::query(
+            r#"
+            SELECT MAX(block) AS block FROM token_transfer
+            WHERE user_id = ? AND chain = ?
+            "#,
+        )
+        .bind(user_id.to_lowercase())
+        .bind(chain)
+        .fetch_one(&self.conn)
+        .await?;
+        Ok(row.try_get::<Option<i64>, _>("block")?)
+    }
+
+    /// get where the transfer sync of the user resumes on the chain.
+    pub async fn load_transfer_sync(
+        &self,
+        user_id: &str,
+        chain: &str,
+    ) -> Result<Option<TransferSync>, StorageError> {
+        let sync = sqlx::query_as::<_, TransferSync>(
+            r#"
+            SELECT next_block, synced_at FROM transfer_sync
+            WHERE user_id = ? AND chain = ?
+            "#,
+        )
+        .bind(user_id.to_lowercase())
+        .bind(chain)
+        .fetch_optional(&self.conn)
+        .await?;
+        Ok(sync)
+    }
+
+    pub async fn save_transfer_sync(
+        &self,
+        user_id: &str,
+        chain: &str,
+        sync: TransferSync,
+    ) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            REPLACE INTO transfer_sync (user_id, chain, next_block, synced_at)
+            VALUES ( ?, ?, ?, ? )
+            "#,
+        )
+        .bind(user_id.to_lowercase())
+        .bind(chain)
+        .bind(sync.next_block)
+        .bind(sync.synced_at)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
+}
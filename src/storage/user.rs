@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{FromRow, Row};
 
 use super::{StorageError, StorageProcessor};
 
@@ -10,7 +10,7 @@ pub struct UserInfo {
 }
 
 impl StorageProcessor {
-    pub async fn load_user_info(&self, user_id: &str) -> Result<UserInfo, StorageError> {
+    pub async fn load_user_info(&self, user_id: &str) -> Result<Option<UserInfo>, StorageError> {
         let id = user_id.to_lowercase(); // use address in normalized format
         let user_info = sqlx::query_as::<_, UserInfo>(
             r#"
@@ -19,27 +19,102 @@ impl StorageProcessor {
             "#,
         )
         .bind(id)
-        .fetch_one(&self.conn)
+        .fetch_optional(&self.conn)
         .await?;
         Ok(user_info)
     }
 
+    /// Stores the activation time of the address, replacing the one a concurrent request may
+    /// have stored, the address is no longer inactive.
     pub async fn set_user_info(
         &self,
         user_id: &str,
         activation_time: i64,
     ) -> Result<(), StorageError> {
         let id = user_id.to_lowercase();
+        let mut tx = self.conn.begin().await?;
         sqlx::query(
             r#"
-            INSERT INTO user (id, activation_time)
+            REPLACE INTO user (id, activation_time)
             VALUES ( ?, ? )
             "#,
         )
-        .bind(id)
+        .bind(&id)
         .bind(activation_time)
-        .execute(&self.conn)
+        .execute(&mut tx)
         .await?;
+        sqlx::query("DELETE FROM inactive_user WHERE id = ?")
+            .bind(id)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// get when the address was last found without transaction, `None` if it never was.
+    pub async fn load_inactive_user(&self, user_id: &str) -> Result<Option<i64>, StorageError> {
+        let row = sqlx::query("SELECT checked_at FROM inactive_user WHERE id = ?")
+            .bind(user_id.to_lowercase())
+            .fetch_optional(&self.conn)
+            .await?;
+        Ok(row.map(|r| r.get("checked_at")))
+    }
+
+    /// Records that the address had no transaction at `checked_at`.
+    pub async fn set_user_inactive(
+        &self,
+        user_id: &str,
+        checked_at: i64,
+    ) -> Result<(), StorageError> {
+        sqlx::query("REPLACE INTO inactive_user (id, checked_at) VALUES ( ?, ? )")
+            .bind(user_id.to_lowercase())
+            .bind(checked_at)
+            .execute(&self.conn)
+            .await?;
         Ok(())
     }
+
+    /// get the inactive addresses last checked before `before`, the oldest checks first.
+    pub async fn load_stale_inactive_users(
+        &self,
+        before: i64,
+        limit: i64,
+    ) -> Result<Vec<String>, StorageError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id FROM inactive_user
+            WHERE checked_at < ?
+            ORDER BY checked_at
+            LIMIT ?
+            "#,
+        )
+        .bind(before)
+        .bind(limit)
+        .fetch_all(&self.conn)
+        .await?;
+        Ok(rows.iter().map(|r| r.get("id")).collect())
+    }
+
+    /// Forgets the stored activation time of the address, or of every address when none is
+    /// given, so it is fetched again. Returns the number of forgotten addresses.
+    pub async fn delete_user_info(&self, user_id: Option<&str>) -> Result<u64, StorageError> {
+        let mut deleted = 0;
+        for table in ["user", "inactive_user"] {
+            let res = match user_id {
+                Some(id) => {
+                    sqlx::query(&format!("DELETE FROM {} WHERE id = ?", table))
+                        .bind(id.to_lowercase())
+                        .execute(&self.conn)
+                        .await?
+                }
+                None => {
+                    sqlx::query(&format!("DELETE FROM {}", table))
+                        .execute(&self.conn)
+                        .await?
+                }
+            };
+            deleted += res.rows_affected();
+        }
+        Ok(deleted)
+    }
 }
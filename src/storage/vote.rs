@@ -0,0 +1,409 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use sqlx::any::{AnyKind, AnyRow};
+use sqlx::{FromRow, Row};
+use utoipa::ToSchema;
+
+use crate::amount::Amount;
+
+use super::{SqlBuilder, StorageError, StorageProcessor};
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct VoteTokenWeight {
+    pub chain: String,
+    pub token_id: String,
+    /// `token` or `uniswap_v2_lp`
+    pub kind: String,
+    pub weight: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct VoteSnapshot {
+    pub id: i64,
+    pub vote_token: String,
+    pub computed_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct VoteSnapshotEntry {
+    pub rank: i64,
+    pub address: String,
+    /// exact amount as a decimal string
+    #[schema(value_type = String)]
+    pub amount: Amount,
+}
+
+impl<'r> FromRow<'r, AnyRow> for VoteSnapshotEntry {
+    fn from_row(row: &'r AnyRow) -> Result<Self, sqlx::Error> {
+        let amount: String = row.try_get("exact_amount")?;
+        Ok(Self {
+            rank: row.try_get("rank")?,
+            address: row.try_get("address")?,
+            amount: amount.parse().map_err(|e| sqlx::Error::ColumnDecode {
+                index: "exact_amount".into(),
+                source: Box::new(e),
+            })?,
+        })
+    }
+}
+
+/// Columns of `vote_token` that are not a chain id.
+const VOTE_TOKEN_META_COLUMNS: [&str; 2] = ["id", "disabled"];
+
+/// The chain ids are used as column names of `vote_token`, only plain identifiers are accepted
+/// so they can be quoted safely.
+pub(super) fn chain_column(chain: &str) -> Result<String, StorageError> {
+    let valid = !chain.is_empty()
+        && chain
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        && !VOTE_TOKEN_META_COLUMNS.contains(&chain);
+    if valid {
+        Ok(format!("`{}`", chain))
+    } else {
+        Err(StorageError::InvalidIdentifier(chain.to_string()))
+    }
+}
+
+impl StorageProcessor {
+    /// get the weighted tokens configured for the vote token besides the vote token itself.
+    pub async fn load_vote_token_weights(
+        &self,
+        vote_token: &str,
+    ) -> Result<Vec<VoteTokenWeight>, StorageError> {
+        let weights = sqlx::query_as::<_, VoteTokenWeight>(
+            r#"
+            SELECT chain, token_id, kind, weight FROM vote_token_weight
+            WHERE vote_token = ?
+            "#,
+        )
+        .bind(vote_token)
+        .fetch_all(&self.conn)
+        .await?;
+        Ok(weights)
+    }
+
+    pub async fn is_vote_excluded(
+        &self,
+        vote_token: &str,
+        address: &str,
+    ) -> Result<bool, StorageError> {
+        let res = sqlx::query(
+            r#"
+            SELECT address FROM vote_exclusion
+            WHERE vote_token = ? AND address = ?
+            "#,
+        )
+        .bind(vote_token)
+        .bind(address.to_lowercase())
+        .fetch_optional(&self.conn)
+        .await?;
+        Ok(res.is_some())
+    }
+
+    /// get the names of all the enabled vote tokens.
+    pub async fn load_vote_token_names(&self) -> Result<Vec<String>, StorageError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id FROM vote_token
+            WHERE disabled = FALSE
+            "#,
+        )
+        .fetch_all(&self.conn)
+        .await?;
+        Ok(rows.iter().map(|row| row.get("id")).collect())
+    }
+
+    /// get the chain and token id of every token referenced by a vote token, the disabled vote
+    /// tokens and chains included.
+    pub async fn load_vote_token_references(&self) -> Result<Vec<(String, String)>, StorageError> {
+        let chains = self.load_vote_token_chains().await?;
+        if chains.is_empty() {
+            return Ok(Vec::new());
+        }
+        // selected by name, like `load_vote_token_chains` a `SELECT *` would miss the newer
+        // columns
+        let mut builder = SqlBuilder::new("SELECT ");
+        for (i, chain) in chains.iter().enumerate() {
+            if i > 0 {
+                builder.push(", ");
+            }
+            builder.push(chain_column(chain)?);
+        }
+        builder.push(" FROM vote_token");
+        let (sql, arguments) = builder.into_parts();
+        let rows = sqlx::query_with(&sql, arguments)
+            .fetch_all(&self.conn)
+            .await?;
+        let mut references = Vec::new();
+        for row in rows {
+            for chain in &chains {
+                if let Some(token_id) = row.try_get::<Option<String>, _>(chain.as_str())? {
+                    references.push((chain.clone(), token_id));
+                }
+            }
+        }
+        Ok(references)
+    }
+
+    /// get the addresses tracked by the leaderboard of the vote token.
+    pub async fn load_vote_addresses(&self, vote_token: &str) -> Result<Vec<String>, StorageError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT address FROM vote_address
+            WHERE vote_token = ?
+            "#,
+        )
+        .bind(vote_token)
+        .fetch_all(&self.conn)
+        .await?;
+        Ok(rows.iter().map(|row| row.get("address")).collect())
+    }
+
+    /// Stores a ranked snapshot of the vote token and returns its id.
+    pub async fn save_vote_snapshot(
+        &self,
+        vote_token: &str,
+        computed_at: i64,
+        entries: &[VoteSnapshotEntry],
+    ) -> Result<i64, StorageError> {
+        let mut tx = self.conn.begin().await?;
+        let id = sqlx::query(
+            r#"
+            INSERT INTO vote_snapshot (vote_token, computed_at)
+            VALUES ( ?, ? )
+            "#,
+        )
+        .bind(vote_token)
+        .bind(computed_at)
+        .execute(&mut tx)
+        .await?
+        .last_insert_id()
+        .unwrap_or_default();
+        for entry in entries {
+            sqlx::query(
+                r#"
+                INSERT INTO vote_snapshot_entry
+                    (snapshot_id, address, amount, exact_amount, `rank`)
+                VALUES ( ?, ?, ?, ?, ? )
+                "#,
+            )
+            .bind(id)
+            .bind(&entry.address)
+            .bind(entry.amount.to_f64())
+            .bind(entry.amount.to_string())
+            .bind(entry.rank)
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    pub async fn load_vote_snapshot(&self, id: i64) -> Result<Option<VoteSnapshot>, StorageError> {
+        let snapshot = sqlx::query_as::<_, VoteSnapshot>(
+            r#"
+            SELECT id, vote_token, computed_at FROM vote_snapshot
+            WHERE id = ?
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.conn)
+        .await?;
+        Ok(snapshot)
+    }
+
+    pub async fn load_latest_vote_snapshot(
+        &self,
+        vote_token: &str,
+    ) -> Result<Option<VoteSnapshot>, StorageError> {
+        let snapshot = sqlx::query_as::<_, VoteSnapshot>(
+            r#"
+            SELECT id, vote_token, computed_at FROM vote_snapshot
+            WHERE vote_token = ?
+            ORDER BY computed_at DESC, id DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(vote_token)
+        .fetch_optional(&self.conn)
+        .await?;
+        Ok(snapshot)
+    }
+
+    /// Loads a page of the snapshot entries ordered by rank.
+    pub async fn load_vote_snapshot_entries(
+        &self,
+        snapshot_id: i64,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<VoteSnapshotEntry>, StorageError> {
+        let entries = sqlx::query_as::<_, VoteSnapshotEntry>(
+            r#"
+            SELECT `rank`, address, exact_amount FROM vote_snapshot_entry
+            WHERE snapshot_id = ?
+            ORDER BY `rank`, address
+            LIMIT ? OFFSET ?
+            "#,
+        )
+        .bind(snapshot_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.conn)
+        .await?;
+        Ok(entries)
+    }
+
+    pub async fn count_vote_snapshot_entries(&self, snapshot_id: i64) -> Result<i64, StorageError> {
+        let row = sqlx::query(
+            r#"
+            SELECT COUNT(*) AS total FROM vote_snapshot_entry
+            WHERE snapshot_id = ?
+            "#,
+        )
+        .bind(snapshot_id)
+        .fetch_one(&self.conn)
+        .await?;
+        Ok(row.try_get("total")?)
+    }
+
+    pub async fn vote_token_exists(&self, id: &str) -> Result<bool, StorageError> {
+        let row = sqlx::query(
+            r#"
+            SELECT id FROM vote_token
+            WHERE id = ?
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.conn)
+        .await?;
+        Ok(row.is_some())
+    }
+
+    /// get the chains having a column in `vote_token`.
+    ///
+    /// The columns are read from the catalog: the columns of a prepared `SELECT *` are the
+    /// ones the connection knew, missing those added since on another connection.
+    pub(super) async fn load_vote_token_chains(&self) -> Result<Vec<String>, StorageError> {
+        let sql = match self.conn.any_kind() {
+            AnyKind::Sqlite => "SELECT name FROM pragma_table_info('vote_token')",
+            _ => {
+                r#"
+                SELECT column_name AS name FROM information_schema.columns
+                WHERE table_schema = DATABASE() AND table_name = 'vote_token'
+                "#
+            }
+        };
+        let rows = sqlx::query(sql).fetch_all(&self.conn).await?;
+        let mut chains = Vec::new();
+        for row in rows {
+            let name: String = row.try_get("name")?;
+            if !VOTE_TOKEN_META_COLUMNS.contains(&name.as_str()) {
+                chains.push(name);
+            }
+        }
+        Ok(chains)
+    }
+
+    /// Adds the columns of the chains missing from `vote_token` and returns every chain column.
+    async fn ensure_vote_token_chains<'a>(
+        &self,
+        chains: impl Iterator<Item = &'a String>,
+    ) -> Result<Vec<String>, StorageError> {
+        let mut columns = self.load_vote_token_chains().await?;
+        for chain in chains {
+            if !columns.contains(chain) {
+                let sql = format!(
+                    "ALTER TABLE vote_token ADD COLUMN {} VARCHAR(128) NULL",
+                    chain_column(chain)?
+                );
+                sqlx::query(&sql).execute(&self.conn).await?;
+                columns.push(chain.clone());
+            }
+        }
+        Ok(columns)
+    }
+
+    /// Creates the vote token with its token id on each chain.
+    pub async fn insert_vote_token(
+        &self,
+        id: &str,
+        tokens: &BTreeMap<String, String>,
+    ) -> Result<(), StorageError> {
+        self.ensure_vote_token_chains(tokens.keys()).await?;
+        let mut builder = SqlBuilder::new("INSERT INTO vote_token (id");
+        for chain in tokens.keys() {
+            builder.push(", ").push(chain_column(chain)?);
+        }
+        builder.push(") VALUES (").push_bind(id);
+        for token_id in tokens.values() {
+            builder.push(", ").push_bind(token_id.clone());
+        }
+        builder.push(")");
+        let (sql, arguments) = builder.into_parts();
+        sqlx::query_with(&sql, arguments)
+            .execute(&self.conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Replaces the token ids of the vote token, the chains missing from `tokens` are unset.
+    pub async fn update_vote_token(
+        &self,
+        id: &str,
+        tokens: &BTreeMap<String, String>,
+    ) -> Result<(), StorageError> {
+        let columns = self.ensure_vote_token_chains(tokens.keys()).await?;
+        if columns.is_empty() {
+            return Ok(());
+        }
+        let mut builder = SqlBuilder::new("UPDATE vote_token SET ");
+        for (i, chain) in columns.iter().enumerate() {
+            if i > 0 {
+                builder.push(", ");
+            }
+            builder
+                .push(format!("{} = ", chain_column(chain)?))
+                .push_bind(tokens.get(chain).cloned());
+        }
+        builder.push(" WHERE id = ").push_bind(id);
+        let (sql, arguments) = builder.into_parts();
+        sqlx::query_with(&sql, arguments)
+            .execute(&self.conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_vote_token_disabled(
+        &self,
+        id: &str,
+        disabled: bool,
+    ) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            UPDATE vote_token SET disabled = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(disabled)
+        .bind(id)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain_column() {
+        assert_eq!(chain_column("eth").unwrap(), "`eth`");
+        assert_eq!(chain_column("base_2").unwrap(), "`base_2`");
+        assert!(chain_column("eth`; DROP TABLE token; --").is_err());
+        assert!(chain_column("disabled").is_err());
+        assert!(chain_column("").is_err());
+    }
+}
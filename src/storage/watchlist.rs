@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+use super::{StorageError, StorageProcessor};
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct Watchlist {
+    pub id: i64,
+    /// lowercase address of the owner
+    pub owner: String,
+    pub name: String,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct WatchlistEntry {
+    /// lowercase address
+    pub address: String,
+    pub label: Option<String>,
+    pub note: Option<String>,
+    pub created_at: i64,
+}
+
+impl StorageProcessor {
+    /// Stores a new watchlist and returns its id.
+    pub async fn insert_watchlist(
+        &self,
+        owner: &str,
+        name: &str,
+        created_at: i64,
+    ) -> Result<i64, StorageError> {
+        let id = sqlx::query(
+            r#"
+            INSERT INTO watchlist (owner, name, created_at)
+            VALUES ( ?, ?, ? )
+            "#,
+        )
+        .bind(owner)
+        .bind(name)
+        .bind(created_at)
+        .execute(&self.conn)
+        .await?
+        .last_insert_id()
+        .unwrap_or_default();
+        Ok(id)
+    }
+
+    pub async fn load_watchlists(&self, owner: &str) -> Result<Vec<Watchlist>, StorageError> {
+        let watchlists = sqlx::query_as::<_, Watchlist>(
+            r#"
+            SELECT id, owner, name, created_at FROM watchlist
+            WHERE owner = ?
+            ORDER BY id
+            "#,
+        )
+        .bind(owner)
+        .fetch_all(&self.conn)
+        .await?;
+        Ok(watchlists)
+    }
+
+    pub async fn load_watchlist(&self, id: i64) -> Result<Option<Watchlist>, StorageError> {
+        let watchlist = sqlx::query_as::<_, Watchlist>(
+            r#"
+            SELECT id, owner, name, created_at FROM watchlist
+            WHERE id = ?
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.conn)
+        .await?;
+        Ok(watchlist)
+    }
+
+    pub async fn rename_watchlist(&self, id: i64, name: &str) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            UPDATE watchlist SET name = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(name)
+        .bind(id)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Deletes the watchlist with its entries.
+    pub async fn delete_watchlist(&self, id: i64) -> Result<(), StorageError> {
+        let mut tx = self.conn.begin().await?;
+        sqlx::query(
+            r#"
+            DELETE FROM watchlist_entry
+            WHERE watchlist_id = ?
+            "#,
+        )
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+        sqlx::query(
+            r#"
+            DELETE FROM watchlist
+            WHERE id = ?
+            "#,
+        )
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn load_watchlist_entries(
+        &self,
+        watchlist_id: i64,
+    ) -> Result<Vec<WatchlistEntry>, StorageError> {
+        let entries = sqlx::query_as::<_, WatchlistEntry>(
+            r#"
+            SELECT address, label, note, created_at FROM watchlist_entry
+            WHERE watchlist_id = ?
+            ORDER BY created_at, address
+            "#,
+        )
+        .bind(watchlist_id)
+        .fetch_all(&self.conn)
+        .await?;
+        Ok(entries)
+    }
+
+    pub async fn insert_watchlist_entry(
+        &self,
+        watchlist_id: i64,
+        entry: &WatchlistEntry,
+    ) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            INSERT INTO watchlist_entry (watchlist_id, address, label, note, created_at)
+            VALUES ( ?, ?, ?, ?, ? )
+            "#,
+        )
+        .bind(watchlist_id)
+        .bind(&entry.address)
+        .bind(&entry.label)
+        .bind(&entry.note)
+        .bind(entry.created_at)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Replaces the label and the note of the entry.
+    pub async fn update_watchlist_entry(
+        &self,
+        watchlist_id: i64,
+        entry: &WatchlistEntry,
+    ) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            UPDATE watchlist_entry SET label = ?, note = ?
+            WHERE watchlist_id = ? AND address = ?
+            "#,
+        )
+        .bind(&entry.label)
+        .bind(&entry.note)
+        .bind(watchlist_id)
+        .bind(&entry.address)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_watchlist_entry(
+        &self,
+        watchlist_id: i64,
+        address: &str,
+    ) -> Result<(), StorageError> {
+        sqlx::query(
+            r#"
+            DELETE FROM watchlist_entry
+            WHERE watchlist_id = ? AND address = ?
+            "#,
+        )
+        .bind(watchlist_id)
+        .bind(address)
+        .execute(&self.conn)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,258 @@
+//! Ingestion of the token transfers the pnl is replayed from.
+//!
+//! Etherscan indexes ethereum only, the ether sent by the transactions and the contract calls
+//! and the erc20 transfers of an address are fetched from where its last sync stopped. The gas
+//! fees and the transfers of the other chains are not counted.
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use primitive_types::U256;
+use thiserror::Error;
+
+use crate::amount::Amount;
+use crate::etherscan::{EtherscanAPi, EtherscanApiError, PAGE_SIZE};
+use crate::price::{PriceProvider, SECONDS_PER_DAY};
+use crate::storage::transfer::{NewTokenTransfer, TransferSync};
+use crate::storage::{StorageError, StorageProcessor};
+
+/// The chain etherscan indexes.
+pub const CHAIN: &str = "eth";
+/// Token id of ether on debank.
+const NATIVE_TOKEN: &str = "eth";
+/// Least time between two syncs of an address, each one spends three etherscan calls.
+pub const SYNC_INTERVAL_SECS: i64 = 10 * 60;
+
+#[derive(Debug, Error)]
+pub enum TransferError {
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Etherscan(#[from] EtherscanApiError),
+}
+
+/// A transfer before it is priced, `amount` is signed.
+struct Movement {
+    block: i64,
+    timestamp: i64,
+    tx_hash: String,
+    token_id: String,
+    amount: f64,
+}
+
+/// Fetches the transfers of the address newer than the stored ones and stores them priced at
+/// their day. Returns the number of stored transfers, nothing is fetched again before
+/// `SYNC_INTERVAL_SECS` passed since the last sync of the address.
+pub async fn sync_transfers(
+    storage: &StorageProcessor,
+    acc_api: &EtherscanAPi,
+    prices: &dyn PriceProvider,
+    address: &str,
+) -> Result<usize, TransferError> {
+    let address = address.to_lowercase();
+    let now = Utc::now().timestamp();
+    let start_block = match storage.load_transfer_sync(&address, CHAIN).await? {
+        Some(sync) if now - sync.synced_at < SYNC_INTERVAL_SECS => return Ok(0),
+        Some(sync) => sync.next_block,
+        // synced before the position was kept
+        None => match storage.load_last_transfer_block(&address, CHAIN).await? {
+            Some(block) => block + 1,
+            None => 0,
+        },
+    };
+    let txs = acc_api.normal_transactions(&address, start_block).await?;
+    let internal_txs = acc_api.internal_transactions(&address, start_block).await?;
+    let events = acc_api.token_transfers(&address, start_block).await?;
+    let pages = [
+        page(txs.iter().map(|t| &t.block_number)),
+        page(internal_txs.iter().map(|t| &t.block_number)),
+        page(events.iter().map(|e| &e.block_number)),
+    ];
+    let complete_until = complete_until(start_block, &pages);
+    let next_block = match complete_until {
+        Some(block) => block + 1,
+        None => pages
+            .iter()
+            .filter_map(|(_, last)| *last)
+            .max()
+            .map_or(start_block, |block| block + 1),
+    };
+
+    let mut movements = Vec::new();
+    for tx in txs
+        .iter()
+        .chain(internal_txs.iter())
+        .filter(|tx| tx.is_error != "1")
+    {
+        if let Some(amount) = signed_amount(&address, &tx.from, &tx.to, &tx.value, 18) {
+            movements.push(Movement {
+                block: tx.block_number.parse().unwrap_or_default(),
+                timestamp: tx.time_stamp.parse().unwrap_or_default(),
+                tx_hash: tx.hash.clone(),
+                token_id: NATIVE_TOKEN.to_string(),
+                amount,
+            });
+        }
+    }
+    for event in events.iter() {
+        let decimals = event.token_decimal.parse().unwrap_or_default();
+        if let Some(amount) =
+            signed_amount(&address, &event.from, &event.to, &event.value, decimals)
+        {
+            movements.push(Movement {
+                block: event.block_number.parse().unwrap_or_default(),
+                timestamp: event.time_stamp.parse().unwrap_or_default(),
+                tx_hash: event.hash.clone(),
+                token_id: event.contract_address.to_lowercase(),
+                amount,
+            });
+        }
+    }
+    if let Some(block) = complete_until {
+        movements.retain(|m| m.block <= block);
+    }
+
+    let mut day_prices = HashMap::<(String, i64), f64>::new();
+    let mut seqs = HashMap::<(String, String), i32>::new();
+    let mut transfers = Vec::with_capacity(movements.len());
+    for movement in movements {
+        let day = movement.timestamp - movement.timestamp.rem_euclid(SECONDS_PER_DAY);
+        let key = (movement.token_id.clone(), day);
+        let price = match day_prices.get(&key) {
+            Some(price) => *price,
+            None => {
+                let price = day_price(storage, prices, &movement.token_id, day).await?;
+                day_prices.insert(key, price);
+                price
+            }
+        };
+        let seq = seqs
+            .entry((movement.tx_hash.clone(), movement.token_id.clone()))
+            .or_default();
+        transfers.push(NewTokenTransfer {
+            chain: CHAIN.to_string(),
+            token_id: movement.token_id,
+            tx_hash: movement.tx_hash,
+            block: movement.block,
+            seq: *seq,
+            timestamp: movement.timestamp,
+            amount: movement.amount,
+            price,
+        });
+        *seq += 1;
+    }
+    storage.save_token_transfers(&address, &transfers).await?;
+    storage
+        .save_transfer_sync(
+            &address,
+            CHAIN,
+            TransferSync {
+                next_block,
+                synced_at: now,
+            },
+        )
+        .await?;
+    Ok(transfers.len())
+}
+
+/// Number of results of a list call and the newest block among them.
+fn page<'a>(blocks: impl Iterator<Item = &'a String>) -> (usize, Option<i64>) {
+    let mut len = 0;
+    let mut last = None;
+    for block in blocks {
+        len += 1;
+        last = last.max(block.parse().ok());
+    }
+    (len, last)
+}
+
+/// Last block whose transfers were all fetched, `None` when every list was fetched to its end.
+/// A full page may end within a block, that block is fetched again by the next sync, but a full
+/// page within a single block can't be paged further and is taken as complete.
+fn complete_until(start_block: i64, pages: &[(usize, Option<i64>)]) -> Option<i64> {
+    pages
+        .iter()
+        .filter(|(len, _)| *len >= PAGE_SIZE)
+        .filter_map(|(_, last)| *last)
+        .map(|last| {
+            if last <= start_block {
+                tracing::warn!("a full page within block {}, the rest is skipped", last);
+                last
+            } else {
+                last - 1
+            }
+        })
+        .min()
+}
+
+/// Amount of the transfer seen from `address`, `None` when it doesn't move its balance.
+fn signed_amount(address: &str, from: &str, to: &str, value: &str, decimals: u32) -> Option<f64> {
+    let raw = U256::from_dec_str(value).ok()?;
+    if raw.is_zero() {
+        return None;
+    }
+    let amount = Amount::new(raw, decimals).to_f64();
+    match (
+        from.eq_ignore_ascii_case(address),
+        to.eq_ignore_ascii_case(address),
+    ) {
+        (true, false) => Some(-amount),
+        (false, true) => Some(amount),
+        _ => None,
+    }
+}
+
+/// Stored close of the day, asked to the provider when the backfill has none. A transfer
+/// without price is counted at zero cost like the pnl does for a missing history.
+async fn day_price(
+    storage: &StorageProcessor,
+    prices: &dyn PriceProvider,
+    token_id: &str,
+    day: i64,
+) -> Result<f64, StorageError> {
+    let stored = storage
+        .load_token_prices(CHAIN, token_id, day, day + SECONDS_PER_DAY - 1)
+        .await?;
+    if let Some(price) = stored.last() {
+        return Ok(price.price);
+    }
+    match prices.daily_price(CHAIN, token_id, day).await {
+        Ok(price) => Ok(price),
+        Err(e) => {
+            tracing::warn!("no price for {} on {}: {}", token_id, day, e);
+            Ok(0.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "0xddbd2b932c763ba5b1b7ae3b362eac3e8d40121a";
+    const OTHER: &str = "0x32be343b94f860124dc4fee278fdcbd38c102d88";
+
+    #[test]
+    fn test_signed_amount() {
+        let incoming = signed_amount(ADDRESS, OTHER, ADDRESS, "1500000000000000000", 18);
+        assert_eq!(incoming, Some(1.5));
+        // the address is matched whatever its case
+        let incoming = signed_amount(&ADDRESS.to_uppercase(), OTHER, ADDRESS, "2500000", 6);
+        assert_eq!(incoming, Some(2.5));
+        let outgoing = signed_amount(ADDRESS, ADDRESS, OTHER, "2500000", 6);
+        assert_eq!(outgoing, Some(-2.5));
+        assert_eq!(signed_amount(ADDRESS, ADDRESS, ADDRESS, "1", 0), None);
+        assert_eq!(signed_amount(ADDRESS, OTHER, ADDRESS, "0", 18), None);
+    }
+
+    #[test]
+    fn test_complete_until() {
+        // every list fetched to its end
+        assert_eq!(complete_until(10, &[(3, Some(20)), (0, None)]), None);
+        // the last block of a full page may be cut, it is fetched again
+        let pages = [(PAGE_SIZE, Some(30)), (5, Some(40))];
+        assert_eq!(complete_until(10, &pages), Some(29));
+        // a full page within the start block is taken as complete so the sync moves on
+        assert_eq!(complete_until(10, &[(PAGE_SIZE, Some(10))]), Some(10));
+    }
+}
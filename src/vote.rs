@@ -0,0 +1,419 @@
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use primitive_types::{U256, U512};
+
+use crate::amount::Amount;
+use crate::rpc::{abi_words, encode_call, hex_to_u256, JsonRpcClient, RpcError};
+use crate::storage::{vote::VoteTokenWeight, StorageError, StorageProcessor};
+
+const BALANCE_OF: &str = "0x70a08231";
+const DECIMALS: &str = "0x313ce567";
+const TOTAL_SUPPLY: &str = "0x18160ddd";
+const GET_RESERVES: &str = "0x0902f1ac";
+const TOKEN0: &str = "0x0dfe1681";
+
+/// Decimals of the native token on every supported evm chain.
+const NATIVE_DECIMALS: u32 = 18;
+
+#[derive(Debug, Error)]
+pub enum VoteError {
+    #[error(transparent)]
+    Rpc(#[from] RpcError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error("Invalid snapshot: {0}")]
+    InvalidSnapshot(String),
+    #[error("Unknown vote token kind: {0}")]
+    UnknownKind(String),
+    #[error("No underlying vote token on chain: {0}")]
+    MissingUnderlying(String),
+    #[error("Unknown vote token: {0}")]
+    UnknownToken(String),
+}
+
+/// The point in time the voting power is computed at.
+///
+/// A block number refers to the home chain, the other chains use their last block mined
+/// before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Snapshot {
+    Latest,
+    Block(u64),
+    Time(i64),
+}
+
+impl FromStr for Snapshot {
+    type Err = VoteError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || VoteError::InvalidSnapshot(s.to_string());
+        match s.split_once(':') {
+            _ if s.is_empty() || s == "latest" => Ok(Snapshot::Latest),
+            Some(("block", n)) => n.parse().map(Snapshot::Block).map_err(|_| invalid()),
+            Some(("time", t)) => t.parse().map(Snapshot::Time).map_err(|_| invalid()),
+            Some(_) => Err(invalid()),
+            None => s.parse().map(Snapshot::Block).map_err(|_| invalid()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum VoteTokenKind {
+    /// the balance of the token itself
+    Token,
+    /// a uniswap v2 style pair counted as its share of the vote token reserve
+    UniswapV2Lp,
+}
+
+impl FromStr for VoteTokenKind {
+    type Err = VoteError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "token" => Ok(VoteTokenKind::Token),
+            "uniswap_v2_lp" => Ok(VoteTokenKind::UniswapV2Lp),
+            other => Err(VoteError::UnknownKind(other.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct VotePowerItem {
+    pub chain: String,
+    pub token_id: String,
+    pub kind: VoteTokenKind,
+    pub weight: f64,
+    pub block: u64,
+    /// amount of the vote token held through this token
+    #[schema(value_type = String)]
+    pub amount: Amount,
+    /// `amount` times `weight`, exactly
+    #[schema(value_type = String)]
+    pub power: Amount,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct VotePower {
+    pub token_name: String,
+    pub address: String,
+    pub blocks: BTreeMap<String, u64>,
+    pub excluded: bool,
+    /// exact sum of the power of the items
+    #[schema(value_type = String)]
+    pub power: Amount,
+    pub items: Vec<VotePowerItem>,
+}
+
+pub struct VotePowerCalculator<'a> {
+    pub storage: &'a StorageProcessor,
+    pub rpcs: &'a HashMap<String, JsonRpcClient>,
+    pub home_chain: &'a str,
+}
+
+impl<'a> VotePowerCalculator<'a> {
+    fn rpc(&self, chain: &str) -> Result<&JsonRpcClient, VoteError> {
+        self.rpcs
+            .get(chain)
+            .ok_or_else(|| RpcError::UnsupportedChain(chain.to_string()).into())
+    }
+
+    /// Resolves the snapshot into a block number on each chain.
+    pub async fn resolve_blocks(
+        &self,
+        chains: &[String],
+        snapshot: Snapshot,
+    ) -> Result<BTreeMap<String, u64>, VoteError> {
+        let mut blocks = BTreeMap::new();
+        let timestamp = match snapshot {
+            Snapshot::Latest => None,
+            Snapshot::Time(t) => Some(t),
+            Snapshot::Block(n) => {
+                blocks.insert(self.home_chain.to_string(), n);
+                Some(self.rpc(self.home_chain)?.block_timestamp(n).await?)
+            }
+        };
+        for chain in chains {
+            if blocks.contains_key(chain) {
+                continue;
+            }
+            let rpc = self.rpc(chain)?;
+            let block = match timestamp {
+                Some(t) => rpc.block_at_time(t).await?,
+                None => rpc.block_number().await?,
+            };
+            blocks.insert(chain.clone(), block);
+        }
+        Ok(blocks)
+    }
+
+    /// Computes the voting power of the address for the vote token at the snapshot.
+    pub async fn voting_power(
+        &self,
+        token_name: &str,
+        address: &str,
+        snapshot: Snapshot,
+    ) -> Result<VotePower, VoteError> {
+        let underlying = self
+            .storage
+            .load_token_ids_by_name(token_name.to_string())
+            .await?
+            .ok_or_else(|| VoteError::UnknownToken(token_name.to_string()))?;
+        let weights = merge_weights(
+            &underlying,
+            self.storage.load_vote_token_weights(token_name).await?,
+        );
+        let mut chains: Vec<String> = weights.iter().map(|w| w.chain.clone()).collect();
+        chains.dedup();
+        let blocks = self.resolve_blocks(&chains, snapshot).await?;
+        let excluded = self.storage.is_vote_excluded(token_name, address).await?;
+
+        let mut items = Vec::new();
+        if !excluded {
+            for weight in weights {
+                let block = blocks[&weight.chain];
+                let kind: VoteTokenKind = weight.kind.parse()?;
+                let amount = match kind {
+                    VoteTokenKind::Token => {
+                        self.token_amount(&weight.chain, &weight.token_id, address, block)
+                            .await?
+                    }
+                    VoteTokenKind::UniswapV2Lp => {
+                        let vote_token = match underlying.get(&weight.chain) {
+                            Some(token_id) => token_id,
+                            None => return Err(VoteError::MissingUnderlying(weight.chain)),
+                        };
+                        self.lp_amount(&weight, vote_token, address, block).await?
+                    }
+                };
+                items.push(VotePowerItem {
+                    power: amount.weighted(weight.weight),
+                    chain: weight.chain,
+                    token_id: weight.token_id,
+                    kind,
+                    weight: weight.weight,
+                    block,
+                    amount,
+                });
+            }
+        }
+        Ok(VotePower {
+            token_name: token_name.to_string(),
+            address: address.to_lowercase(),
+            blocks,
+            excluded,
+            power: items.iter().map(|i| i.power).sum(),
+            items,
+        })
+    }
+
+    async fn decimals(&self, chain: &str, token_id: &str, block: u64) -> Result<u32, VoteError> {
+        if is_native(token_id) {
+            return Ok(NATIVE_DECIMALS);
+        }
+        let res = self
+            .rpc(chain)?
+            .eth_call(token_id, &encode_call(DECIMALS, None), block)
+            .await?;
+        // uint8 in the abi
+        match hex_to_u256(&res)? {
+            decimals if decimals <= U256::from(u8::MAX) => Ok(decimals.as_u32()),
+            _ => Err(RpcError::InvalidResponse(res).into()),
+        }
+    }
+
+    async fn raw_balance(
+        &self,
+        chain: &str,
+        token_id: &str,
+        address: &str,
+        block: u64,
+    ) -> Result<U256, VoteError> {
+        let rpc = self.rpc(chain)?;
+        let res = if is_native(token_id) {
+            rpc.get_balance(address, block).await?
+        } else {
+            rpc.eth_call(token_id, &encode_call(BALANCE_OF, Some(address)), block)
+                .await?
+        };
+        Ok(hex_to_u256(&res)?)
+    }
+
+    async fn token_amount(
+        &self,
+        chain: &str,
+        token_id: &str,
+        address: &str,
+        block: u64,
+    ) -> Result<Amount, VoteError> {
+        let raw = self.raw_balance(chain, token_id, address, block).await?;
+        let decimals = self.decimals(chain, token_id, block).await?;
+        Ok(Amount::new(raw, decimals))
+    }
+
+    /// The share of the pair reserve of `vote_token` owned by the address, rounded down to the
+    /// smallest unit of the vote token like the pair does when the liquidity is burnt.
+    async fn lp_amount(
+        &self,
+        pair: &VoteTokenWeight,
+        vote_token: &str,
+        address: &str,
+        block: u64,
+    ) -> Result<Amount, VoteError> {
+        let rpc = self.rpc(&pair.chain)?;
+        let lp_balance = self
+            .raw_balance(&pair.chain, &pair.token_id, address, block)
+            .await?;
+        if lp_balance.is_zero() {
+            return Ok(Amount::default());
+        }
+        let total_supply = hex_to_u256(
+            &rpc.eth_call(&pair.token_id, &encode_call(TOTAL_SUPPLY, None), block)
+                .await?,
+        )?;
+        let reserves = rpc
+            .eth_call(&pair.token_id, &encode_call(GET_RESERVES, None), block)
+            .await?;
+        let token0 = rpc
+            .eth_call(&pair.token_id, &encode_call(TOKEN0, None), block)
+            .await?;
+        let words = abi_words(&reserves);
+        if words.len() < 2 || total_supply.is_zero() {
+            return Err(RpcError::InvalidResponse(reserves).into());
+        }
+        let reserve = if same_address(&token0, vote_token) {
+            hex_to_u256(words[0])?
+        } else {
+            hex_to_u256(words[1])?
+        };
+        let decimals = self.decimals(&pair.chain, vote_token, block).await?;
+        Ok(Amount::new(
+            lp_share(lp_balance, total_supply, reserve),
+            decimals,
+        ))
+    }
+}
+
+/// Combines the vote token on each chain (weight 1) with the configured weighted tokens, a
+/// configured weight overrides the default one of the vote token.
+fn merge_weights(
+    underlying: &HashMap<String, String>,
+    configured: Vec<VoteTokenWeight>,
+) -> Vec<VoteTokenWeight> {
+    let mut merged = BTreeMap::new();
+    for (chain, token_id) in underlying {
+        merged.insert(
+            (chain.clone(), token_id.to_lowercase()),
+            VoteTokenWeight {
+                chain: chain.clone(),
+                token_id: token_id.clone(),
+                kind: "token".to_string(),
+                weight: 1.0,
+            },
+        );
+    }
+    for weight in configured {
+        merged.insert(
+            (weight.chain.clone(), weight.token_id.to_lowercase()),
+            weight,
+        );
+    }
+    merged.into_values().collect()
+}
+
+/// `balance / total_supply * reserve` rounded down, the product is computed on 512 bits.
+fn lp_share(balance: U256, total_supply: U256, reserve: U256) -> U256 {
+    let share = balance.full_mul(reserve) / U512::from(total_supply);
+    U256::try_from(share).unwrap_or(U256::MAX)
+}
+
+fn is_native(token_id: &str) -> bool {
+    !token_id.starts_with("0x")
+}
+
+/// Compares an abi encoded address word with a hex address.
+fn same_address(word: &str, address: &str) -> bool {
+    let word = word.trim_start_matches("0x").to_lowercase();
+    let address = address.trim_start_matches("0x").to_lowercase();
+    word.len() >= address.len() && word.ends_with(&address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_snapshot() {
+        assert_eq!("".parse::<Snapshot>().unwrap(), Snapshot::Latest);
+        assert_eq!("latest".parse::<Snapshot>().unwrap(), Snapshot::Latest);
+        assert_eq!(
+            "15000000".parse::<Snapshot>().unwrap(),
+            Snapshot::Block(15000000)
+        );
+        assert_eq!(
+            "block:15000000".parse::<Snapshot>().unwrap(),
+            Snapshot::Block(15000000)
+        );
+        assert_eq!(
+            "time:1660000000".parse::<Snapshot>().unwrap(),
+            Snapshot::Time(1660000000)
+        );
+        assert!("slot:1".parse::<Snapshot>().is_err());
+        assert!("time:abc".parse::<Snapshot>().is_err());
+    }
+
+    #[test]
+    fn test_merge_weights() {
+        let underlying = HashMap::from([
+            ("eth".to_string(), "0xAAA".to_string()),
+            ("bsc".to_string(), "0xbbb".to_string()),
+        ]);
+        let configured = vec![
+            VoteTokenWeight {
+                chain: "eth".to_string(),
+                token_id: "0xaaa".to_string(),
+                kind: "token".to_string(),
+                weight: 2.0,
+            },
+            VoteTokenWeight {
+                chain: "eth".to_string(),
+                token_id: "0xccc".to_string(),
+                kind: "uniswap_v2_lp".to_string(),
+                weight: 1.0,
+            },
+        ];
+        let merged = merge_weights(&underlying, configured);
+        assert_eq!(merged.len(), 3);
+        assert_eq!(merged[0].chain, "bsc");
+        assert_eq!(merged[1].weight, 2.0);
+        assert_eq!(merged[2].kind, "uniswap_v2_lp");
+    }
+
+    #[test]
+    fn test_lp_share() {
+        let e18 = U256::exp10(18);
+        // a float would round the reserve of 10^27 + 1 wei
+        let reserve = U256::exp10(27) + 1;
+        assert_eq!(lp_share(e18, e18 * 4, reserve), U256::exp10(27) / 4);
+        assert_eq!(lp_share(e18 * 4, e18 * 4, reserve), reserve);
+        assert_eq!(lp_share(U256::MAX, U256::one(), U256::MAX), U256::MAX);
+    }
+
+    #[test]
+    fn test_same_address() {
+        let word = format!("0x{:0>64}", "c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2");
+        assert!(same_address(
+            &word,
+            "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"
+        ));
+        assert!(!same_address(
+            &word,
+            "0x6b175474e89094c44da98b954eedeac495271d0f"
+        ));
+    }
+}